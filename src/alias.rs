@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config;
+
+/// The file a project's shared aliases are checked in as. It's found in the current directory or the
+/// nearest parent that has one.
+const PROJECT_FILE: &str = ".jq-aliases.toml";
+
+/// Saved queries by name, stored as a TOML table of `name = "query"`
+type Aliases = BTreeMap<String, String>;
+
+/// `~/.config/jq/aliases.toml`
+pub fn user_path() -> Option<PathBuf> {
+    config::dir().map(|dir| dir.join("aliases.toml"))
+}
+
+fn project_path() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors().map(|dir| dir.join(PROJECT_FILE)).find(|path| path.is_file())
+}
+
+fn load(path: &Path) -> Result<Aliases> {
+    if !path.exists() {
+        return Ok(Aliases::new());
+    }
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid aliases file {}", path.display()))
+}
+
+fn save(path: &Path, aliases: &Aliases) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, toml::to_string(aliases)?).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn target(file: Option<&str>) -> Result<PathBuf> {
+    match file {
+        Some(file) => Ok(PathBuf::from(file)),
+        None => user_path().ok_or_else(|| anyhow!("Can't find the config directory; set HOME or XDG_CONFIG_HOME")),
+    }
+}
+
+/// Save `query` as `name` in `file`, or the user's aliases file
+pub fn add(file: Option<&str>, name: &str, query: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!("Invalid alias name {}. Use letters, digits, - and _", name));
+    }
+    let path = target(file)?;
+    let mut aliases = load(&path)?;
+    aliases.insert(name.to_string(), query.to_string());
+    save(&path, &aliases)
+}
+
+/// Delete `name` from `file`, or the user's aliases file
+pub fn remove(file: Option<&str>, name: &str) -> Result<()> {
+    let path = target(file)?;
+    let mut aliases = load(&path)?;
+    if aliases.remove(name).is_none() {
+        return Err(anyhow!("No alias {} in {}", name, path.display()));
+    }
+    save(&path, &aliases)
+}
+
+/// Every alias: the user's, overridden by the project's where both have the same name
+pub fn all() -> Result<Aliases> {
+    let mut aliases = match user_path() {
+        Some(path) => load(&path)?,
+        None => Aliases::new(),
+    };
+    if let Some(path) = project_path() {
+        aliases.extend(load(&path)?);
+    }
+    Ok(aliases)
+}
+
+/// The query saved as `name`
+pub fn find(name: &str) -> Result<String> {
+    all()?.remove(name).ok_or_else(|| anyhow!("No alias named {}. See `jq alias list`", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(PROJECT_FILE);
+        let file = path.to_str();
+        add(file, "failing", "jobs[].steps[status=failed]").unwrap();
+        add(file, "names", "items[].name").unwrap();
+        let aliases = load(&path).unwrap();
+        assert_eq!(aliases.keys().collect::<Vec<_>>(), ["failing", "names"]);
+        assert_eq!(aliases["names"], "items[].name");
+        remove(file, "failing").unwrap();
+        assert!(remove(file, "failing").is_err());
+        assert!(add(file, "bad name", ".").is_err());
+        assert_eq!(load(&path).unwrap().len(), 1);
+    }
+}
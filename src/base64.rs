@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64. Padding is optional and whitespace is ignored.
+pub fn decode(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace()).take_while(|&c| c != b'=') {
+        let v = ALPHABET.iter().position(|&a| a == c).ok_or_else(|| anyhow!("Invalid base64: {}", s))?;
+        n = n << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(encode(b"a.b"), "YS5i");
+        assert_eq!(encode(b"a[0]"), "YVswXQ==");
+        assert_eq!(decode("YVswXQ==").unwrap(), b"a[0]");
+        assert_eq!(decode("YVswXQ").unwrap(), b"a[0]");
+        assert_eq!(decode(&encode(b"hunter2\n")).unwrap(), b"hunter2\n");
+        assert!(decode("a$b").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+use crate::base64;
+
+/// A binary serialization format, for `--binary` input and `--binary-output`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Msgpack,
+    Cbor,
+    Bson,
+}
+
+/// The key of the object a byte string becomes, holding its bytes in base64, as JSON has no byte strings
+const BYTES: &str = "$bytes";
+
+fn bytes(data: &[u8]) -> Value {
+    json!({BYTES: base64::encode(data)})
+}
+
+/// The bytes of an object made from a byte string, which has no other key
+fn as_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value.as_object()? {
+        obj if obj.len() == 1 => base64::decode(obj.get(BYTES)?.as_str()?).ok(),
+        _ => None,
+    }
+}
+
+/// A map key that isn't a string, as the JSON of its value
+fn key(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// The documents of `input`, one after another in `format`: a MessagePack or CBOR sequence, or concatenated
+/// BSON documents
+pub fn documents(mut input: Box<dyn Read>, format: Format) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut input = Cursor::new(data);
+    Ok(Box::new(std::iter::from_fn(move || {
+        if input.position() as usize >= input.get_ref().len() {
+            return None;
+        }
+        Some(match format {
+            Format::Msgpack => rmpv::decode::read_value(&mut input).map_err(|e| anyhow!("Invalid MessagePack: {}", e))
+                .and_then(from_msgpack),
+            Format::Cbor => ciborium::from_reader(&mut input).map_err(|e| anyhow!("Invalid CBOR: {}", e))
+                .and_then(from_cbor),
+            Format::Bson => bson::Document::from_reader(&mut input).map_err(|e| anyhow!("Invalid BSON: {}", e))
+                .map(|doc| from_bson(bson::Bson::Document(doc))),
+        })
+    })))
+}
+
+/// Write `value` to `out` as a document in `format`. Objects made from byte strings are written as byte
+/// strings again.
+pub fn write<W: Write>(out: &mut W, value: &Value, format: Format) -> Result<()> {
+    match format {
+        Format::Msgpack => rmpv::encode::write_value(out, &to_msgpack(value))?,
+        Format::Cbor => ciborium::into_writer(&to_cbor(value), out)?,
+        Format::Bson => match to_bson(value)? {
+            bson::Bson::Document(doc) => doc.to_writer(out)?,
+            _ => bail!("BSON output must be an object, not {}", value),
+        },
+    }
+    Ok(())
+}
+
+fn from_msgpack(value: rmpv::Value) -> Result<Value> {
+    use rmpv::Value as M;
+    Ok(match value {
+        M::Nil => Value::Null,
+        M::Boolean(b) => Value::Bool(b),
+        M::Integer(n) => match n.as_i64() {
+            Some(n) => Value::from(n),
+            None => Value::from(n.as_u64().unwrap()),
+        },
+        M::F32(n) => Value::from(n),
+        M::F64(n) => Value::from(n),
+        // A string that isn't UTF-8 is kept as its bytes, rather than mangled
+        M::String(s) if !s.is_str() => bytes(s.as_bytes()),
+        M::String(s) => Value::String(s.into_str().unwrap()),
+        M::Binary(data) => bytes(&data),
+        M::Array(arr) => Value::Array(arr.into_iter().map(from_msgpack).collect::<Result<_>>()?),
+        M::Map(entries) => Value::Object(entries.into_iter()
+            .map(|(k, v)| Ok((key(from_msgpack(k)?), from_msgpack(v)?)))
+            .collect::<Result<_>>()?),
+        M::Ext(kind, _) => bail!("MessagePack extension type {} isn't supported", kind),
+    })
+}
+
+fn to_msgpack(value: &Value) -> rmpv::Value {
+    use rmpv::Value as M;
+    if let Some(data) = as_bytes(value) {
+        return M::Binary(data);
+    }
+    match value {
+        Value::Null => M::Nil,
+        Value::Bool(b) => M::Boolean(*b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(n), _) => M::from(n),
+            (_, Some(n)) => M::from(n),
+            _ => M::F64(n.as_f64().unwrap()),
+        },
+        Value::String(s) => M::from(s.as_str()),
+        Value::Array(arr) => M::Array(arr.iter().map(to_msgpack).collect()),
+        Value::Object(obj) => M::Map(obj.iter().map(|(k, v)| (M::from(k.as_str()), to_msgpack(v))).collect()),
+    }
+}
+
+fn from_cbor(value: ciborium::Value) -> Result<Value> {
+    use ciborium::Value as C;
+    Ok(match value {
+        C::Null => Value::Null,
+        C::Bool(b) => Value::Bool(b),
+        C::Integer(n) => {
+            let n = i128::from(n);
+            match (i64::try_from(n), u64::try_from(n)) {
+                (Ok(n), _) => Value::from(n),
+                (_, Ok(n)) => Value::from(n),
+                _ => bail!("CBOR integer {} is out of range", n),
+            }
+        }
+        C::Float(n) => Value::from(n),
+        C::Text(s) => Value::String(s),
+        C::Bytes(data) => bytes(&data),
+        // A tag, such as a date, says how to read its value, which is kept as it is
+        C::Tag(_, value) => from_cbor(*value)?,
+        C::Array(arr) => Value::Array(arr.into_iter().map(from_cbor).collect::<Result<_>>()?),
+        C::Map(entries) => Value::Object(entries.into_iter()
+            .map(|(k, v)| Ok((key(from_cbor(k)?), from_cbor(v)?)))
+            .collect::<Result<_>>()?),
+        _ => bail!("Unsupported CBOR value: {:?}", value),
+    })
+}
+
+fn to_cbor(value: &Value) -> ciborium::Value {
+    use ciborium::Value as C;
+    if let Some(data) = as_bytes(value) {
+        return C::Bytes(data);
+    }
+    match value {
+        Value::Null => C::Null,
+        Value::Bool(b) => C::Bool(*b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(n), _) => C::Integer(n.into()),
+            (_, Some(n)) => C::Integer(n.into()),
+            _ => C::Float(n.as_f64().unwrap()),
+        },
+        Value::String(s) => C::Text(s.clone()),
+        Value::Array(arr) => C::Array(arr.iter().map(to_cbor).collect()),
+        Value::Object(obj) => C::Map(obj.iter().map(|(k, v)| (C::Text(k.clone()), to_cbor(v))).collect()),
+    }
+}
+
+/// BSON types JSON has no type for, other than generic binary, become relaxed extended JSON, like
+/// `{"$oid": "..."}`, and are written back as what they were
+fn from_bson(value: bson::Bson) -> Value {
+    use bson::Bson as B;
+    match value {
+        B::Binary(bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: data }) => bytes(&data),
+        B::Array(arr) => Value::Array(arr.into_iter().map(from_bson).collect()),
+        B::Document(doc) => Value::Object(doc.into_iter().map(|(k, v)| (k, from_bson(v))).collect::<Map<_, _>>()),
+        other => other.into_relaxed_extjson(),
+    }
+}
+
+fn to_bson(value: &Value) -> Result<bson::Bson> {
+    use bson::Bson as B;
+    if let Some(data) = as_bytes(value) {
+        return Ok(B::Binary(bson::Binary { subtype: bson::spec::BinarySubtype::Generic, bytes: data }));
+    }
+    Ok(match value {
+        Value::Array(arr) => B::Array(arr.iter().map(to_bson).collect::<Result<_>>()?),
+        Value::Object(obj) if !obj.keys().any(|k| k.starts_with('$')) => B::Document(obj.iter()
+            .map(|(k, v)| Ok((k.clone(), to_bson(v)?)))
+            .collect::<Result<_>>()?),
+        other => B::try_from(other.clone())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: &Value, format: Format) -> Value {
+        let mut out = Vec::new();
+        write(&mut out, value, format).unwrap();
+        write(&mut out, value, format).unwrap();
+        let docs: Vec<Value> = documents(Box::new(Cursor::new(out)), format).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(docs.len(), 2);
+        docs.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_binary() {
+        let value = json!({"name": "a", "n": -3, "big": 18446744073709551615u64, "x": 1.5, "ok": true, "none": null,
+            "data": {"$bytes": "AAH/"}, "list": [{"$bytes": ""}, "s"]});
+        assert_eq!(round_trip(&value, Format::Msgpack), value);
+        assert_eq!(round_trip(&value, Format::Cbor), value);
+        let value = json!({"name": "a", "n": -3, "x": 1.5, "ok": true, "none": null, "data": {"$bytes": "AAH/"},
+            "list": [{"$bytes": ""}, "s"], "id": {"$oid": "507f1f77bcf86cd799439011"}});
+        assert_eq!(round_trip(&value, Format::Bson), value);
+
+        // Byte strings are written as such, not as objects
+        let mut out = Vec::new();
+        write(&mut out, &json!({"$bytes": "AAH/"}), Format::Cbor).unwrap();
+        assert_eq!(out, [0x43, 0x00, 0x01, 0xff]);
+        let mut out = Vec::new();
+        write(&mut out, &json!({"$bytes": "AAH/"}), Format::Msgpack).unwrap();
+        assert_eq!(out, [0xc4, 0x03, 0x00, 0x01, 0xff]);
+
+        // An object that only looks like one is left alone
+        let value = json!({"$bytes": "AAH/", "n": 1});
+        assert_eq!(round_trip(&value, Format::Cbor), value);
+
+        let docs: Vec<Value> = documents(Box::new(Cursor::new(vec![0xa1, 0x01, 0x42, 0x68, 0x69])), Format::Cbor).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![json!({"1": {"$bytes": "aGk="}})]);
+        assert!(write(&mut Vec::new(), &json!([1]), Format::Bson).is_err());
+        assert!(documents(Box::new(Cursor::new(vec![0x92, 0x01])), Format::Msgpack).unwrap().next().unwrap().is_err());
+    }
+}
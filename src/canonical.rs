@@ -0,0 +1,107 @@
+use serde_json::Value;
+
+/// Format a number as ECMAScript's `Number.prototype.toString` does, which JCS requires
+fn canonical_number(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+    // `{:e}` gives the shortest digits that round-trip, like `1.2345e3`
+    let exp = format!("{:e}", f.abs());
+    let (mantissa, e) = exp.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = e.parse::<i32>().unwrap() + 1;
+    let mut out = String::new();
+    if f < 0.0 {
+        out.push('-');
+    }
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-n as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        out.push_str(&format!("e{}{}", if n > 0 { '+' } else { '-' }, (n - 1).abs()));
+    }
+    out
+}
+
+fn write_canonical(out: &mut String, obj: &Value) {
+    match obj {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(k.clone()).to_string());
+                out.push(':');
+                write_canonical(out, v);
+            }
+            out.push('}');
+        }
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(out, v);
+            }
+            out.push(']');
+        }
+        Value::Number(n) => out.push_str(&canonical_number(n.as_f64().unwrap_or(f64::NAN))),
+        z => out.push_str(&z.to_string()),
+    }
+}
+
+/// Serialize `obj` in the RFC 8785 JSON Canonicalization Scheme: no whitespace, keys sorted by UTF-16
+/// code units, and numbers formatted as IEEE doubles the way ECMAScript prints them.
+pub fn canonical(obj: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(&mut out, obj);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::excessive_precision)]
+    fn test_canonical_number() {
+        let cases = [
+            (333333333.33333329, "333333333.3333333"),
+            (1e30, "1e+30"),
+            (4.50, "4.5"),
+            (2e-3, "0.002"),
+            (1e-27, "1e-27"),
+            (-0.0, "0"),
+            (1e21, "1e+21"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (-1.5e-7, "-1.5e-7"),
+        ];
+        for (f, expected) in cases {
+            assert_eq!(canonical_number(f), expected);
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        let value: Value = serde_json::from_str(r#"{"b": [1.0, "\u00e9\n"], "a": {"\u20ac": 1, "\r": 2}, "c": null}"#).unwrap();
+        assert_eq!(canonical(&value), "{\"a\":{\"\\r\":2,\"€\":1},\"b\":[1,\"é\\n\"],\"c\":null}");
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+
+/// The commands that read or write the system clipboard on this platform, in order of preference. They're run
+/// rather than talking to the clipboard directly because on X11 and Wayland the clipboard is owned by a
+/// process and empties when it exits, which these tools handle by staying in the background.
+fn commands(copy: bool) -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        return vec![if copy { ("pbcopy", &[][..]) } else { ("pbpaste", &[][..]) }];
+    }
+    if cfg!(windows) {
+        return vec![if copy {
+            ("clip", &[][..])
+        } else {
+            ("powershell", &["-NoProfile", "-Command", "Get-Clipboard"][..])
+        }];
+    }
+    let mut commands = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        commands.push(if copy { ("wl-copy", &[][..]) } else { ("wl-paste", &["--no-newline"][..]) });
+    }
+    if copy {
+        commands.push(("xclip", &["-selection", "clipboard"][..]));
+        commands.push(("xsel", &["--clipboard", "--input"][..]));
+    } else {
+        commands.push(("xclip", &["-selection", "clipboard", "-o"][..]));
+        commands.push(("xsel", &["--clipboard", "--output"][..]));
+    }
+    commands
+}
+
+fn names(copy: bool) -> String {
+    commands(copy).iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+}
+
+/// The text on the system clipboard
+pub fn paste() -> Result<String> {
+    for (name, args) in commands(false) {
+        match Command::new(name).args(args).stderr(Stdio::inherit()).output() {
+            Ok(output) if output.status.success() => {
+                return String::from_utf8(output.stdout).context("The clipboard doesn't contain text");
+            }
+            Ok(output) => return Err(anyhow!("{} failed with {}", name, output.status)),
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", name)),
+        }
+    }
+    Err(anyhow!("Reading the clipboard needs one of: {}", names(false)))
+}
+
+/// Put `text` on the system clipboard
+pub fn copy(text: &[u8]) -> Result<()> {
+    for (name, args) in commands(true) {
+        let mut child = match Command::new(name).args(args).stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to run {}", name)),
+        };
+        child.stdin.take().expect("stdin is piped").write_all(text)?;
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!("{} failed with {}", name, status));
+        }
+        return Ok(());
+    }
+    Err(anyhow!("Writing the clipboard needs one of: {}", names(true)))
+}
@@ -0,0 +1,106 @@
+use clap_complete::Shell;
+use serde_json::Value;
+
+use jq::evaluate_command;
+
+use crate::select;
+
+/// Completions for a partially typed path: every key (or index) of the value at the path before the last
+/// `.` or `[` that starts with the text after it. Candidates are whole paths, ready to replace the word.
+pub fn keys(doc: &Value, partial: &str) -> Vec<String> {
+    let (prefix, sep, fragment) = match partial.rfind(['.', '[']) {
+        Some(i) => (&partial[..i], &partial[i..i + 1], &partial[i + 1..]),
+        None => ("", ".", partial),
+    };
+    let Ok((commands, _)) = evaluate_command(prefix) else {
+        return Vec::new();
+    };
+    let Ok(value) = select(doc, prefix, &commands) else {
+        return Vec::new();
+    };
+    match value.as_ref() {
+        Value::Object(map) if sep == "." => map.keys()
+            .filter(|k| k.starts_with(fragment))
+            .map(|k| if prefix.is_empty() && !partial.starts_with('.') { k.clone() } else { format!("{}.{}", prefix, k) })
+            .collect(),
+        Value::Array(arr) => (0..arr.len())
+            .map(|i| i.to_string())
+            .filter(|i| sep == "." && fragment.is_empty() || i.starts_with(fragment))
+            .map(|i| format!("{}[{}]", prefix, i))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Shell code that completes paths starting with `.` from the keys of the file named on the command line,
+/// and defers to the generated completions otherwise. It's appended to the output of `completions`.
+pub fn snippet(shell: Shell, bin: &str) -> Option<String> {
+    let snippet = match shell {
+        Shell::Bash => r#"
+_BIN_with_keys() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" file word
+    if [[ "$cur" == .* ]]; then
+        for word in "${COMP_WORDS[@]:1}"; do
+            [[ "$word" != -* && -f "$word" ]] && file="$word"
+        done
+        if [[ -n "$file" ]]; then
+            COMPREPLY=( $(BIN __complete-keys "$file" "$cur" 2>/dev/null) )
+            compopt -o nospace 2>/dev/null
+            return
+        fi
+    fi
+    _BIN "$@"
+}
+complete -F _BIN_with_keys -o bashdefault -o default BIN
+"#,
+        Shell::Zsh => r#"
+_BIN_with_keys() {
+    local file word
+    if [[ "$PREFIX" == .* ]]; then
+        for word in "${words[@]:1}"; do
+            [[ "$word" != -* && -f "$word" ]] && file="$word"
+        done
+        if [[ -n "$file" ]]; then
+            local -a keys
+            keys=(${(f)"$(BIN __complete-keys "$file" "$PREFIX" 2>/dev/null)"})
+            compadd -S '' -Q -- $keys
+            return
+        fi
+    fi
+    _BIN "$@"
+}
+compdef _BIN_with_keys BIN
+"#,
+        Shell::Fish => r#"
+function __BIN_complete_keys
+    set -l file
+    for word in (commandline -opc)[2..-1]
+        if test -f "$word"
+            set file $word
+        end
+    end
+    test -n "$file"; and BIN __complete-keys $file (commandline -ct) 2>/dev/null
+end
+complete -c BIN -n 'string match -q -- ".*" (commandline -ct)' -f -a '(__BIN_complete_keys)'
+"#,
+        _ => return None,
+    };
+    Some(snippet.replace("BIN", bin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_keys() {
+        let doc = json!({"spec": {"replicas": 1, "template": {}, "containers": [{"name": "a"}]}, "status": {}});
+        assert_eq!(keys(&doc, ".s"), vec![".spec", ".status"]);
+        assert_eq!(keys(&doc, "s"), vec!["spec", "status"]);
+        assert_eq!(keys(&doc, ".spec.re"), vec![".spec.replicas"]);
+        assert_eq!(keys(&doc, ".spec.containers."), vec![".spec.containers[0]"]);
+        assert_eq!(keys(&doc, ".spec.containers[0].n"), vec![".spec.containers[0].name"]);
+        assert!(keys(&doc, ".missing.").is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use colored_json::{Color, Style, Styler};
+use serde::Deserialize;
+
+use crate::Cli;
+
+/// When output is colored, for `--color` and the `color` config key
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and the NO_COLOR environment variable isn't set, the default
+    Auto,
+    /// Even when stdout isn't a terminal
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Pretty,
+    Json,
+    Compact,
+    Yaml,
+    Toml,
+    Xml,
+}
+
+/// Styles for each kind of JSON token, each written like `bold blue` or `#ff8800`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    key: Option<String>,
+    string: Option<String>,
+    number: Option<String>,
+    bool: Option<String>,
+    null: Option<String>,
+    brackets: Option<String>,
+}
+
+/// Defaults for command line flags, read from `~/.config/jq/config.toml`. Flags given on the command line
+/// take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    indent: Option<u8>,
+    tab: Option<bool>,
+    color: Option<ColorChoice>,
+    output: Option<OutputFormat>,
+    sort_keys: Option<bool>,
+    ascii_output: Option<bool>,
+    theme: Option<Theme>,
+}
+
+/// `~/.config/jq`, or under `$XDG_CONFIG_HOME` when it's set
+pub fn dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("jq"))
+}
+
+fn default_path() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("config.toml"))
+}
+
+fn parse_style(s: &str) -> Result<Style> {
+    let mut style = Style::new();
+    for word in s.split_whitespace() {
+        style = match word.to_ascii_lowercase().as_str() {
+            "bold" => style.bold(),
+            "dim" => style.dim(),
+            "italic" => style.italic(),
+            "underline" => style.underline(),
+            "black" => style.fg(Color::Black),
+            "red" => style.fg(Color::Red),
+            "green" => style.fg(Color::Green),
+            "yellow" => style.fg(Color::Yellow),
+            "blue" => style.fg(Color::Blue),
+            "magenta" => style.fg(Color::Magenta),
+            "cyan" => style.fg(Color::Cyan),
+            "white" => style.fg(Color::White),
+            "bright-black" | "gray" | "grey" => style.fg(Color::BrightBlack),
+            "bright-red" => style.fg(Color::BrightRed),
+            "bright-green" => style.fg(Color::BrightGreen),
+            "bright-yellow" => style.fg(Color::BrightYellow),
+            "bright-blue" => style.fg(Color::BrightBlue),
+            "bright-magenta" => style.fg(Color::BrightMagenta),
+            "bright-cyan" => style.fg(Color::BrightCyan),
+            "bright-white" => style.fg(Color::BrightWhite),
+            hex if hex.len() == 7 && hex.starts_with('#') => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+                match (channel(1), channel(3), channel(5)) {
+                    (Ok(r), Ok(g), Ok(b)) => style.fg(Color::Rgb(r, g, b)),
+                    _ => return Err(anyhow!("Invalid color {} in theme", word)),
+                }
+            }
+            _ => return Err(anyhow!("Unknown style {} in theme. Use color names, #rrggbb, bold, dim, italic or underline", word)),
+        };
+    }
+    Ok(style)
+}
+
+impl Theme {
+    pub fn styler(&self) -> Result<Styler> {
+        let mut styler = Styler::default();
+        let set = |target: &mut Style, style: &Option<String>| -> Result<()> {
+            if let Some(s) = style {
+                *target = parse_style(s)?;
+            }
+            Ok(())
+        };
+        set(&mut styler.key, &self.key)?;
+        set(&mut styler.string_value, &self.string)?;
+        set(&mut styler.integer_value, &self.number)?;
+        set(&mut styler.float_value, &self.number)?;
+        set(&mut styler.bool_value, &self.bool)?;
+        set(&mut styler.nil_value, &self.null)?;
+        set(&mut styler.object_brackets, &self.brackets)?;
+        set(&mut styler.array_brackets, &self.brackets)?;
+        Ok(styler)
+    }
+}
+
+impl Config {
+    /// Load the config at `path`, or at the default location if it exists
+    pub fn load(path: Option<&str>) -> Result<Config> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Set each flag the command line didn't give to its configured default
+    pub fn apply(&self, cli: &mut Cli, matches: &ArgMatches) -> Result<()> {
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        if let (Some(indent), true) = (self.indent, unset("indent")) {
+            if indent > 7 {
+                return Err(anyhow!("indent in config must be between 0 and 7, got {}", indent));
+            }
+            cli.indent = indent;
+        }
+        if let (Some(tab), true) = (self.tab, unset("tab")) {
+            cli.tab = tab;
+        }
+        if let (Some(color), true) = (self.color, unset("monochrome") && unset("color_output") && unset("color")) {
+            cli.monochrome = color == ColorChoice::Never;
+            cli.color_output = color == ColorChoice::Always;
+        }
+        let outputs = ["json_output", "compact", "jsonl", "yaml_output", "toml_output", "xml_output", "raw", "seq_output", "canonical"];
+        if let (Some(output), true) = (self.output, outputs.iter().all(|id| unset(id))) {
+            match output {
+                OutputFormat::Pretty => {}
+                OutputFormat::Json => cli.json_output = true,
+                OutputFormat::Compact => cli.compact = true,
+                OutputFormat::Yaml => cli.yaml_output = true,
+                OutputFormat::Toml => cli.toml_output = true,
+                OutputFormat::Xml => cli.xml_output = true,
+            }
+        }
+        if let (Some(sort_keys), true) = (self.sort_keys, unset("sort_keys")) {
+            cli.sort_keys = sort_keys;
+        }
+        if let (Some(ascii), true) = (self.ascii_output, unset("ascii_output")) {
+            cli.ascii_output = ascii;
+        }
+        Ok(())
+    }
+
+    pub fn styler(&self) -> Result<Styler> {
+        self.theme.as_ref().map_or(Ok(Styler::default()), Theme::styler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    #[test]
+    fn test_apply() {
+        let config: Config = toml::from_str("indent = 4\noutput = \"yaml\"\ncolor = \"never\"\nsort-keys = true").unwrap();
+        let matches = Cli::command().get_matches_from(["jq", "--indent", "3", "-C"]);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        config.apply(&mut cli, &matches).unwrap();
+        assert_eq!(cli.indent, 3);
+        assert!(cli.yaml_output);
+        assert!(cli.color_output && !cli.monochrome);
+        assert!(cli.sort_keys);
+    }
+
+    #[test]
+    fn test_theme() {
+        let theme = Theme { key: Some("bold #ff8800".into()), ..Theme::default() };
+        let styler = theme.styler().unwrap();
+        assert_eq!(styler.key, Style::new().bold().fg(Color::Rgb(255, 136, 0)));
+        assert!(parse_style("purple").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use serde_json::{json, Value};
+
+use crate::pointer;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// One difference between two documents, at a path like `a.b[0]`
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+fn key_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+/// Numbers compare by value, so `1` and `1.0` are the same
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn walk(changes: &mut Vec<Change>, path: String, a: &Value, b: &Value) {
+    match (a, b) {
+        (Value::Object(x), Value::Object(y)) => {
+            for (k, v) in x {
+                match y.get(k) {
+                    Some(w) => walk(changes, key_path(&path, k), v, w),
+                    None => changes.push(Change::Removed(key_path(&path, k), v.clone())),
+                }
+            }
+            for (k, w) in y.iter().filter(|(k, _)| !x.contains_key(*k)) {
+                changes.push(Change::Added(key_path(&path, k), w.clone()));
+            }
+        }
+        (Value::Array(x), Value::Array(y)) => {
+            for (i, v) in x.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                match y.get(i) {
+                    Some(w) => walk(changes, path, v, w),
+                    None => changes.push(Change::Removed(path, v.clone())),
+                }
+            }
+            for (i, w) in y.iter().enumerate().skip(x.len()) {
+                changes.push(Change::Added(format!("{}[{}]", path, i), w.clone()));
+            }
+        }
+        (a, b) if same(a, b) => {}
+        (a, b) => changes.push(Change::Changed(path, a.clone(), b.clone())),
+    }
+}
+
+/// The paths that differ between `a` and `b`, ignoring key order and formatting
+pub fn diff(a: &Value, b: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk(&mut changes, String::new(), a, b);
+    changes
+}
+
+fn walk_patch(ops: &mut Vec<Value>, path: String, a: &Value, b: &Value) {
+    match (a, b) {
+        (Value::Object(x), Value::Object(y)) => {
+            for (k, v) in x {
+                let path = format!("{}/{}", path, pointer::escape(k));
+                match y.get(k) {
+                    Some(w) => walk_patch(ops, path, v, w),
+                    None => ops.push(json!({"op": "remove", "path": path})),
+                }
+            }
+            for (k, w) in y.iter().filter(|(k, _)| !x.contains_key(*k)) {
+                ops.push(json!({"op": "add", "path": format!("{}/{}", path, pointer::escape(k)), "value": w}));
+            }
+        }
+        (Value::Array(x), Value::Array(y)) => {
+            for (i, (v, w)) in x.iter().zip(y).enumerate() {
+                walk_patch(ops, format!("{}/{}", path, i), v, w);
+            }
+            // Remove from the end so earlier removals don't shift the later indices
+            for i in (y.len()..x.len()).rev() {
+                ops.push(json!({"op": "remove", "path": format!("{}/{}", path, i)}));
+            }
+            for w in y.iter().skip(x.len()) {
+                ops.push(json!({"op": "add", "path": format!("{}/-", path), "value": w}));
+            }
+        }
+        (a, b) if same(a, b) => {}
+        (_, b) => ops.push(json!({"op": "replace", "path": path, "value": b})),
+    }
+}
+
+/// An RFC 6902 JSON Patch that transforms `a` into `b`
+pub fn patch(a: &Value, b: &Value) -> Value {
+    let mut ops = Vec::new();
+    walk_patch(&mut ops, String::new(), a, b);
+    Value::Array(ops)
+}
+
+/// Render a change as a line like `+ a.b: 1`, `- a.c: 2`, or `~ a.d: 1 → 2`
+pub fn render(change: &Change, color: bool) -> String {
+    let (style, line) = match change {
+        Change::Added(path, v) => (GREEN, format!("+ {}: {}", display_path(path), v)),
+        Change::Removed(path, v) => (RED, format!("- {}: {}", display_path(path), v)),
+        Change::Changed(path, a, b) => (YELLOW, format!("~ {}: {} → {}", display_path(path), a, b)),
+    };
+    if color {
+        format!("{}{}{}", style, line, RESET)
+    } else {
+        line
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "." } else { path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let a = json!({"name": "web", "port": 80, "tags": ["a", "b"], "limits": {"cpu": 1}});
+        let b = json!({"limits": {"cpu": 1.0}, "port": 8080, "tags": ["a"], "name": "web", "debug": true});
+        let lines: Vec<String> = diff(&a, &b).iter().map(|c| render(c, false)).collect();
+        assert_eq!(lines, vec!["~ port: 80 → 8080", "- tags[1]: \"b\"", "+ debug: true"]);
+        assert!(diff(&json!(1), &json!(1)).is_empty());
+        assert_eq!(render(&diff(&json!(1), &json!(2))[0], false), "~ .: 1 → 2");
+    }
+
+    #[test]
+    fn test_patch() {
+        let a = json!({"a/b": 1, "tags": ["x", "y", "z"], "gone": true});
+        let b = json!({"a/b": 2, "tags": ["x"], "new": {"k": null}});
+        let ops = patch(&a, &b);
+        assert_eq!(ops, json!([
+            {"op": "replace", "path": "/a~1b", "value": 2},
+            {"op": "remove", "path": "/tags/2"},
+            {"op": "remove", "path": "/tags/1"},
+            {"op": "remove", "path": "/gone"},
+            {"op": "add", "path": "/new", "value": {"k": null}},
+        ]));
+        assert_eq!(crate::patch::apply(a, &ops).unwrap(), b);
+    }
+}
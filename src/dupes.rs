@@ -0,0 +1,172 @@
+use std::fmt;
+use std::io::Read;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Number, Value};
+
+/// What `--dupes` does with an object in the input that has the same key more than once
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Dupes {
+    /// Fail, saying where the duplicate is
+    Error,
+    /// Keep the first value of the key
+    First,
+    /// Keep the last value of the key, as without --dupes
+    Last,
+    /// Keep the last value of the key, and print a warning saying where it is
+    Warn,
+}
+
+/// The JSON documents in `reader`, with duplicate keys handled according to `dupes`
+pub fn documents<R: Read + 'static>(reader: R, dupes: Dupes) -> Box<dyn Iterator<Item=Result<Value>>> {
+    fn stream<R: Read + 'static, const DUPES: u8>(reader: R) -> Box<dyn Iterator<Item=Result<Value>>> {
+        Box::new(serde_json::Deserializer::from_reader(reader).into_iter::<Checked<DUPES>>().map(|v| {
+            v.map(|v| v.0).map_err(anyhow::Error::from)
+        }))
+    }
+    match dupes {
+        Dupes::Error => stream::<R, { Dupes::Error as u8 }>(reader),
+        Dupes::First => stream::<R, { Dupes::First as u8 }>(reader),
+        Dupes::Last => stream::<R, { Dupes::Last as u8 }>(reader),
+        Dupes::Warn => stream::<R, { Dupes::Warn as u8 }>(reader),
+    }
+}
+
+/// A document read with duplicate keys handled according to `DUPES`. The policy is a type parameter rather
+/// than a field, so that documents can be read with serde_json's stream deserializer, which keeps track of the
+/// line and column that errors are reported at.
+struct Checked<const DUPES: u8>(Value);
+
+impl<'de, const DUPES: u8> Deserialize<'de> for Checked<DUPES> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let dupes = [Dupes::Error, Dupes::First, Dupes::Last, Dupes::Warn][DUPES as usize];
+        let mut path = Vec::new();
+        Ok(Checked(Checker { dupes, path: &mut path }.deserialize(deserializer)?))
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a value is in its document, like `.users[3]`
+struct Path<'a>(&'a [Segment]);
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str(".");
+        }
+        for segment in self.0 {
+            match segment {
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a value like `Value`'s own deserializer, keeping track of the path to it to report duplicates at
+struct Checker<'p> {
+    dupes: Dupes,
+    path: &'p mut Vec<Segment>,
+}
+
+impl<'de> DeserializeSeed<'de> for Checker<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Checker<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any valid JSON value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Number(n.into()))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(Value::Number(n.into()))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(n).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut arr = Vec::new();
+        loop {
+            self.path.push(Segment::Index(arr.len()));
+            let v = seq.next_element_seed(Checker { dupes: self.dupes, path: &mut *self.path })?;
+            self.path.pop();
+            match v {
+                Some(v) => arr.push(v),
+                None => return Ok(Value::Array(arr)),
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut obj = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            self.path.push(Segment::Key(key));
+            let v = map.next_value_seed(Checker { dupes: self.dupes, path: &mut *self.path })?;
+            let Some(Segment::Key(key)) = self.path.pop() else {
+                unreachable!()
+            };
+            if obj.contains_key(&key) {
+                let message = format!("duplicate key `{}` in {}", key, Path(self.path));
+                match self.dupes {
+                    Dupes::Error => return Err(de::Error::custom(message)),
+                    Dupes::First => continue,
+                    Dupes::Last => {}
+                    Dupes::Warn => eprintln!("Warning: {}", message),
+                }
+            }
+            obj.insert(key, v);
+        }
+        Ok(Value::Object(obj))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_dupes() {
+        let input = "{\"a\": 1, \"b\": [{\"c\": 2, \"c\": 3}], \"a\": 4}\n[5]";
+        let read = |dupes| documents(input.as_bytes(), dupes).collect::<Result<Vec<Value>>>();
+        assert_eq!(read(Dupes::First).unwrap(), vec![json!({"a": 1, "b": [{"c": 2}]}), json!([5])]);
+        assert_eq!(read(Dupes::Last).unwrap(), vec![json!({"a": 4, "b": [{"c": 3}]}), json!([5])]);
+        let err = read(Dupes::Error).unwrap_err();
+        assert_eq!(err.to_string(), "duplicate key `c` in .b[0] at line 1 column 31");
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::{self, BufReader, Bytes, Read};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// A reader of JSON documents that splits top-level arrays into their elements as they're read, so a query
+/// starting with `[]` or a filter runs on a multi-gigabyte array without holding it in memory. Each element
+/// of `range` is produced wrapped in an array of its own, which the query's first command then unwraps the
+/// same way it would the whole array. Documents that aren't arrays are produced as they are.
+pub struct Elements<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    peeked: Option<u8>,
+    /// The elements to produce of each array, by index: from the start, up to the end if there is one
+    range: (usize, Option<usize>),
+    /// The index of the next element, while inside a top-level array
+    index: Option<usize>,
+    failed: bool,
+}
+
+impl<R: Read> Elements<R> {
+    pub fn new(reader: R, range: (usize, Option<usize>)) -> Self {
+        Elements { bytes: BufReader::new(reader).bytes(), peeked: None, range, index: None, failed: false }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.peeked.is_none() {
+            self.peeked = self.bytes.next().transpose()?;
+        }
+        Ok(self.peeked)
+    }
+
+    fn bump(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek()?;
+        self.peeked = None;
+        Ok(b)
+    }
+
+    /// The next byte that isn't whitespace, without consuming it
+    fn peek_token(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+            self.peeked = None;
+        }
+        Ok(None)
+    }
+
+    /// Read the text of one value, without parsing it: a container up to its closing bracket, a string up to its
+    /// closing quote, or a scalar up to the next delimiter
+    fn raw_value(&mut self) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        let mut depth = 0;
+        let mut in_string = false;
+        while let Some(b) = self.peek()? {
+            if !in_string && depth == 0 && !raw.is_empty() && !matches!(raw[0], b'[' | b'{' | b'"')
+                && (b.is_ascii_whitespace() || matches!(b, b',' | b']' | b'}' | b'[' | b'{' | b'"')) {
+                break;
+            }
+            raw.push(b);
+            self.peeked = None;
+            match b {
+                b'\\' if in_string => raw.push(self.bump()?.ok_or_else(|| anyhow!("Unterminated string"))?),
+                b'"' => in_string = !in_string,
+                b'[' | b'{' if !in_string => depth += 1,
+                b']' | b'}' if !in_string => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 && !in_string && matches!(raw[0], b'[' | b'{' | b'"') {
+                break;
+            }
+        }
+        if raw.is_empty() {
+            return Err(match self.peek()? {
+                Some(b) => anyhow!("Unexpected '{}'", b as char),
+                None => anyhow!("Unexpected end of input"),
+            });
+        }
+        Ok(raw)
+    }
+
+    fn read(&mut self) -> Result<Option<Value>> {
+        loop {
+            let Some(index) = self.index else {
+                match self.peek_token()? {
+                    None => return Ok(None),
+                    Some(b'[') => {
+                        self.peeked = None;
+                        self.index = Some(0);
+                        continue;
+                    }
+                    Some(_) => return Ok(Some(serde_json::from_slice(&self.raw_value()?)?)),
+                }
+            };
+            match self.peek_token()? {
+                Some(b']') => {
+                    self.peeked = None;
+                    self.index = None;
+                    continue;
+                }
+                Some(b',') if index > 0 => {
+                    self.peeked = None;
+                    self.peek_token()?;
+                }
+                _ if index > 0 => return Err(anyhow!("Expected ',' or ']' after element {} of the array", index - 1)),
+                _ => {}
+            }
+            let raw = self.raw_value()?;
+            self.index = Some(index + 1);
+            let (start, end) = self.range;
+            if index >= start && end.is_none_or(|end| index < end) {
+                return Ok(Some(Value::Array(vec![serde_json::from_slice(&raw)?])));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Elements<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_elements() {
+        let input = r#" [1, {"a": [2, "]"]}, "x,\"]" ,true] 3 "s" [4] {"b": null}"#;
+        let docs: Vec<Value> = Elements::new(input.as_bytes(), (0, None)).collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![
+            json!([1]), json!([{"a": [2, "]"]}]), json!(["x,\"]"]), json!([true]), json!(3), json!("s"), json!([4]),
+            json!({"b": null}),
+        ]);
+
+        let docs: Vec<Value> = Elements::new("[0, 1, 2, 3] []".as_bytes(), (1, Some(3))).collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![json!([1]), json!([2])]);
+
+        let docs: Vec<Result<Value>> = Elements::new("[1 2]".as_bytes(), (0, None)).collect();
+        assert!(matches!(docs.as_slice(), [Ok(_), Err(_)]));
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// What `--encoding` decodes input from, when it isn't UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Encoding {
+    /// ISO-8859-1, where every byte is the character with that code point
+    Latin1,
+    /// UTF-16, little-endian unless a byte order mark or the first character says otherwise
+    Utf16,
+    /// UTF-8, replacing invalid bytes with U+FFFD
+    Lossy,
+}
+
+/// `input` as UTF-8 without a byte order mark. Input in another encoding is read whole and decoded, with a
+/// warning if anything in it couldn't be and was replaced.
+pub fn decode(mut input: Box<dyn Read>, encoding: Option<Encoding>) -> Result<Box<dyn Read>> {
+    let Some(encoding) = encoding else {
+        return skip_bom(input);
+    };
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+    let (text, replaced) = match encoding {
+        Encoding::Latin1 => (bytes.iter().map(|&b| b as char).collect(), Replaced::default()),
+        Encoding::Utf16 => utf16(&bytes),
+        Encoding::Lossy => lossy(&bytes),
+    };
+    if let Some(offset) = replaced.first {
+        eprintln!(
+            "Warning: replaced {} invalid {} in the input with U+FFFD, the first at byte {}",
+            replaced.count, if replaced.count == 1 { "sequence" } else { "sequences" }, offset,
+        );
+    }
+    let text = text.strip_prefix('\u{feff}').map(str::to_string).unwrap_or(text);
+    Ok(Box::new(Cursor::new(text.into_bytes())))
+}
+
+/// Drop a UTF-8 byte order mark from the start of `input`, if it has one
+fn skip_bom(mut input: Box<dyn Read>) -> Result<Box<dyn Read>> {
+    let mut head = Vec::with_capacity(BOM.len());
+    (&mut input).take(BOM.len() as u64).read_to_end(&mut head)?;
+    if head == BOM {
+        return Ok(input);
+    }
+    Ok(Box::new(Cursor::new(head).chain(input)))
+}
+
+/// Where input was replaced in decoding it: how many times, and the byte offset of the first
+#[derive(Default)]
+struct Replaced {
+    count: usize,
+    first: Option<usize>,
+}
+
+impl Replaced {
+    fn add(&mut self, offset: usize) {
+        self.count += 1;
+        self.first.get_or_insert(offset);
+    }
+}
+
+fn lossy(bytes: &[u8]) -> (String, Replaced) {
+    let mut text = String::with_capacity(bytes.len());
+    let mut replaced = Replaced::default();
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+        text.push_str(chunk.valid());
+        offset += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            text.push(char::REPLACEMENT_CHARACTER);
+            replaced.add(offset);
+            offset += chunk.invalid().len();
+        }
+    }
+    (text, replaced)
+}
+
+fn utf16(bytes: &[u8]) -> (String, Replaced) {
+    let (big_endian, start) = match bytes {
+        [0xfe, 0xff, ..] => (true, 2),
+        [0xff, 0xfe, ..] => (false, 2),
+        // Without a byte order mark, JSON starting with an ASCII character gives the order away
+        [0, _, ..] => (true, 0),
+        _ => (false, 0),
+    };
+    let units = bytes[start..].chunks(2).map(|pair| match pair {
+        [a, b] if big_endian => u16::from_be_bytes([*a, *b]),
+        [a, b] => u16::from_le_bytes([*a, *b]),
+        // An odd byte out at the end can't be decoded
+        _ => 0xdc00,
+    });
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut replaced = Replaced::default();
+    let mut offset = start;
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => {
+                text.push(c);
+                offset += c.len_utf16() * 2;
+            }
+            Err(_) => {
+                text.push(char::REPLACEMENT_CHARACTER);
+                replaced.add(offset);
+                offset += 2;
+            }
+        }
+    }
+    (text, replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &'static [u8], encoding: Option<Encoding>) -> String {
+        let mut text = String::new();
+        decode(Box::new(input), encoding).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(read(b"\xef\xbb\xbf{\"a\": 1}", None), "{\"a\": 1}");
+        assert_eq!(read(b"{}", None), "{}");
+        assert_eq!(read(b"\"caf\xe9\"", Some(Encoding::Latin1)), "\"café\"");
+        assert_eq!(read(b"\"a\xffb\"", Some(Encoding::Lossy)), "\"a\u{fffd}b\"");
+        assert_eq!(read(b"\xff\xfe\"\0\xe9\0\"\0", Some(Encoding::Utf16)), "\"é\"");
+        assert_eq!(read(b"\0\"\0\xe9\0\"", Some(Encoding::Utf16)), "\"é\"");
+
+        let (text, replaced) = lossy(b"ab\xff\xfecd\xff");
+        assert_eq!(text, "ab\u{fffd}\u{fffd}cd\u{fffd}");
+        assert_eq!((replaced.count, replaced.first), (3, Some(2)));
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, Result};
+
+/// Expand `${VAR}` and `${VAR:-default}` placeholders in `text` with the values `lookup` gives, like
+/// `envsubst`. `$${` is a literal `${`. A variable that isn't set and has no default is an error, so a typo
+/// doesn't silently render as an empty string.
+pub fn expand(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("$${") {
+            out.push_str("${");
+            rest = &rest[3..];
+            continue;
+        }
+        let Some(body) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = body.find('}').ok_or_else(|| anyhow!("Unterminated placeholder: {}", rest.lines().next().unwrap_or(rest)))?;
+        let (name, default) = match body[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&body[..end], None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(anyhow!("Invalid variable name in ${{{}}}", &body[..end]));
+        }
+        match lookup(name).filter(|v| default.is_none() || !v.is_empty()).or(default.map(str::to_string)) {
+            Some(value) => out.push_str(&value),
+            None => return Err(anyhow!("Environment variable {} is not set", name)),
+        }
+        rest = &body[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let lookup = |name: &str| match name {
+            "HOST" => Some("db.local".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let text = r#"{"url": "postgres://${HOST}:${PORT:-5432}", "e": "${EMPTY:-x}", "cost": "$5", "lit": "$${HOST}"}"#;
+        assert_eq!(
+            expand(text, lookup).unwrap(),
+            r#"{"url": "postgres://db.local:5432", "e": "x", "cost": "$5", "lit": "${HOST}"}"#,
+        );
+        assert!(expand("${MISSING}", lookup).is_err());
+        assert!(expand("${HOST", lookup).is_err());
+    }
+}
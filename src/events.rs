@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Bytes, Read};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// The `[path, leaf]` and closing `[path]` events of a value, in the same shape as `jq --stream`: a leaf is
+/// a scalar or an empty array or object, and after the last child of a container comes a closing event with
+/// that child's path.
+pub fn of_value(value: &Value) -> Vec<Value> {
+    fn walk(out: &mut Vec<Value>, path: &mut Vec<Value>, value: &Value) {
+        let children: Vec<(Value, &Value)> = match value {
+            Value::Object(map) if !map.is_empty() => map.iter().map(|(k, v)| (json!(k), v)).collect(),
+            Value::Array(arr) if !arr.is_empty() => arr.iter().enumerate().map(|(i, v)| (json!(i), v)).collect(),
+            leaf => {
+                out.push(json!([path, leaf]));
+                return;
+            }
+        };
+        for (key, child) in children {
+            path.push(key);
+            walk(out, path, child);
+            path.pop();
+        }
+        path.push(match value {
+            Value::Object(map) => json!(map.keys().next_back()),
+            _ => json!(value.as_array().map_or(0, |a| a.len()) - 1),
+        });
+        out.push(json!([path]));
+        path.pop();
+    }
+    let mut out = Vec::new();
+    walk(&mut out, &mut Vec::new(), value);
+    out
+}
+
+enum Frame {
+    Object,
+    Array(usize),
+}
+
+/// A pull parser producing the events of a stream of JSON documents as they're read, so memory use depends
+/// on how deeply the input is nested rather than how large it is
+pub struct Events<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    peeked: Option<u8>,
+    stack: Vec<Frame>,
+    path: Vec<Value>,
+    pending: VecDeque<Value>,
+    /// Set once parsing fails. It's reported after the events that came before it, and ends the stream.
+    error: Option<anyhow::Error>,
+    failed: bool,
+}
+
+impl<R: Read> Events<R> {
+    pub fn new(reader: R) -> Self {
+        Events {
+            bytes: BufReader::new(reader).bytes(),
+            peeked: None,
+            stack: Vec::new(),
+            path: Vec::new(),
+            pending: VecDeque::new(),
+            error: None,
+            failed: false,
+        }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.peeked.is_none() {
+            self.peeked = self.bytes.next().transpose()?;
+        }
+        Ok(self.peeked)
+    }
+
+    fn bump(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek()?;
+        self.peeked = None;
+        Ok(b)
+    }
+
+    /// The next byte that isn't whitespace, without consuming it
+    fn peek_token(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+            self.peeked = None;
+        }
+        Ok(None)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<()> {
+        match self.peek_token()? {
+            Some(b) if b == expected => {
+                self.peeked = None;
+                Ok(())
+            }
+            Some(b) => Err(anyhow!("Expected '{}' but found '{}'", expected as char, b as char)),
+            None => Err(anyhow!("Expected '{}' but reached the end of the input", expected as char)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut raw = vec![b'"'];
+        loop {
+            let b = self.bump()?.ok_or_else(|| anyhow!("Unterminated string"))?;
+            raw.push(b);
+            match b {
+                b'"' => break,
+                b'\\' => raw.push(self.bump()?.ok_or_else(|| anyhow!("Unterminated string"))?),
+                _ => {}
+            }
+        }
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    fn scalar(&mut self) -> Result<Value> {
+        if self.peek_token()? == Some(b'"') {
+            return Ok(Value::String(self.string()?));
+        }
+        let mut raw = Vec::new();
+        while let Some(b) = self.peek()? {
+            if !(b.is_ascii_alphanumeric() || matches!(b, b'-' | b'+' | b'.')) {
+                break;
+            }
+            raw.push(b);
+            self.peeked = None;
+        }
+        if raw.is_empty() {
+            return Err(match self.peek()? {
+                Some(b) => anyhow!("Unexpected '{}'", b as char),
+                None => anyhow!("Unexpected end of input"),
+            });
+        }
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    /// Parse the start of a value at the current path: either a leaf, which becomes an event, or the first
+    /// key or index of a container
+    fn value(&mut self) -> Result<()> {
+        match self.peek_token()? {
+            Some(open @ (b'{' | b'[')) => {
+                self.peeked = None;
+                let close = if open == b'{' { b'}' } else { b']' };
+                if self.peek_token()? == Some(close) {
+                    self.peeked = None;
+                    let empty = if open == b'{' { json!({}) } else { json!([]) };
+                    self.pending.push_back(json!([self.path, empty]));
+                    return self.after_value();
+                }
+                if open == b'{' {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    self.stack.push(Frame::Object);
+                    self.path.push(Value::String(key));
+                } else {
+                    self.stack.push(Frame::Array(0));
+                    self.path.push(json!(0));
+                }
+                Ok(())
+            }
+            _ => {
+                let leaf = self.scalar()?;
+                self.pending.push_back(json!([self.path, leaf]));
+                self.after_value()
+            }
+        }
+    }
+
+    /// Consume what follows a complete value: a comma and the next key or index, or the ends of containers,
+    /// each of which produces a closing event
+    fn after_value(&mut self) -> Result<()> {
+        loop {
+            if self.stack.is_empty() {
+                return Ok(());
+            }
+            let token = self.peek_token()?;
+            self.peeked = None;
+            match (self.stack.last_mut().unwrap(), token) {
+                (Frame::Object, Some(b',')) => {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    *self.path.last_mut().unwrap() = Value::String(key);
+                    return Ok(());
+                }
+                (Frame::Array(i), Some(b',')) => {
+                    *i += 1;
+                    *self.path.last_mut().unwrap() = json!(*i);
+                    return Ok(());
+                }
+                (Frame::Object, Some(b'}')) | (Frame::Array(_), Some(b']')) => {
+                    self.pending.push_back(json!([self.path]));
+                    self.stack.pop();
+                    self.path.pop();
+                }
+                (_, Some(b)) => return Err(anyhow!("Unexpected '{}'", b as char)),
+                (_, None) => return Err(anyhow!("Unexpected end of input")),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Events<R> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.failed {
+                return self.error.take().map(Err);
+            }
+            let step = match self.peek_token() {
+                Ok(None) if self.stack.is_empty() => return None,
+                Ok(_) => self.value(),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = step {
+                self.failed = true;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let input = r#"{"a": [1, {"b": null}], "c": {}, "d\n": "x"} 3 []"#;
+        let events: Vec<Value> = Events::new(input.as_bytes()).collect::<Result<_>>().unwrap();
+        assert_eq!(events, vec![
+            json!([["a", 0], 1]),
+            json!([["a", 1, "b"], null]),
+            json!([["a", 1, "b"]]),
+            json!([["a", 1]]),
+            json!([["c"], {}]),
+            json!([["d\n"], "x"]),
+            json!([["d\n"]]),
+            json!([[], 3]),
+            json!([[], []]),
+        ]);
+        let doc: Value = serde_json::from_str(input.split(" 3").next().unwrap()).unwrap();
+        assert_eq!(of_value(&doc), events[..7]);
+
+        let errors: Vec<Result<Value>> = Events::new(r#"{"a": 1 "b"}"#.as_bytes()).collect();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[1].is_err());
+    }
+}
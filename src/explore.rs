@@ -0,0 +1,364 @@
+use std::collections::HashSet;
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::{anyhow, Result};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use serde_json::Value;
+
+use crate::base64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Format a path as a selector for the command line, like `a.b[0].c`
+fn selector(path: &[Segment]) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(k) if out.is_empty() => out.push_str(k),
+            Segment::Key(k) => {
+                out.push('.');
+                out.push_str(k);
+            }
+            Segment::Index(i) => out.push_str(&format!("[{}]", i)),
+        }
+    }
+    out
+}
+
+/// One line of the explorer: a node of the document that isn't inside a collapsed container
+struct Row<'a> {
+    path: Vec<Segment>,
+    value: &'a Value,
+}
+
+fn is_container(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(arr) => !arr.is_empty(),
+        _ => false,
+    }
+}
+
+fn summary(value: &Value) -> String {
+    match value {
+        Value::Object(map) if !map.is_empty() => format!("{{{} {}}}", map.len(), if map.len() == 1 { "key" } else { "keys" }),
+        Value::Array(arr) if !arr.is_empty() => format!("[{} {}]", arr.len(), if arr.len() == 1 { "item" } else { "items" }),
+        z => z.to_string(),
+    }
+}
+
+fn children(value: &Value) -> Vec<(Segment, &Value)> {
+    match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (Segment::Key(k.clone()), v)).collect(),
+        Value::Array(arr) => arr.iter().enumerate().map(|(i, v)| (Segment::Index(i), v)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn flatten<'a>(rows: &mut Vec<Row<'a>>, path: Vec<Segment>, value: &'a Value, collapsed: &HashSet<String>) {
+    let open = is_container(value) && !collapsed.contains(&selector(&path));
+    rows.push(Row { path: path.clone(), value });
+    if open {
+        for (segment, child) in children(value) {
+            let mut path = path.clone();
+            path.push(segment);
+            flatten(rows, path, child, collapsed);
+        }
+    }
+}
+
+/// Collapse every container nested deeper than `depth`
+fn collapse_below(collapsed: &mut HashSet<String>, path: Vec<Segment>, value: &Value, depth: usize) {
+    if !is_container(value) {
+        return;
+    }
+    if path.len() >= depth {
+        collapsed.insert(selector(&path));
+        return;
+    }
+    for (segment, child) in children(value) {
+        let mut path = path.clone();
+        path.push(segment);
+        collapse_below(collapsed, path, child, depth);
+    }
+}
+
+/// Whether the key or scalar value of a node contains `query`, ignoring case
+fn matches(path: &[Segment], value: &Value, query: &str) -> bool {
+    let query = query.to_lowercase();
+    let key = match path.last() {
+        Some(Segment::Key(k)) => k.to_lowercase().contains(&query),
+        _ => false,
+    };
+    key || (!is_container(value) && summary(value).to_lowercase().contains(&query))
+}
+
+/// Every node of the document in display order, ignoring collapsed state
+fn all_paths(value: &Value) -> Vec<(Vec<Segment>, &Value)> {
+    let mut rows = Vec::new();
+    flatten(&mut rows, Vec::new(), value, &HashSet::new());
+    rows.into_iter().map(|r| (r.path, r.value)).collect()
+}
+
+enum Mode {
+    Browse,
+    Search { query: String, origin: usize },
+}
+
+struct Explorer<'a> {
+    root: &'a Value,
+    collapsed: HashSet<String>,
+    cursor: usize,
+    scroll: usize,
+    mode: Mode,
+    query: String,
+    status: String,
+}
+
+impl<'a> Explorer<'a> {
+    fn rows(&self) -> Vec<Row<'a>> {
+        let mut rows = Vec::new();
+        flatten(&mut rows, Vec::new(), self.root, &self.collapsed);
+        rows
+    }
+
+    /// Expand the ancestors of `path` and put the cursor on it
+    fn reveal(&mut self, path: &[Segment]) {
+        for i in 0..path.len() {
+            self.collapsed.remove(&selector(&path[..i]));
+        }
+        self.cursor = self.rows().iter().position(|r| r.path == path).unwrap_or(0);
+    }
+
+    /// Move to the next node matching the search query, starting at (or after) the node at `from`
+    fn find(&mut self, from: usize, forward: bool, inclusive: bool) -> bool {
+        if self.query.is_empty() {
+            return false;
+        }
+        let rows = self.rows();
+        let Some(current) = rows.get(from).map(|r| r.path.clone()) else {
+            return false;
+        };
+        let all = all_paths(self.root);
+        let start = all.iter().position(|(p, _)| *p == current).unwrap_or(0);
+        let n = all.len();
+        let offsets: Box<dyn Iterator<Item=usize>> = if inclusive { Box::new(0..n) } else { Box::new(1..=n) };
+        for offset in offsets {
+            let i = if forward { (start + offset) % n } else { (start + n - offset % n) % n };
+            let (path, value) = &all[i];
+            if matches(path, value, &self.query) {
+                let path = path.clone();
+                self.reveal(&path);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn toggle(&mut self, open: Option<bool>) {
+        let rows = self.rows();
+        let row = &rows[self.cursor];
+        if !is_container(row.value) {
+            return;
+        }
+        let key = selector(&row.path);
+        let is_open = !self.collapsed.contains(&key);
+        if open.unwrap_or(!is_open) {
+            self.collapsed.remove(&key);
+        } else {
+            self.collapsed.insert(key);
+        }
+    }
+
+    /// Copy the selector of the highlighted node to the clipboard with an OSC 52 escape, which most
+    /// terminal emulators (and tmux, with set-clipboard on) forward to the system clipboard
+    fn copy(&mut self, out: &mut impl Write) -> Result<()> {
+        let rows = self.rows();
+        let path = selector(&rows[self.cursor].path);
+        write!(out, "\x1b]52;c;{}\x07", base64::encode(path.as_bytes()))?;
+        self.status = format!("Copied {}", path);
+        Ok(())
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, body) = (width as usize, height.saturating_sub(1) as usize);
+        let rows = self.rows();
+        self.cursor = self.cursor.min(rows.len() - 1);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + body {
+            self.scroll = self.cursor + 1 - body;
+        }
+        queue!(out, Clear(ClearType::All))?;
+        for (line, (i, row)) in rows.iter().enumerate().skip(self.scroll).take(body).enumerate() {
+            let marker = match is_container(row.value) {
+                true if self.collapsed.contains(&selector(&row.path)) => "▸ ",
+                true => "▾ ",
+                false => "  ",
+            };
+            let label = match row.path.last() {
+                Some(Segment::Key(k)) => format!("{}: ", k),
+                Some(Segment::Index(i)) => format!("[{}]: ", i),
+                None => String::new(),
+            };
+            let mut text = format!("{}{}{}{}", "  ".repeat(row.path.len()), marker, label, summary(row.value));
+            if text.chars().count() > width {
+                text = text.chars().take(width.saturating_sub(1)).chain(['…']).collect();
+            }
+            queue!(out, MoveTo(0, line as u16))?;
+            if i == self.cursor {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(&text), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(&text))?;
+            }
+        }
+        let status = match &self.mode {
+            Mode::Search { query, .. } => format!("/{}", query),
+            Mode::Browse if !self.status.is_empty() => self.status.clone(),
+            Mode::Browse => selector(&rows[self.cursor].path),
+        };
+        let status: String = status.chars().take(width).collect();
+        queue!(out, MoveTo(0, body as u16), SetAttribute(Attribute::Dim), Print(status), SetAttribute(Attribute::Reset))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Handle a key press, returning false to quit
+    fn key(&mut self, key: KeyEvent, out: &mut impl Write) -> Result<bool> {
+        self.status.clear();
+        if let Mode::Search { query, origin } = &mut self.mode {
+            let origin = *origin;
+            match key.code {
+                KeyCode::Esc => {
+                    self.mode = Mode::Browse;
+                    self.cursor = origin;
+                }
+                KeyCode::Enter => {
+                    self.query = query.clone();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    self.query = query.clone();
+                    self.cursor = origin;
+                    self.find(origin, true, true);
+                }
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    self.query = query.clone();
+                    if !self.find(origin, true, true) {
+                        self.status = "No match".to_string();
+                    }
+                }
+                _ => {}
+            }
+            return Ok(true);
+        }
+        let len = self.rows().len();
+        let (_, height) = terminal::size()?;
+        let page = height.saturating_sub(1).max(1) as usize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (self.cursor + 1).min(len - 1),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            KeyCode::PageDown => self.cursor = (self.cursor + page).min(len - 1),
+            KeyCode::Home | KeyCode::Char('g') => self.cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => self.cursor = len - 1,
+            KeyCode::Right | KeyCode::Char('l') => self.toggle(Some(true)),
+            KeyCode::Enter | KeyCode::Char(' ') => self.toggle(None),
+            KeyCode::Left | KeyCode::Char('h') => {
+                let rows = self.rows();
+                let row = &rows[self.cursor];
+                if is_container(row.value) && !self.collapsed.contains(&selector(&row.path)) {
+                    self.toggle(Some(false));
+                } else if let Some((_, parent)) = row.path.split_last() {
+                    let parent = parent.to_vec();
+                    self.reveal(&parent);
+                }
+            }
+            KeyCode::Char('/') => {
+                self.mode = Mode::Search { query: String::new(), origin: self.cursor };
+            }
+            KeyCode::Char(c @ ('n' | 'N')) if !self.find(self.cursor, c == 'n', false) => {
+                self.status = "No match".to_string();
+            }
+            KeyCode::Char('y') => self.copy(out)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+}
+
+/// Browse `value` in a full-screen, collapsible tree. Arrows or hjkl move and fold, `/` searches as you type
+/// (`n`/`N` for the next and previous match), `y` copies the selector of the highlighted node, and `q` quits.
+pub fn run(value: &Value) -> Result<()> {
+    let mut out = io::stdout();
+    if !out.is_terminal() {
+        return Err(anyhow!("--interactive requires a terminal on stdout"));
+    }
+    let mut explorer = Explorer {
+        root: value,
+        collapsed: HashSet::new(),
+        cursor: 0,
+        scroll: 0,
+        mode: Mode::Browse,
+        query: String::new(),
+        status: String::new(),
+    };
+    collapse_below(&mut explorer.collapsed, Vec::new(), value, 2);
+
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+    let result = (|| -> Result<()> {
+        loop {
+            explorer.draw(&mut out)?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release && !explorer.key(key, &mut out)? {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    execute!(out, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_selector() {
+        let path = vec![Segment::Key("a".into()), Segment::Index(0), Segment::Key("b".into())];
+        assert_eq!(selector(&path), "a[0].b");
+        assert_eq!(selector(&[Segment::Index(2)]), "[2]");
+        assert_eq!(selector(&[]), ".");
+    }
+
+    #[test]
+    fn test_flatten() {
+        let value = json!({"a": {"b": [1, 2]}, "c": 3});
+        let mut collapsed = HashSet::new();
+        collapse_below(&mut collapsed, Vec::new(), &value, 2);
+        let mut rows = Vec::new();
+        flatten(&mut rows, Vec::new(), &value, &collapsed);
+        let paths: Vec<String> = rows.iter().map(|r| selector(&r.path)).collect();
+        assert_eq!(paths, vec![".", "a", "a.b", "c"]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+/// Files a command that works through many of them couldn't do, kept so it can carry on with the rest and
+/// list them all at the end
+#[derive(Debug, Default)]
+pub struct Failures {
+    files: usize,
+    failed: Vec<(String, String)>,
+}
+
+impl Failures {
+    /// Count a file that was done
+    pub fn ok(&mut self) {
+        self.files += 1;
+    }
+
+    pub fn add(&mut self, file: &str, error: impl fmt::Display) {
+        self.files += 1;
+        let error = error.to_string();
+        // The file has a column of its own, so it isn't repeated where an error starts with it, like `a.json:2:5: ...`
+        let error = match error.strip_prefix(file).and_then(|rest| rest.strip_prefix(':')) {
+            Some(rest) => rest.trim_start().to_string(),
+            None => error,
+        };
+        self.failed.push((file.to_string(), error));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// A table of the files that failed, and why
+impl fmt::Display for Failures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} files failed:", self.failed.len(), self.files)?;
+        let width = self.failed.iter().map(|(file, _)| file.chars().count()).max().unwrap_or(0);
+        for (file, error) in &self.failed {
+            // Errors that run over several lines are indented to stay in their column
+            let error = error.replace('\n', &format!("\n  {:width$}  ", ""));
+            write!(f, "\n  {:width$}  {}", file, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Failures {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures() {
+        let mut failures = Failures::default();
+        failures.ok();
+        failures.add("a.json", "line 1, column 2: key must be a string");
+        failures.add("missing.yaml", "No such file or directory\nsecond line");
+        failures.add("b.json", "b.json:2:5: invalid JSON in document 2");
+        assert_eq!(failures.to_string(), concat!(
+            "3 of 4 files failed:\n",
+            "  a.json        line 1, column 2: key must be a string\n",
+            "  missing.yaml  No such file or directory\n",
+            "                second line\n",
+            "  b.json        2:5: invalid JSON in document 2",
+        ));
+    }
+}
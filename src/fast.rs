@@ -0,0 +1,256 @@
+use std::io::BufRead;
+
+use anyhow::Result;
+use jq::StreamCommand;
+use serde_json::Value;
+
+/// A key or index a query starts by selecting
+#[derive(Clone)]
+pub enum Step {
+    Key(String),
+    Index(i64),
+}
+
+/// The keys and indexes a query starts with, the only part of each document it can reach. The rest of the query
+/// runs on what they select.
+pub fn path(stream: &[StreamCommand]) -> Vec<Step> {
+    stream.iter()
+        .map_while(|c| match c {
+            StreamCommand::Key(key) => Some(Step::Key(key.clone())),
+            StreamCommand::Index(i) => Some(Step::Index(*i)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The documents of JSON input parsed with simd-json, for --fast. Documents can be one to a line as in NDJSON,
+/// spread over many lines, or both. Of each document, only what `path` selects is converted to a `Value`; the
+/// query still runs whole on the result, so it gives the same results as on the whole document.
+#[cfg(feature = "simd")]
+pub fn documents<R: BufRead + 'static>(mut reader: R, path: Vec<Step>) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    use anyhow::anyhow;
+
+    let mut split = Split::default();
+    let mut n = 0;
+    Ok(Box::new(std::iter::from_fn(move || {
+        let mut doc = loop {
+            if let Some(doc) = split.document() {
+                break doc;
+            }
+            split.compact();
+            match reader.read_until(b'\n', &mut split.pending) {
+                Ok(0) => break split.rest()?,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        };
+        n += 1;
+        Some(match simd_json::to_tape(&mut doc) {
+            Ok(tape) => Ok(select(tape.as_value(), &path)),
+            Err(e) => Err(anyhow!("Invalid JSON in document {}: {}", n, e)),
+        })
+    })))
+}
+
+#[cfg(not(feature = "simd"))]
+pub fn documents<R: BufRead + 'static>(_reader: R, _path: Vec<Step>) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    Err(anyhow::anyhow!("--fast needs jq built with the `simd` feature"))
+}
+
+/// The part of `value` that `path` selects: the key or index its first command selects, with the rest of `path`
+/// selected from that in turn, and everything else left out. Arrays keep their length, with nulls in place of
+/// the entries left out, so an index from the end still finds the same entry. Wherever a command doesn't simply
+/// select something that's there, what's been reached is converted whole, for the query to deal with.
+#[cfg(feature = "simd")]
+fn select(value: simd_json::tape::Value, path: &[Step]) -> Value {
+    match path.first() {
+        Some(Step::Key(key)) => if let Some(obj) = value.as_object() {
+            // The last of duplicate keys wins, as when parsing to a Value
+            if let Some((_, v)) = obj.iter().filter(|(k, _)| k == key).last() {
+                let mut map = serde_json::Map::new();
+                map.insert(key.clone(), select(v, &path[1..]));
+                return Value::Object(map);
+            }
+        }
+        Some(&Step::Index(i)) => if let Some(arr) = value.as_array() {
+            if let Some(at) = jq::resolve_index(i, arr.len()) {
+                return Value::Array(arr.iter().enumerate()
+                    .map(|(j, v)| if j == at { select(v, &path[1..]) } else { Value::Null })
+                    .collect());
+            }
+        }
+        _ => {}
+    }
+    convert(value)
+}
+
+/// `value` converted whole, keeping the order of object keys
+#[cfg(feature = "simd")]
+fn convert(value: simd_json::tape::Value) -> Value {
+    use simd_json::prelude::*;
+
+    if let Some(obj) = value.as_object() {
+        Value::Object(obj.iter().map(|(k, v)| (k.to_string(), convert(v))).collect())
+    } else if let Some(arr) = value.as_array() {
+        Value::Array(arr.iter().map(convert).collect())
+    } else if let Some(s) = value.as_str() {
+        Value::String(s.to_string())
+    } else if let Some(b) = value.as_bool() {
+        Value::Bool(b)
+    } else if let Some(n) = value.as_i64() {
+        Value::from(n)
+    } else if let Some(n) = value.as_u64() {
+        Value::from(n)
+    } else if let Some(n) = value.as_f64() {
+        Value::from(n)
+    } else {
+        Value::Null
+    }
+}
+
+/// Finds where each document in a stream of JSON ends, without parsing it, so documents can be parsed one at a
+/// time however they're laid out over lines
+#[cfg(any(feature = "simd", test))]
+#[derive(Default)]
+struct Split {
+    /// Input read but not yet split off into documents
+    pending: Vec<u8>,
+    /// How much of `pending` has been split off
+    taken: usize,
+    /// How much of `pending` has been scanned
+    scanned: usize,
+    /// Where the document being scanned starts, once it has
+    start: Option<usize>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+#[cfg(any(feature = "simd", test))]
+impl Split {
+    /// The next whole document in `pending`, if there is one
+    fn document(&mut self) -> Option<Vec<u8>> {
+        while self.scanned < self.pending.len() {
+            let i = self.scanned;
+            let b = self.pending[i];
+            self.scanned += 1;
+            if self.start.is_none() {
+                if !b.is_ascii_whitespace() {
+                    self.start = Some(i);
+                    match b {
+                        b'{' | b'[' => self.depth = 1,
+                        b'"' => self.in_string = true,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            let end = if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                (!self.in_string && self.depth == 0).then_some(i + 1)
+            } else if self.depth == 0 {
+                // A number, true, false or null, which runs until whitespace or the start of another document
+                (b.is_ascii_whitespace() || matches!(b, b'{' | b'[' | b'"')).then_some(i)
+            } else {
+                match b {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => self.depth += 1,
+                    b'}' | b']' => self.depth -= 1,
+                    _ => {}
+                }
+                (self.depth == 0).then_some(i + 1)
+            };
+            if let Some(end) = end {
+                self.scanned = end;
+                return Some(self.take(end));
+            }
+        }
+        None
+    }
+
+    /// Whatever is left of a document at the end of the input, which is whole only if it's a number, true,
+    /// false or null
+    fn rest(&mut self) -> Option<Vec<u8>> {
+        self.start?;
+        Some(self.take(self.pending.len()))
+    }
+
+    fn take(&mut self, end: usize) -> Vec<u8> {
+        let doc = self.pending[self.start.take().unwrap()..end].to_vec();
+        self.taken = end;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        doc
+    }
+
+    /// Drop what's been split off from `pending`, before reading more into it
+    fn compact(&mut self) {
+        self.pending.drain(..self.taken);
+        self.scanned -= self.taken;
+        self.start = self.start.map(|s| s - self.taken);
+        self.taken = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &str) -> Vec<String> {
+        let mut split = Split { pending: input.as_bytes().to_vec(), ..Split::default() };
+        let mut docs: Vec<String> = std::iter::from_fn(|| split.document()).map(|d| String::from_utf8(d).unwrap()).collect();
+        docs.extend(split.rest().map(|d| String::from_utf8(d).unwrap()));
+        docs
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split("{\"a\":1}\n{\n  \"b\": [2, {\"c\": \"}\"}]\n}\n3\n"), ["{\"a\":1}", "{\n  \"b\": [2, {\"c\": \"}\"}]\n}", "3"]);
+        assert_eq!(split("[1]{}\"x\\\"]\" true null\n-1.5e3"), ["[1]", "{}", "\"x\\\"]\"", "true", "null", "-1.5e3"]);
+        assert_eq!(split("\n  \n"), Vec::<String>::new());
+        assert_eq!(split("{\"a\": [1,\n"), ["{\"a\": [1,\n"]);
+    }
+
+    #[test]
+    fn test_path() {
+        let (stream, _) = jq::evaluate_command("items[0].spec keys").unwrap();
+        assert_eq!(path(&stream).len(), 3);
+        assert!(path(&[]).is_empty());
+    }
+
+    #[cfg(feature = "simd")]
+    fn documents(input: &'static str, path: &str) -> Vec<Value> {
+        let (path, _) = jq::evaluate_command(path).unwrap();
+        super::documents(input.as_bytes(), super::path(&path)).unwrap().collect::<Result<_>>().unwrap()
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_documents() {
+        use serde_json::json;
+
+        let docs = documents("{\"b\": 1, \"a\": [2]}\n\n3\n", "");
+        assert_eq!(docs, vec![json!({"b": 1, "a": [2]}), json!(3)]);
+        assert_eq!(docs[0].as_object().unwrap().keys().next().unwrap(), "b");
+
+        let docs = documents("{\"a\": 1}\n{\n  \"a\": 2\n}\n[3, 4.5, -6, 18446744073709551615]", "");
+        assert_eq!(docs, vec![json!({"a": 1}), json!({"a": 2}), json!([3, 4.5, -6, 18446744073709551615u64])]);
+
+        let docs = documents("{\"a\": {\"b\": 1, \"c\": 2}, \"d\": 3, \"a\": [{\"e\": 4}, {\"e\": 5}, 6]}\n{\"d\": 7}\n[8]", "a[-2].e");
+        assert_eq!(docs, vec![json!({"a": [null, {"e": 5}, null]}), json!({"d": 7}), json!([8])]);
+
+        let docs = documents("{\"a\": [{\"b\": 1}], \"z\": 2}", "a[0] keys");
+        assert_eq!(docs, vec![json!({"a": [{"b": 1}]})]);
+
+        let input = "{\"x\": [{\"y\": 1}, {\"y\": 2}], \"w\": 0}";
+        assert_eq!(documents(input, "x.y"), vec![json!({"x": [{"y": 1}, {"y": 2}]})]);
+        assert!(super::documents("{\"a\": [1,\n".as_bytes(), vec![]).unwrap().next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait before checking a file for new data
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A reader over a file that's still being written, like `tail -f`. At the end of the file it waits for more
+/// data instead of returning EOF, so a document whose trailing line is only partly written is simply finished
+/// once the writer catches up. If the file shrinks, it was truncated or rotated, and reading restarts from the top.
+pub struct Follow {
+    path: String,
+    file: File,
+    pos: u64,
+}
+
+impl Follow {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Follow { path: path.to_string(), file: File::open(path)?, pos: 0 })
+    }
+
+    /// Start over if the file at our path is now shorter than what we've read
+    fn check_rotated(&mut self) -> io::Result<()> {
+        let Ok(len) = std::fs::metadata(&self.path).map(|m| m.len()) else {
+            // Mid-rotation, the path may briefly not exist
+            return Ok(());
+        };
+        if len < self.pos {
+            self.file = File::open(&self.path)?;
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            thread::sleep(POLL_INTERVAL);
+            self.check_rotated()?;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::yaml::{self, YamlStyle};
+
+/// Markup of a front matter block, chosen by its fence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Fenced by `---` lines, as in Jekyll and Hugo
+    Yaml,
+    /// Fenced by `+++` lines, as in Hugo and Zola
+    Toml,
+}
+
+impl Format {
+    fn fence(self) -> &'static str {
+        match self {
+            Format::Yaml => "---",
+            Format::Toml => "+++",
+        }
+    }
+}
+
+/// A Markdown (or other text) file split into its front matter and the body after it
+#[derive(Debug, PartialEq)]
+pub struct Document<'a> {
+    /// None when the file has no front matter block
+    pub format: Option<Format>,
+    pub metadata: &'a str,
+    pub body: &'a str,
+}
+
+/// Split `input` at its front matter fences. Input without front matter is all body.
+pub fn split(input: &str) -> Document<'_> {
+    let no_front_matter = Document { format: None, metadata: "", body: input };
+    let format = match input.lines().next().map(str::trim_end) {
+        Some("---") => Format::Yaml,
+        Some("+++") => Format::Toml,
+        _ => return no_front_matter,
+    };
+    let start = input.find('\n').map(|i| i + 1).unwrap_or(input.len());
+    let mut pos = start;
+    for line in input[start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == format.fence() || (format == Format::Yaml && trimmed == "...") {
+            return Document {
+                format: Some(format),
+                metadata: &input[start..pos],
+                body: &input[pos + line.len()..],
+            };
+        }
+        pos += line.len();
+    }
+    no_front_matter
+}
+
+/// Parse the metadata of `doc`. A document without front matter has empty metadata.
+pub fn parse(doc: &Document) -> Result<Value> {
+    let value = match doc.format {
+        None => Value::Object(Map::new()),
+        Some(Format::Yaml) => serde_yaml::from_str::<Option<Value>>(doc.metadata)?
+            .unwrap_or_else(|| Value::Object(Map::new())),
+        Some(Format::Toml) => toml::from_str(doc.metadata)?,
+    };
+    Ok(value)
+}
+
+/// Render `value` as front matter in `format` followed by `body`. An empty object on a document that had no
+/// front matter leaves the body as it was.
+pub fn render(format: Option<Format>, value: &Value, body: &str, style: &YamlStyle) -> Result<String> {
+    if format.is_none() && value.as_object().is_some_and(|o| o.is_empty()) {
+        return Ok(body.to_string());
+    }
+    let format = format.unwrap_or(Format::Yaml);
+    let metadata = match format {
+        Format::Yaml => {
+            if !value.is_object() {
+                return Err(anyhow!("Front matter must be an object, encountered: {}", value));
+            }
+            let style = YamlStyle { document_start: false, ..style.clone() };
+            yaml::to_string(value, &style)
+        }
+        Format::Toml => toml::to_string_pretty(value)?,
+    };
+    let mut out = String::new();
+    out.push_str(format.fence());
+    out.push('\n');
+    out.push_str(&metadata);
+    if !metadata.is_empty() && !metadata.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(format.fence());
+    out.push('\n');
+    out.push_str(body);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_split() {
+        let doc = split("---\ntitle: Hello\n---\n# Hello\n");
+        assert_eq!(doc, Document { format: Some(Format::Yaml), metadata: "title: Hello\n", body: "# Hello\n" });
+        let doc = split("+++\ntitle = \"Hello\"\n+++\nbody");
+        assert_eq!(doc.format, Some(Format::Toml));
+        assert_eq!(parse(&doc).unwrap(), json!({"title": "Hello"}));
+        let doc = split("---\nunterminated\n");
+        assert_eq!(doc.format, None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = "---\ntitle: Hello\ndraft: true\n---\n\n# Hello\n";
+        let doc = split(input);
+        let mut value = parse(&doc).unwrap();
+        assert_eq!(render(doc.format, &value, doc.body, &YamlStyle::default()).unwrap(), input);
+        value["draft"] = json!(false);
+        assert_eq!(
+            render(doc.format, &value, doc.body, &YamlStyle::default()).unwrap(),
+            "---\ntitle: Hello\ndraft: false\n---\n\n# Hello\n",
+        );
+    }
+}
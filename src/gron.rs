@@ -0,0 +1,183 @@
+//! gron-style flattening: every value in a document becomes one `path = value;` assignment line, which
+//! makes documents greppable. `ungron` reverses it, so that filtered or edited lines become JSON again.
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+const ROOT: &str = "json";
+
+fn is_identifier(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+fn push_key(path: &mut String, key: &str) {
+    if is_identifier(key) {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push('[');
+        path.push_str(&Value::String(key.to_string()).to_string());
+        path.push(']');
+    }
+}
+
+fn flatten(value: &Value, path: &mut String, lines: &mut Vec<String>) {
+    let len = path.len();
+    match value {
+        Value::Object(map) => {
+            lines.push(format!("{} = {{}};", path));
+            for (k, v) in map {
+                push_key(path, k);
+                flatten(v, path, lines);
+                path.truncate(len);
+            }
+        }
+        Value::Array(arr) => {
+            lines.push(format!("{} = [];", path));
+            for (i, v) in arr.iter().enumerate() {
+                path.push_str(&format!("[{}]", i));
+                flatten(v, path, lines);
+                path.truncate(len);
+            }
+        }
+        z => lines.push(format!("{} = {};", path, z)),
+    }
+}
+
+/// Flatten `value` into assignment lines
+pub fn gron(value: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    flatten(value, &mut ROOT.to_string(), &mut lines);
+    lines
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+fn parse_path(mut s: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    s = s.strip_prefix(ROOT).ok_or_else(|| anyhow!("path must start with `{}`", ROOT))?;
+    while !s.is_empty() {
+        if let Some(rest) = s.strip_prefix('.') {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            segments.push(Segment::Key(rest[..end].to_string()));
+            s = &rest[end..];
+        } else if let Some(rest) = s.strip_prefix("[\"") {
+            // Find the closing quote, skipping escaped characters
+            let mut escaped = false;
+            let end = rest.char_indices()
+                .find(|&(_, c)| {
+                    let found = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    found
+                })
+                .map(|(i, _)| i)
+                .ok_or_else(|| anyhow!("unterminated key"))?;
+            let key: String = serde_json::from_str(&format!("\"{}\"", &rest[..end]))?;
+            segments.push(Segment::Key(key));
+            s = rest[end + 1..].strip_prefix(']').ok_or_else(|| anyhow!("expected `]` after key"))?;
+        } else if let Some(rest) = s.strip_prefix('[') {
+            let end = rest.find(']').ok_or_else(|| anyhow!("unterminated index"))?;
+            segments.push(Segment::Index(rest[..end].trim().parse()?));
+            s = &rest[end + 1..];
+        } else {
+            return Err(anyhow!("unexpected `{}`", s));
+        }
+    }
+    Ok(segments)
+}
+
+fn empty_for(segment: &Segment) -> Value {
+    match segment {
+        Segment::Key(_) => Value::Object(Map::new()),
+        Segment::Index(_) => Value::Array(Vec::new()),
+    }
+}
+
+fn assign(root: &mut Value, segments: &[Segment], value: Value) {
+    let mut current = root;
+    for (i, segment) in segments.iter().enumerate() {
+        let next = segments.get(i + 1);
+        let slot = match segment {
+            Segment::Key(k) => {
+                if !current.is_object() {
+                    *current = Value::Object(Map::new());
+                }
+                current.as_object_mut().unwrap().entry(k.clone()).or_insert(Value::Null)
+            }
+            &Segment::Index(idx) => {
+                if !current.is_array() {
+                    *current = Value::Array(Vec::new());
+                }
+                let arr = current.as_array_mut().unwrap();
+                if arr.len() <= idx {
+                    arr.resize(idx + 1, Value::Null);
+                }
+                &mut arr[idx]
+            }
+        };
+        if let Some(next) = next {
+            if slot.is_null() {
+                *slot = empty_for(next);
+            }
+        }
+        current = slot;
+    }
+    // Container assignments (`= {}` / `= []`) only declare a type, and mustn't wipe out children that
+    // were assigned by earlier lines.
+    let is_empty_container = matches!(&value, Value::Object(m) if m.is_empty()) || matches!(&value, Value::Array(a) if a.is_empty());
+    if is_empty_container && (current.is_object() || current.is_array()) {
+        return;
+    }
+    *current = value;
+}
+
+/// Rebuild a document from gron assignment lines. Lines can be in any order, and intermediate
+/// objects and arrays are created as needed, so grepped subsets of the output still reconstruct.
+pub fn ungron(text: &str) -> Result<Value> {
+    let mut root = Value::Null;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |e: anyhow::Error| anyhow!("Invalid gron on line {}: {}: {}", n + 1, e, line);
+        let statement = line.strip_suffix(';').unwrap_or(line);
+        let (path, value) = statement.split_once(" = ").ok_or_else(|| err(anyhow!("expected `path = value;`")))?;
+        let segments = parse_path(path.trim()).map_err(err)?;
+        let value: Value = serde_json::from_str(value.trim()).map_err(|e| err(e.into()))?;
+        assign(&mut root, &segments, value);
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({"a": [1, {"b": null}], "weird key": "x\"y", "e": {}});
+        let lines = gron(&value);
+        assert_eq!(lines, vec![
+            "json = {};",
+            "json.a = [];",
+            "json.a[0] = 1;",
+            "json.a[1] = {};",
+            "json.a[1].b = null;",
+            r#"json["weird key"] = "x\"y";"#,
+            "json.e = {};",
+        ]);
+        assert_eq!(ungron(&lines.join("\n")).unwrap(), value);
+    }
+
+    #[test]
+    fn test_ungron_subset() {
+        let value = ungron("json.users[1].name = \"bob\";\njson.count = 2;").unwrap();
+        assert_eq!(value, json!({"users": [null, {"name": "bob"}], "count": 2}));
+    }
+}
@@ -0,0 +1,60 @@
+use serde_json::Value;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+use crate::canonical::canonical;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn parse(s: &str) -> Option<Algorithm> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha224" => Some(Algorithm::Sha224),
+            "sha256" => Some(Algorithm::Sha256),
+            "sha384" => Some(Algorithm::Sha384),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha224 => Sha224::digest(data).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+            Algorithm::Sha384 => Sha384::digest(data).to_vec(),
+            Algorithm::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Hex digest of the canonical (RFC 8785) form of `obj`, so documents that differ only in formatting,
+/// key order, or number spelling hash the same.
+pub fn hash(obj: &Value, algorithm: Algorithm) -> String {
+    algorithm.digest(canonical(obj).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hash() {
+        let a: Value = serde_json::from_str(r#"{"b": 1.0, "a": [true]}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":[true],"b":1}"#).unwrap();
+        assert_eq!(hash(&a, Algorithm::Sha256), hash(&b, Algorithm::Sha256));
+        // sha256 of `"abc"`, with the quotes
+        assert_eq!(
+            hash(&json!("abc"), Algorithm::Sha256),
+            "6cc43f858fbb763301637b5af970e2a46b46f461f27e5a0f41e009c59b827b25",
+        );
+    }
+}
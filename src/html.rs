@@ -0,0 +1,379 @@
+use std::io::{self, Write};
+
+use anyhow::Result;
+use colored_json::{Color, Style, Styler};
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use serde_json::Value;
+
+use crate::json::AsciiFormatter;
+use crate::yaml;
+use crate::PrintOptions;
+
+/// The 16 ANSI colors, as rendered by xterm
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let index = match color {
+        Color::Primary => return None,
+        Color::Rgb(r, g, b) => return Some((r, g, b)),
+        Color::Fixed(n @ 16..=231) => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            return Some((level(n / 36), level(n / 6 % 6), level(n % 6)));
+        }
+        Color::Fixed(n @ 232..=255) => {
+            let v = 8 + (n - 232) * 10;
+            return Some((v, v, v));
+        }
+        Color::Fixed(n) => n,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White => 7,
+        Color::BrightBlack => 8,
+        Color::BrightRed => 9,
+        Color::BrightGreen => 10,
+        Color::BrightYellow => 11,
+        Color::BrightBlue => 12,
+        Color::BrightMagenta => 13,
+        Color::BrightCyan => 14,
+        Color::BrightWhite => 15,
+    };
+    Some(PALETTE[index as usize])
+}
+
+/// Inline CSS equivalent to a terminal style. Attributes are read back from the style's ANSI prefix, which
+/// lists them before any colors.
+fn css(style: &Style) -> String {
+    let mut css = Vec::new();
+    let prefix = style.prefix();
+    for code in prefix.trim_start_matches("\x1b[").trim_end_matches('m').split(';') {
+        match code {
+            "1" => css.push("font-weight:bold".to_string()),
+            "2" => css.push("opacity:0.7".to_string()),
+            "3" => css.push("font-style:italic".to_string()),
+            "4" => css.push("text-decoration:underline".to_string()),
+            "9" => css.push("text-decoration:line-through".to_string()),
+            _ if code.len() > 1 => break,
+            _ => {}
+        }
+    }
+    if let Some((r, g, b)) = style.foreground.and_then(rgb) {
+        css.push(format!("color:#{:02x}{:02x}{:02x}", r, g, b));
+    }
+    if let Some((r, g, b)) = style.background.and_then(rgb) {
+        css.push(format!("background-color:#{:02x}{:02x}{:02x}", r, g, b));
+    }
+    css.join(";")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Opening and closing tags for text in `style`, which are empty for unstyled text
+fn tags(style: &Style) -> (String, &'static str) {
+    match css(style) {
+        css if css.is_empty() => (String::new(), ""),
+        css => (format!("<span style=\"{}\">", css), "</span>"),
+    }
+}
+
+fn span(out: &mut String, style: &Style, text: &str) {
+    let (open, close) = tags(style);
+    out.push_str(&open);
+    out.push_str(&escape(text));
+    out.push_str(close);
+}
+
+/// Wraps a formatter to write HTML, with each token in a span styled like the terminal colors
+struct HtmlFormatter<F> {
+    inner: F,
+    styler: Styler,
+    in_key: bool,
+}
+
+impl<F: Formatter> HtmlFormatter<F> {
+    /// Run an inner formatter method into a buffer, and write its output HTML-escaped
+    fn escaped<W: ?Sized + Write>(&mut self, w: &mut W, f: impl FnOnce(&mut F, &mut Vec<u8>) -> io::Result<()>) -> io::Result<()> {
+        let mut buf = Vec::new();
+        f(&mut self.inner, &mut buf)?;
+        w.write_all(escape(&String::from_utf8_lossy(&buf)).as_bytes())
+    }
+
+    fn styled<W: ?Sized + Write>(&mut self, w: &mut W, style: Style, f: impl FnOnce(&mut F, &mut Vec<u8>) -> io::Result<()>) -> io::Result<()> {
+        let (open, close) = tags(&style);
+        w.write_all(open.as_bytes())?;
+        self.escaped(w, f)?;
+        w.write_all(close.as_bytes())
+    }
+
+    fn string_style(&self) -> Style {
+        if self.in_key { self.styler.key } else { self.styler.string_value }
+    }
+}
+
+macro_rules! scalar {
+    ($($name:ident($value:ident: $ty:ty) => $style:ident;)*) => {
+        $(
+            fn $name<W: ?Sized + Write>(&mut self, w: &mut W, $value: $ty) -> io::Result<()> {
+                let style = self.styler.$style;
+                self.styled(w, style, |f, buf| f.$name(buf, $value))
+            }
+        )*
+    };
+}
+
+impl<F: Formatter> Formatter for HtmlFormatter<F> {
+    scalar! {
+        write_bool(value: bool) => bool_value;
+        write_i8(value: i8) => integer_value;
+        write_i16(value: i16) => integer_value;
+        write_i32(value: i32) => integer_value;
+        write_i64(value: i64) => integer_value;
+        write_i128(value: i128) => integer_value;
+        write_u8(value: u8) => integer_value;
+        write_u16(value: u16) => integer_value;
+        write_u32(value: u32) => integer_value;
+        write_u64(value: u64) => integer_value;
+        write_u128(value: u128) => integer_value;
+        write_f32(value: f32) => float_value;
+        write_f64(value: f64) => float_value;
+        write_number_str(value: &str) => float_value;
+    }
+
+    fn write_null<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.nil_value;
+        self.styled(w, style, |f, buf| f.write_null(buf))
+    }
+
+    fn begin_string<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        w.write_all(tags(&self.string_style()).0.as_bytes())?;
+        self.escaped(w, |f, buf| f.begin_string(buf))
+    }
+
+    fn end_string<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.end_string(buf))?;
+        w.write_all(tags(&self.string_style()).1.as_bytes())
+    }
+
+    fn write_string_fragment<W: ?Sized + Write>(&mut self, w: &mut W, fragment: &str) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.write_string_fragment(buf, fragment))
+    }
+
+    fn write_char_escape<W: ?Sized + Write>(&mut self, w: &mut W, escape: serde_json::ser::CharEscape) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.write_char_escape(buf, escape))
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.array_brackets;
+        self.styled(w, style, |f, buf| f.begin_array(buf))
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.array_brackets;
+        self.styled(w, style, |f, buf| f.end_array(buf))
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.begin_array_value(buf, first))
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.end_array_value(buf))
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.object_brackets;
+        self.styled(w, style, |f, buf| f.begin_object(buf))
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.object_brackets;
+        self.styled(w, style, |f, buf| f.end_object(buf))
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(&mut self, w: &mut W, first: bool) -> io::Result<()> {
+        self.in_key = true;
+        self.escaped(w, |f, buf| f.begin_object_key(buf, first))
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.in_key = false;
+        self.escaped(w, |f, buf| f.end_object_key(buf))
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        let style = self.styler.object_colon;
+        self.styled(w, style, |f, buf| f.begin_object_value(buf))
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, w: &mut W) -> io::Result<()> {
+        self.escaped(w, |f, buf| f.end_object_value(buf))
+    }
+}
+
+fn serialize<F: Formatter>(obj: &Value, f: F, styler: Styler) -> Result<String> {
+    let mut out = Vec::new();
+    let f = HtmlFormatter { inner: f, styler, in_key: false };
+    obj.serialize(&mut serde_json::Serializer::with_formatter(&mut out, f))?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// Render `obj` as JSON in a `<pre>` block, highlighted with the same theme as terminal output
+pub fn json(obj: &Value, opts: &PrintOptions, pretty: bool) -> Result<String> {
+    let indent = opts.json_indent();
+    let body = match (pretty && !indent.is_empty(), opts.ascii) {
+        (true, true) => serialize(obj, AsciiFormatter(PrettyFormatter::with_indent(&indent)), opts.styler)?,
+        (true, false) => serialize(obj, PrettyFormatter::with_indent(&indent), opts.styler)?,
+        (false, true) => serialize(obj, AsciiFormatter(CompactFormatter), opts.styler)?,
+        (false, false) => serialize(obj, CompactFormatter, opts.styler)?,
+    };
+    Ok(format!("<pre>{}</pre>", body))
+}
+
+/// Length of the scalar at the start of `s`: a quoted string, or plain text up to the next `: `, or the
+/// next flow indicator in flow style
+fn scalar_len(s: &str, flow: bool) -> usize {
+    let bytes = s.as_bytes();
+    match bytes[0] {
+        b'\'' => {
+            let mut i = 1;
+            while i < bytes.len() {
+                if bytes[i] == b'\'' {
+                    if bytes.get(i + 1) == Some(&b'\'') {
+                        i += 2;
+                        continue;
+                    }
+                    return i + 1;
+                }
+                i += 1;
+            }
+            bytes.len()
+        }
+        b'"' => {
+            let mut i = 1;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'"' => return i + 1,
+                    _ => i += 1,
+                }
+            }
+            bytes.len()
+        }
+        _ => {
+            for (i, c) in s.char_indices() {
+                if c == ':' && matches!(bytes.get(i + 1), None | Some(b' ')) {
+                    return i;
+                }
+                if flow && matches!(c, ',' | '[' | ']' | '{' | '}') {
+                    return i;
+                }
+            }
+            s.len()
+        }
+    }
+}
+
+fn scalar_style(token: &str, styler: &Styler) -> Style {
+    match token {
+        "null" | "~" => styler.nil_value,
+        "true" | "false" => styler.bool_value,
+        ".inf" | "-.inf" | ".nan" => styler.float_value,
+        t if t.parse::<i64>().is_ok() => styler.integer_value,
+        t if t.parse::<f64>().is_ok() => styler.float_value,
+        _ => styler.string_value,
+    }
+}
+
+fn yaml_line(out: &mut String, line: &str, styler: &Styler, flow: bool) {
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let bare = rest.len() == 1 || rest[1..].starts_with(' ');
+        if c == ' ' || c == ',' || (c == '-' && bare) {
+            out.push(c);
+            rest = &rest[1..];
+        } else if c == ':' {
+            span(out, &styler.object_colon, ":");
+            rest = &rest[1..];
+        } else if matches!(c, '{' | '}') {
+            span(out, &styler.object_brackets, &rest[..1]);
+            rest = &rest[1..];
+        } else if matches!(c, '[' | ']') {
+            span(out, &styler.array_brackets, &rest[..1]);
+            rest = &rest[1..];
+        } else {
+            let (token, after) = rest.split_at(scalar_len(rest, flow).max(1));
+            let is_key = after.starts_with(':') && (after.len() == 1 || after[1..].starts_with(' '));
+            let style = if is_key { styler.key } else { scalar_style(token, styler) };
+            span(out, &style, token);
+            rest = after;
+        }
+    }
+}
+
+/// Render `obj` as YAML in a `<pre>` block, highlighted with the same theme as terminal output
+pub fn yaml(obj: &Value, opts: &PrintOptions) -> String {
+    let (styler, style) = (opts.styler, &opts.yaml);
+    let mut out = String::from("<pre>");
+    // Indentation of the line that opened a block literal, whose content is indented further
+    let mut literal: Option<usize> = None;
+    for line in yaml::to_string(obj, style).lines() {
+        let indent = line.len() - line.trim_start().len();
+        if literal.is_some_and(|open| line.trim().is_empty() || indent > open) {
+            span(&mut out, &styler.string_value, line);
+        } else if line == "---" {
+            out.push_str(line);
+            literal = None;
+        } else {
+            yaml_line(&mut out, line, &styler, style.flow);
+            let last = line.rsplit(' ').next().unwrap_or(line);
+            literal = matches!(last, "|" | "|-" | "|+").then_some(indent);
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json() {
+        let html = json(&json!({"a<b": [1, null]}), &PrintOptions::default(), false).unwrap();
+        assert_eq!(html, concat!(
+            "<pre><span style=\"font-weight:bold\">{</span>",
+            "<span style=\"font-weight:bold;color:#0000ee\">\"a&lt;b\"</span>:",
+            "<span style=\"font-weight:bold\">[</span>1,",
+            "<span style=\"font-weight:bold;color:#7f7f7f\">null</span><span style=\"font-weight:bold\">]</span>",
+            "<span style=\"font-weight:bold\">}</span></pre>",
+        ));
+    }
+
+    #[test]
+    fn test_yaml() {
+        let html = yaml(&json!({"a": "x: y", "b": [true], "c": "1\n2"}), &PrintOptions::default());
+        assert_eq!(html, concat!(
+            "<pre><span style=\"font-weight:bold;color:#0000ee\">a</span>: ",
+            "<span style=\"color:#00cd00\">'x: y'</span>\n",
+            "<span style=\"font-weight:bold;color:#0000ee\">b</span>:\n",
+            "- true\n",
+            "<span style=\"font-weight:bold;color:#0000ee\">c</span>: <span style=\"color:#00cd00\">|-</span>\n",
+            "<span style=\"color:#00cd00\">  1</span>\n",
+            "<span style=\"color:#00cd00\">  2</span>\n",
+            "</pre>",
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::{self, File, Metadata, TryLockError};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use similar::TextDiff;
+use tempfile::NamedTempFile;
+
+/// Replace the contents of `dest` without ever leaving it truncated. The new contents are written to a
+/// temporary file in the same directory, given the original's permissions, and renamed over it, which is
+/// atomic on the same filesystem. With a `backup` suffix, the original is first copied to `dest` + suffix.
+pub fn write(dest: &str, contents: &[u8], backup: Option<&str>) -> Result<()> {
+    let mut file = Replacement::create(dest)?;
+    file.write_all(contents)?;
+    file.commit(backup)
+}
+
+/// An advisory lock on a file being edited in place, held until it's dropped, so another jq editing the same
+/// file waits for it or fails rather than interleaving their changes. Programs that don't take the lock aren't
+/// kept out, but with `no_clobber` a change they make while the file is edited is noticed before it's replaced.
+pub struct Lock {
+    file: File,
+    dest: String,
+    /// What the file was like when it was locked, to tell if it's changed since, with `no_clobber`
+    seen: Option<Stamp>,
+}
+
+/// Enough of a file's metadata to tell if it's been written to or replaced
+#[derive(Debug, PartialEq)]
+struct Stamp {
+    id: Option<(u64, u64)>,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    fn of(metadata: &Metadata) -> Self {
+        Stamp { id: id(metadata), len: metadata.len(), modified: metadata.modified().ok() }
+    }
+}
+
+/// The device and inode of a file, which change when it's replaced by renaming another over it
+#[cfg(unix)]
+fn id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+impl Lock {
+    /// Lock `dest` to edit it, or only to read it if `shared`, as for a dry run. If another process has it
+    /// locked, wait for it to finish if `wait`, and fail otherwise.
+    pub fn acquire(dest: &str, shared: bool, wait: bool, no_clobber: bool) -> Result<Self> {
+        loop {
+            let file = File::open(dest).with_context(|| format!("Failed to open {}", dest))?;
+            let locked = match (shared, wait) {
+                (false, true) => file.lock().map_err(TryLockError::Error),
+                (true, true) => file.lock_shared().map_err(TryLockError::Error),
+                (false, false) => file.try_lock(),
+                (true, false) => file.try_lock_shared(),
+            };
+            match locked {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => bail!("{} is being edited by another process, pass --wait to wait for it", dest),
+                Err(TryLockError::Error(e)) => return Err(e).with_context(|| format!("Failed to lock {}", dest)),
+            }
+            // The process that had the lock may have replaced the file, leaving this one locking the old one
+            let locked = Stamp::of(&file.metadata()?);
+            let current = Stamp::of(&fs::metadata(dest).with_context(|| format!("Failed to read {}", dest))?);
+            if locked.id == current.id {
+                return Ok(Lock { file, dest: dest.to_string(), seen: no_clobber.then_some(locked) });
+            }
+        }
+    }
+
+    /// The locked file, to read it
+    pub fn file(&self) -> Result<File> {
+        Ok(self.file.try_clone()?)
+    }
+
+    /// Fail if the file has changed since it was locked, with `no_clobber`, so it isn't replaced
+    pub fn unchanged(&self) -> Result<()> {
+        let Some(seen) = &self.seen else {
+            return Ok(());
+        };
+        let current = fs::metadata(&self.dest).with_context(|| format!("Failed to read {}", self.dest))?;
+        if Stamp::of(&current) != *seen {
+            bail!("{} changed while it was being edited, so it was left as it is", self.dest);
+        }
+        Ok(())
+    }
+}
+
+/// New contents for `dest`, written a piece at a time as with `write`, so they never have to be held in
+/// memory whole. `dest` is only replaced by `commit`: dropping a replacement leaves it as it was.
+pub struct Replacement {
+    dest: String,
+    permissions: fs::Permissions,
+    tmp: BufWriter<NamedTempFile>,
+}
+
+impl Replacement {
+    pub fn create(dest: &str) -> Result<Self> {
+        let path = Path::new(dest);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let metadata = fs::metadata(path).with_context(|| format!("Failed to read {}", dest))?;
+        let tmp = NamedTempFile::new_in(dir)
+            .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+        Ok(Replacement { dest: dest.to_string(), permissions: metadata.permissions(), tmp: BufWriter::new(tmp) })
+    }
+
+    /// Replace `dest` with what's been written, first copying it to `dest` + `backup` if there's a suffix
+    pub fn commit(self, backup: Option<&str>) -> Result<()> {
+        let Replacement { dest, permissions, tmp } = self;
+        let tmp = tmp.into_inner().map_err(|e| e.into_error())?;
+        tmp.as_file().sync_all()?;
+        fs::set_permissions(tmp.path(), permissions)?;
+        if let Some(suffix) = backup {
+            let backup = format!("{}{}", dest, suffix);
+            fs::copy(&dest, &backup).with_context(|| format!("Failed to write backup {}", backup))?;
+        }
+        tmp.persist(&dest).with_context(|| format!("Failed to replace {}", dest))?;
+        Ok(())
+    }
+}
+
+impl Write for Replacement {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tmp.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tmp.flush()
+    }
+}
+
+/// A unified diff from the current contents of `dest` to `contents`, empty if they're the same. With `color`,
+/// removed lines are red, added lines green, and hunk headers cyan.
+pub fn diff(dest: &str, contents: &[u8], color: bool) -> Result<String> {
+    let old = fs::read_to_string(dest).with_context(|| format!("Failed to read {}", dest))?;
+    let new = String::from_utf8_lossy(contents);
+    // Relative paths get git's a/ and b/ prefixes, so the diff applies with `git apply` or `patch -p1`
+    let label = |side: &str| match Path::new(dest).is_absolute() {
+        true => dest.to_string(),
+        false => format!("{}/{}", side, dest),
+    };
+    let diff = TextDiff::from_lines(old.as_str(), new.as_ref())
+        .unified_diff()
+        .header(&label("a"), &label("b"))
+        .to_string();
+    if !color {
+        return Ok(diff);
+    }
+    Ok(diff.split_inclusive('\n')
+        .map(|line| {
+            let code = match line.as_bytes().first() {
+                _ if line.starts_with("---") || line.starts_with("+++") => "1",
+                Some(b'-') => "31",
+                Some(b'+') => "32",
+                Some(b'@') => "36",
+                _ => return line.to_string(),
+            };
+            let (text, newline) = line.strip_suffix('\n').map_or((line, ""), |t| (t, "\n"));
+            format!("\x1b[{}m{}\x1b[0m{}", code, text, newline)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("data.json");
+        let dest = dest.to_str().unwrap();
+        fs::write(dest, "old").unwrap();
+        write(dest, b"new", Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(dest).unwrap(), "new");
+        assert_eq!(fs::read_to_string(format!("{}.bak", dest)).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let mut file = Replacement::create(dest).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(dest).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("data.json");
+        let dest = dest.to_str().unwrap();
+        fs::write(dest, "old").unwrap();
+        let lock = Lock::acquire(dest, false, false, true).unwrap();
+        assert!(Lock::acquire(dest, false, false, false).is_err());
+        assert!(Lock::acquire(dest, true, false, false).is_err());
+        lock.unchanged().unwrap();
+        fs::write(dest, "changed").unwrap();
+        assert!(lock.unchanged().is_err());
+        drop(lock);
+
+        let reading = Lock::acquire(dest, true, false, false).unwrap();
+        let _also_reading = Lock::acquire(dest, true, false, false).unwrap();
+        assert!(Lock::acquire(dest, false, false, false).is_err());
+        reading.unchanged().unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("data.json");
+        let dest = dest.to_str().unwrap();
+        fs::write(dest, "{\n  \"a\": 1,\n  \"b\": 2\n}\n").unwrap();
+        let diff = diff(dest, b"{\n  \"b\": 2\n}\n", false).unwrap();
+        assert!(diff.starts_with(&format!("--- {}\n+++ {}\n@@ -1,4 +1,3 @@\n", dest, dest)));
+        assert!(diff.contains("\n-  \"a\": 1,\n"));
+        assert_eq!(super::diff(dest, &fs::read(dest).unwrap(), false).unwrap(), "");
+    }
+}
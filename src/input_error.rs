@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+/// How much of a line is shown either side of where it couldn't be parsed
+const CONTEXT: usize = 60;
+
+/// Input that couldn't be parsed, with where it went wrong and what the input is there
+#[derive(Debug)]
+pub struct InputError {
+    format: &'static str,
+    file: String,
+    document: usize,
+    line: usize,
+    column: usize,
+    offset: Option<u64>,
+    message: String,
+    /// The part of the line around the error, and how many characters into it the error is
+    snippet: Option<(String, usize)>,
+}
+
+impl InputError {
+    fn new(format: &'static str, file: &str, document: usize, e: &dyn fmt::Display, line: usize, column: usize) -> Self {
+        // The message is shown without the location serde puts in it
+        let message = e.to_string().replacen(&format!(" at line {} column {}", line, column), "", 1);
+        InputError {
+            format,
+            file: file.to_string(),
+            document,
+            line,
+            column,
+            offset: None,
+            message,
+            snippet: None,
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: invalid {} in document {}", self.file, self.line, self.column, self.format, self.document)?;
+        if let Some(offset) = self.offset {
+            write!(f, " (byte {})", offset)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some((text, caret)) = &self.snippet {
+            let number = self.line.to_string();
+            write!(f, "\n  {} | {}\n  {} | {}^", number, text, " ".repeat(number.len()), " ".repeat(*caret))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// The part of `line` around byte `at`, made printable, and how many characters into it `at` is. `cut` says
+/// `line` is only the end of the line.
+fn snippet(line: &[u8], at: usize, cut: bool) -> (String, usize) {
+    let at = at.min(line.len());
+    let start = at.saturating_sub(CONTEXT);
+    let end = line.len().min(at + CONTEXT);
+    let printable = |bytes: &[u8]| String::from_utf8_lossy(bytes).replace(['\t', '\r'], " ");
+    let before = printable(&line[start..at]);
+    let ellipsis = if start > 0 || cut { "…" } else { "" };
+    let caret = ellipsis.chars().count() + before.chars().count();
+    let after = if end < line.len() { "…" } else { "" };
+    (format!("{}{}{}{}", ellipsis, before, printable(&line[at..end]), after), caret)
+}
+
+/// Where a YAML parse error in the `document`th document of `text` is, if `e` is one
+pub fn yaml(file: &str, text: &[u8], document: usize, e: anyhow::Error) -> anyhow::Error {
+    match yaml_at(file, text, 0, document, &e) {
+        Some(error) => error.into(),
+        None => e,
+    }
+}
+
+/// Like `yaml`, for a document parsed on its own from `text` starting `start` bytes in
+pub fn yaml_at(file: &str, text: &[u8], start: usize, document: usize, e: &anyhow::Error) -> Option<InputError> {
+    let location = e.downcast_ref::<serde_yaml::Error>().and_then(serde_yaml::Error::location)?;
+    let mut error = InputError::new("YAML", file, document, e, location.line(), location.column());
+    let index = (start + location.index()).min(text.len());
+    let line_start = text[..index].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let line_end = text[index..].iter().position(|&b| b == b'\n').map_or(text.len(), |i| index + i);
+    error.line = text[..line_start].iter().filter(|&&b| b == b'\n').count() + 1;
+    // Any other locations in the message are in the document too
+    let first_line = text[..start.min(text.len())].iter().filter(|&&b| b == b'\n').count();
+    if first_line > 0 {
+        error.message = shift_lines(&error.message, first_line);
+    }
+    error.offset = Some(index as u64);
+    error.snippet = Some(snippet(&text[line_start..line_end], index - line_start, false));
+    Some(error)
+}
+
+/// `message` with the line numbers in the locations in it moved `by` lines on
+fn shift_lines(message: &str, by: usize) -> String {
+    let mut parts = message.split(" at line ");
+    let mut shifted = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+        match part[..digits].parse::<usize>() {
+            Ok(line) => shifted.push_str(&format!(" at line {}{}", line + by, &part[digits..])),
+            Err(_) => shifted.push_str(&format!(" at line {}", part)),
+        }
+    }
+    shifted
+}
+
+/// A JSON parse error in a document that's the whole of the `line`th line of the input, which is `text` and
+/// starts `start` bytes in
+pub fn json_line(file: &str, document: usize, line: usize, start: u64, text: &[u8], e: serde_json::Error) -> InputError {
+    let mut error = InputError::new("JSON", file, document, &e, e.line(), e.column());
+    let at = e.column().saturating_sub(1);
+    error.line = line;
+    error.offset = Some(start + at as u64);
+    error.snippet = Some(snippet(text, at, false));
+    error
+}
+
+/// The input, read through a buffer that's kept, along with the one before it, to show where a JSON parse error
+/// is. Clones share the buffer, so one can be given to a deserializer and another kept to explain its errors.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Rc<str>,
+    state: Rc<RefCell<State>>,
+}
+
+const CHUNK: usize = 8192;
+
+struct State {
+    input: Box<dyn Read>,
+    /// The chunk of input being read from, how much of it has been read, and the chunk before it
+    chunk: Vec<u8>,
+    pos: usize,
+    previous: Vec<u8>,
+    /// Where the chunk starts in the input, which line that is, and where that line and the one before it start
+    chunk_start: u64,
+    line: usize,
+    line_start: u64,
+    previous_start: u64,
+}
+
+impl State {
+    /// The line `end` bytes into the chunk is on, where it starts, and where the line before it starts
+    fn lines(&self, end: usize) -> (usize, u64, u64) {
+        let (mut line, mut start, mut previous) = (self.line, self.line_start, self.previous_start);
+        for (i, _) in self.chunk[..end].iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            line += 1;
+            previous = start;
+            start = self.chunk_start + i as u64 + 1;
+        }
+        (line, start, previous)
+    }
+
+    /// Move on to the next chunk of input, the whole of this one having been read
+    fn refill(&mut self) -> io::Result<()> {
+        // Reading again at the end of the input keeps the last chunk there was
+        if !self.chunk.is_empty() {
+            (self.line, self.line_start, self.previous_start) = self.lines(self.chunk.len());
+            self.chunk_start += self.chunk.len() as u64;
+            std::mem::swap(&mut self.chunk, &mut self.previous);
+        }
+        self.chunk.resize(CHUNK, 0);
+        self.pos = 0;
+        match self.input.read(&mut self.chunk) {
+            Ok(n) => self.chunk.truncate(n),
+            Err(e) => {
+                self.chunk.clear();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The byte at `offset` in the input, if it's in the chunks kept
+    fn byte(&self, offset: u64) -> Option<u8> {
+        match offset.checked_sub(self.chunk_start) {
+            Some(i) => self.chunk.get(i as usize).copied(),
+            None => {
+                let i = offset.checked_sub(self.chunk_start - self.previous.len() as u64)?;
+                self.previous.get(i as usize).copied()
+            }
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(input: Box<dyn Read>, file: &str) -> Self {
+        let state = State {
+            input,
+            chunk: Vec::new(),
+            pos: 0,
+            previous: Vec::new(),
+            chunk_start: 0,
+            line: 1,
+            line_start: 0,
+            previous_start: 0,
+        };
+        Recorder { file: file.into(), state: Rc::new(RefCell::new(state)) }
+    }
+
+    /// Where a JSON parse error in the `document`th document is, if `e` is one
+    pub fn json(&self, document: usize, e: anyhow::Error) -> anyhow::Error {
+        let Some((line, column)) = e.downcast_ref::<serde_json::Error>()
+            .filter(|e| !e.is_io() && e.line() > 0)
+            .map(|e| (e.line(), e.column())) else {
+            return e;
+        };
+        let mut error = InputError::new("JSON", &self.file, document, &e, line, column);
+        let mut state = self.state.borrow_mut();
+        // The error is at the end of what's been read, or on the line before if that ended it
+        let read = state.chunk_start + state.pos as u64;
+        let start = match state.lines(state.pos) {
+            (l, start, _) if l == line => start,
+            (l, _, previous) if l == line + 1 => previous,
+            _ => return error.into(),
+        };
+        let at = (start + column.saturating_sub(1) as u64).min(read);
+        error.offset = Some(at);
+        let kept = state.chunk_start - state.previous.len() as u64;
+        let from = start.max(at.saturating_sub(CONTEXT as u64)).max(kept);
+        let mut text: Vec<u8> = (from..at).filter_map(|i| state.byte(i)).collect();
+        // The rest of the line is shown too, reading it if need be, as there's no more parsing to do
+        let mut next = at;
+        while text.len() as u64 <= at - from + CONTEXT as u64 {
+            let mut byte = [0];
+            let b = match state.byte(next) {
+                Some(b) => b,
+                None if matches!(state.input.read(&mut byte), Ok(1)) => byte[0],
+                None => break,
+            };
+            if b == b'\n' {
+                break;
+            }
+            text.push(b);
+            next += 1;
+        }
+        error.snippet = Some(snippet(&text, (at - from) as usize, from > start));
+        error.into()
+    }
+}
+
+impl Read for Recorder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.borrow_mut();
+        if state.pos == state.chunk.len() {
+            state.refill()?;
+        }
+        let n = buf.len().min(state.chunk.len() - state.pos);
+        buf[..n].copy_from_slice(&state.chunk[state.pos..state.pos + n]);
+        state.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    #[test]
+    fn test_json() {
+        let recorder = Recorder::new(Box::new("{\"a\": 1}\n{\"a\": 2 \"b\": 3}\n".as_bytes()), "data.json");
+        let mut docs = serde_json::Deserializer::from_reader(recorder.clone()).into_iter::<Value>();
+        assert!(docs.next().unwrap().is_ok());
+        let e = recorder.json(2, docs.next().unwrap().unwrap_err().into());
+        assert_eq!(e.to_string(), concat!(
+            "data.json:2:9: invalid JSON in document 2 (byte 17): expected `,` or `}`\n",
+            "  2 | {\"a\": 2 \"b\": 3}\n",
+            "    |         ^",
+        ));
+    }
+
+    #[test]
+    fn test_json_cut_off() {
+        let recorder = Recorder::new(Box::new("{\"a\": 1}\n{".as_bytes()), "data.json");
+        let mut docs = serde_json::Deserializer::from_reader(recorder.clone()).into_iter::<Value>();
+        assert!(docs.next().unwrap().is_ok());
+        let e = recorder.json(2, docs.next().unwrap().unwrap_err().into());
+        assert_eq!(e.to_string(), concat!(
+            "data.json:2:1: invalid JSON in document 2 (byte 9): EOF while parsing an object\n",
+            "  2 | {\n",
+            "    | ^",
+        ));
+    }
+
+    #[test]
+    fn test_yaml() {
+        let text = "a: 1\n---\nb: [1, 2\nc: 3\n";
+        let doc = serde_yaml::Deserializer::from_str(text).nth(1).unwrap();
+        let e = yaml("<stdin>", text.as_bytes(), 2, serde_yaml::Value::deserialize(doc).unwrap_err().into());
+        assert_eq!(e.to_string(), concat!(
+            "<stdin>:4:2: invalid YAML in document 2 (byte 19): did not find expected ',' or ']', ",
+            "while parsing a flow sequence at line 3 column 4\n",
+            "  4 | c: 3\n",
+            "    |  ^",
+        ));
+    }
+
+    #[test]
+    fn test_shift_lines() {
+        assert_eq!(shift_lines("bad, while parsing a map at line 2 column 4", 10), "bad, while parsing a map at line 12 column 4");
+        assert_eq!(shift_lines("bad at line x", 10), "bad at line x");
+    }
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(snippet(b"{\"a\":\tx}", 6, false), ("{\"a\": x}".to_string(), 6));
+        let long = [b'a'; 200];
+        let (text, caret) = snippet(&long, 100, false);
+        assert_eq!((text.chars().count(), caret), (2 * CONTEXT + 2, CONTEXT + 1));
+    }
+}
@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::failures::Failures;
+use crate::validate::Format;
+
+pub type Documents<'a> = Box<dyn Iterator<Item=Result<Value>> + 'a>;
+
+/// The documents of several input files in turn. A file that can't be opened or parsed doesn't stop the rest
+/// being read: once they have been, the ones that failed are an error listing them all.
+pub struct Files<'a, F> {
+    files: std::vec::IntoIter<String>,
+    open: F,
+    docs: Option<Documents<'a>>,
+    /// The name of the file being read, for results to say where they came from
+    current: Rc<RefCell<Rc<str>>>,
+    failures: Option<Failures>,
+}
+
+impl<'a, F: FnMut(&str) -> Result<Documents<'a>>> Files<'a, F> {
+    pub fn new(files: Vec<String>, current: Rc<RefCell<Rc<str>>>, open: F) -> Self {
+        Files { files: files.into_iter(), open, docs: None, current, failures: Some(Failures::default()) }
+    }
+}
+
+impl<'a, F: FnMut(&str) -> Result<Documents<'a>>> Iterator for Files<'a, F> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        loop {
+            let failures = self.failures.as_mut()?;
+            if let Some(docs) = &mut self.docs {
+                match docs.next() {
+                    Some(Ok(v)) => return Some(Ok(v)),
+                    // The rest of a file is skipped once part of it can't be parsed
+                    Some(Err(e)) => failures.add(&self.current.borrow(), format!("{:#}", e)),
+                    None => failures.ok(),
+                }
+                self.docs = None;
+                continue;
+            }
+            let Some(file) = self.files.next() else {
+                let failures = self.failures.take()?;
+                return (!failures.is_empty()).then(|| Err(failures.into()));
+            };
+            *self.current.borrow_mut() = file.as_str().into();
+            match (self.open)(&file) {
+                Ok(docs) => self.docs = Some(docs),
+                Err(e) => failures.add(&file, format!("{:#}", e)),
+            }
+        }
+    }
+}
+
+/// The records of a JSON, YAML or TOML file, chosen by its extension (or YAML if `yaml`): its documents, with
+/// the elements of any that are arrays in their place. JSON is read as it's needed, so it can be any size.
+pub fn records(path: &str, yaml: bool) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+    let format = if yaml { Format::Yaml } else { Format::of(path) };
+    let docs: Box<dyn Iterator<Item=Result<Value>>> = match format {
+        Format::Json => Box::new(serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter::<Value>()
+            .map(|doc| doc.map_err(anyhow::Error::from))),
+        Format::Yaml => {
+            let mut text = String::new();
+            BufReader::new(file).read_to_string(&mut text)?;
+            let docs: Vec<Result<Value>> = serde_yaml::Deserializer::from_str(&text)
+                .map(|doc| Value::deserialize(doc).map_err(anyhow::Error::from))
+                .collect();
+            Box::new(docs.into_iter())
+        }
+        Format::Toml => {
+            let mut text = String::new();
+            BufReader::new(file).read_to_string(&mut text)?;
+            Box::new(std::iter::once(toml::from_str(&text).map_err(anyhow::Error::from)))
+        }
+    };
+    let path = path.to_string();
+    Ok(Box::new(docs.flat_map(move |doc| -> Box<dyn Iterator<Item=Result<Value>>> {
+        match doc {
+            Ok(Value::Array(arr)) => Box::new(arr.into_iter().map(Ok)),
+            Ok(doc) => Box::new(std::iter::once(Ok(doc))),
+            Err(e) => Box::new(std::iter::once(Err(anyhow!("Failed to parse {}: {}", path, e)))),
+        }
+    })))
+}
+
+/// Writes through to `inner`, starting every line with `prefix`, for --with-filename
+pub struct Prefixed<'a> {
+    inner: &'a mut dyn Write,
+    prefix: &'a [u8],
+    line_start: bool,
+}
+
+impl<'a> Prefixed<'a> {
+    pub fn new(inner: &'a mut dyn Write, prefix: &'a [u8]) -> Self {
+        Prefixed { inner, prefix, line_start: true }
+    }
+}
+
+impl Write for Prefixed<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                self.inner.write_all(self.prefix)?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    #[test]
+    fn test_files() {
+        let current = Rc::new(RefCell::new(Rc::from("")));
+        let seen = current.clone();
+        let files = vec!["a".to_string(), "missing".to_string(), "b".to_string()];
+        let mut docs = Files::new(files, current, |file| -> Result<Documents> {
+            match file {
+                "a" => Ok(Box::new([Ok(json!(1)), Err(anyhow!("bad")), Ok(json!(2))].into_iter())),
+                "b" => Ok(Box::new([Ok(json!(3))].into_iter())),
+                _ => Err(anyhow!("Failed to open {}", file)),
+            }
+        });
+        assert_eq!(docs.next().unwrap().unwrap(), json!(1));
+        assert_eq!(&**seen.borrow(), "a");
+        assert_eq!(docs.next().unwrap().unwrap(), json!(3));
+        assert_eq!(&**seen.borrow(), "b");
+        let err = docs.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "2 of 3 files failed:\n  a        bad\n  missing  Failed to open missing");
+        assert!(docs.next().is_none());
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let mut out = Vec::new();
+        let mut w = Prefixed::new(&mut out, b"a.json:");
+        w.write_all(b"{\n  \"a\"").unwrap();
+        w.write_all(b": 1\n}\n").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a.json:{\na.json:  \"a\": 1\na.json:}\n");
+    }
+}
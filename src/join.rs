@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use jq::{apply_stream_ref, canonical::canonical, evaluate_command, Options, StreamCommand};
+
+/// Which records of the first input `join` prints
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum How {
+    /// Only the records with a match, once for each match
+    Inner,
+    /// Every record, merged with its matches if it has any and as it is if it doesn't
+    Left,
+}
+
+/// The records of the second input of a join, by the canonical form of their key, so the first can be streamed
+/// past them
+pub struct Join {
+    left_key: Vec<StreamCommand>,
+    right: HashMap<String, Vec<Map<String, Value>>>,
+    how: How,
+}
+
+/// The canonical form of `record`'s key, or None if it hasn't got one
+fn key(record: &Value, commands: &[StreamCommand]) -> Result<Option<String>> {
+    let key = apply_stream_ref(record, commands, Options::default()).next().transpose()?;
+    Ok(key.filter(|key| !key.is_null()).map(|key| canonical(&key)))
+}
+
+fn object(record: Value) -> Result<Map<String, Value>> {
+    match record {
+        Value::Object(o) => Ok(o),
+        other => Err(anyhow!("Records to join must be objects, found {}", other)),
+    }
+}
+
+impl Join {
+    /// Index the records of `right` by the key `on`, which is `left_key=right_key` if the inputs name it
+    /// differently. Records of `right` without the key can't match anything, and are left out.
+    pub fn new(on: &str, how: How, right: impl Iterator<Item=Result<Value>>) -> Result<Self> {
+        let (left_key, right_key) = on.split_once('=').unwrap_or((on, on));
+        let left_key = evaluate_command(left_key)?.0;
+        let right_key = evaluate_command(right_key)?.0;
+        let mut index: HashMap<String, Vec<Map<String, Value>>> = HashMap::new();
+        for record in right {
+            let record = record?;
+            if let Some(key) = key(&record, &right_key)? {
+                index.entry(key).or_default().push(object(record)?);
+            }
+        }
+        Ok(Join { left_key, right: index, how })
+    }
+
+    /// The records `left` is joined into: it merged with each record of the second input with the same key. The
+    /// fields of `left` win where both have one, so the second input only adds to it.
+    pub fn join(&self, left: Value) -> Result<Vec<Value>> {
+        let matches = match key(&left, &self.left_key)? {
+            Some(key) => self.right.get(&key).map_or(&[][..], Vec::as_slice),
+            None => &[],
+        };
+        let left = object(left)?;
+        if matches.is_empty() {
+            return Ok(match self.how {
+                How::Inner => vec![],
+                How::Left => vec![Value::Object(left)],
+            });
+        }
+        Ok(matches.iter().map(|right| {
+            let mut merged = left.clone();
+            for (k, v) in right {
+                merged.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+            Value::Object(merged)
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_join() {
+        let right = vec![
+            json!({"id": 1, "name": "a"}),
+            json!({"id": 2, "name": "b"}),
+            json!({"id": 2, "name": "c"}),
+            json!({"name": "no id"}),
+        ];
+        let join = Join::new("user=id", How::Inner, right.clone().into_iter().map(Ok)).unwrap();
+        assert_eq!(join.join(json!({"user": 1.0, "total": 5})).unwrap(), vec![json!({"user": 1.0, "total": 5, "id": 1, "name": "a"})]);
+        assert_eq!(join.join(json!({"user": 2})).unwrap().len(), 2);
+        assert!(join.join(json!({"user": 3})).unwrap().is_empty());
+        assert!(join.join(json!({"total": 1})).unwrap().is_empty());
+        assert!(join.join(json!([1])).is_err());
+
+        let join = Join::new("id", How::Left, right.into_iter().map(Ok)).unwrap();
+        assert_eq!(join.join(json!({"id": 3})).unwrap(), vec![json!({"id": 3})]);
+        assert_eq!(join.join(json!({"id": 1, "name": "x"})).unwrap(), vec![json!({"id": 1, "name": "x"})]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+
+use anyhow::Result;
+use colored_json::{ColorMode, ColoredFormatter};
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use serde_json::Value;
+
+use crate::PrintOptions;
+
+/// Wraps a formatter to escape every non-ASCII character in strings as `\uXXXX`, using surrogate pairs
+/// outside the basic multilingual plane.
+pub struct AsciiFormatter<F>(pub F);
+
+macro_rules! delegate {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $name<W: ?Sized + Write>(&mut self, writer: &mut W $(, $arg: $ty)*) -> io::Result<()> {
+                self.0.$name(writer $(, $arg)*)
+            }
+        )*
+    };
+}
+
+impl<F: Formatter> Formatter for AsciiFormatter<F> {
+    delegate! {
+        begin_array();
+        end_array();
+        begin_array_value(first: bool);
+        end_array_value();
+        begin_object();
+        end_object();
+        begin_object_key(first: bool);
+        end_object_key();
+        begin_object_value();
+        end_object_value();
+    }
+
+    fn write_string_fragment<W: ?Sized + Write>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()> {
+        write_ascii(writer, fragment)
+    }
+}
+
+/// Write `s`, escaping non-ASCII characters as JSON `\uXXXX` escapes
+pub fn write_ascii<W: ?Sized + Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if c.is_ascii() {
+            continue;
+        }
+        writer.write_all(&s.as_bytes()[start..i])?;
+        let mut buf = [0; 2];
+        for unit in c.encode_utf16(&mut buf) {
+            write!(writer, "\\u{:04x}", unit)?;
+        }
+        start = i + c.len_utf8();
+    }
+    writer.write_all(&s.as_bytes()[start..])
+}
+
+fn serialize<W: Write, F: Formatter>(w: &mut W, obj: &Value, f: F, opts: &PrintOptions, color: ColorMode) -> Result<()> {
+    if opts.ascii {
+        ColoredFormatter::with_styler(AsciiFormatter(f), opts.styler).write_colored_json(obj, w, color)?;
+    } else {
+        ColoredFormatter::with_styler(f, opts.styler).write_colored_json(obj, w, color)?;
+    }
+    Ok(())
+}
+
+/// Write `obj` as JSON. Pretty output is indented according to `opts`, and falls back to a single line
+/// when the indent is zero.
+pub fn write_json<W: Write>(w: &mut W, obj: &Value, opts: &PrintOptions, pretty: bool, color: ColorMode) -> Result<()> {
+    let indent = opts.json_indent();
+    if pretty && !indent.is_empty() {
+        serialize(w, obj, PrettyFormatter::with_indent(&indent), opts, color)
+    } else {
+        serialize(w, obj, CompactFormatter, opts, color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ascii() {
+        let opts = PrintOptions { ascii: true, ..PrintOptions::default() };
+        let mut out = Vec::new();
+        write_json(&mut out, &json!({"é": "a😀b"}), &opts, false, ColorMode::Off).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"{"\u00e9":"a\ud83d\ude00b"}"#);
+    }
+}
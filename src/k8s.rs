@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::base64;
+
+/// The Secret manifests in `value`: the value itself, or the items of a List
+fn secrets(value: &mut Value) -> Vec<&mut Value> {
+    match value.get("kind").and_then(Value::as_str) {
+        Some("Secret") => vec![value],
+        Some("List") | Some("SecretList") => match value.get_mut("items") {
+            Some(Value::Array(items)) => items.iter_mut()
+                .filter(|item| item.get("kind").and_then(Value::as_str).is_none_or(|kind| kind == "Secret"))
+                .collect(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Base64-decode every value under `.data` of the Kubernetes Secrets in `value`. Values that aren't UTF-8 text
+/// are an error, since they couldn't be encoded back unchanged.
+pub fn decode_secrets(value: &mut Value) -> Result<()> {
+    for secret in secrets(value) {
+        if let Some(Value::Object(data)) = secret.get_mut("data") {
+            for (key, v) in data.iter_mut() {
+                let Value::String(s) = v else { continue };
+                let text = String::from_utf8(base64::decode(s)?)
+                    .map_err(|_| anyhow!("Secret key {} is binary, not text", key))?;
+                *v = Value::String(text);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Base64-encode every value under `.data` of the Kubernetes Secrets in `value`, undoing `decode_secrets`
+pub fn encode_secrets(value: &mut Value) {
+    for secret in secrets(value) {
+        if let Some(Value::Object(data)) = secret.get_mut("data") {
+            for v in data.values_mut() {
+                if let Value::String(s) = v {
+                    *v = Value::String(base64::encode(s.as_bytes()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_secrets() {
+        let original = json!({"kind": "Secret", "metadata": {"name": "db"}, "data": {"password": "aHVudGVyMg=="}});
+        let mut secret = original.clone();
+        decode_secrets(&mut secret).unwrap();
+        assert_eq!(secret["data"]["password"], "hunter2");
+        encode_secrets(&mut secret);
+        assert_eq!(secret, original);
+
+        let mut list = json!({"kind": "List", "items": [original, {"kind": "ConfigMap", "data": {"a": "YQ=="}}]});
+        decode_secrets(&mut list).unwrap();
+        assert_eq!(list["items"][0]["data"]["password"], "hunter2");
+        assert_eq!(list["items"][1]["data"]["a"], "YQ==");
+
+        let mut binary = json!({"kind": "Secret", "data": {"key": "/w=="}});
+        assert!(decode_secrets(&mut binary).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Result};
+use jmespath::Expression;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use crate::pointer;
+
+/// A query in a standard language, run on each input before the query on the command line
+pub enum Query {
+    /// RFC 9535 JSONPath, e.g. `$.items[?@.price < 10].name`
+    JsonPath(JsonPath),
+    /// RFC 6901 JSON Pointer, e.g. `/items/0/name`
+    Pointer(Vec<String>),
+    /// JMESPath, as used by the AWS CLI's --query, e.g. `Reservations[].Instances[].InstanceId`
+    JmesPath(Expression<'static>),
+}
+
+impl Query {
+    pub fn json_path(expr: &str) -> Result<Self> {
+        JsonPath::parse(expr)
+            .map(Query::JsonPath)
+            .map_err(|e| anyhow!("Invalid JSONPath {}: {}", expr, e))
+    }
+
+    pub fn pointer(expr: &str) -> Result<Self> {
+        pointer::parse(expr).map(Query::Pointer)
+    }
+
+    pub fn jmespath(expr: &str) -> Result<Self> {
+        jmespath::compile(expr)
+            .map(Query::JmesPath)
+            .map_err(|e| anyhow!("Invalid JMESPath {}: {}", expr, e))
+    }
+
+    /// The values the query selects from `value`, in document order
+    pub fn eval(&self, value: &Value) -> Result<Vec<Value>> {
+        match self {
+            Query::JsonPath(path) => Ok(path.query(value).all().into_iter().cloned().collect()),
+            Query::Pointer(tokens) => Ok(pointer::get(value, tokens).cloned().into_iter().collect()),
+            // JMESPath always has exactly one result, which is null when nothing matches
+            Query::JmesPath(expr) => {
+                let result = expr.search(value).map_err(|e| anyhow!("JMESPath failed: {}", e))?;
+                Ok(vec![serde_json::to_value(&*result)?])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_path() {
+        let doc = json!({"items": [{"name": "a", "price": 5}, {"name": "b", "price": 20}]});
+        let query = Query::json_path("$.items[?@.price < 10].name").unwrap();
+        assert_eq!(query.eval(&doc).unwrap(), vec![json!("a")]);
+        assert_eq!(Query::json_path("$..price").unwrap().eval(&doc).unwrap(), vec![json!(5), json!(20)]);
+        assert!(Query::json_path("items").is_err());
+    }
+
+    #[test]
+    fn test_jmespath() {
+        let doc = json!({"Reservations": [{"Instances": [{"InstanceId": "i-1"}, {"InstanceId": "i-2"}]}]});
+        let query = Query::jmespath("Reservations[].Instances[].InstanceId").unwrap();
+        assert_eq!(query.eval(&doc).unwrap(), vec![json!(["i-1", "i-2"])]);
+        assert_eq!(Query::jmespath("missing").unwrap().eval(&doc).unwrap(), vec![Value::Null]);
+        assert!(Query::jmespath("a[").is_err());
+    }
+
+    #[test]
+    fn test_pointer() {
+        let doc = json!({"items": [{"name": "a"}]});
+        assert_eq!(Query::pointer("/items/0/name").unwrap().eval(&doc).unwrap(), vec![json!("a")]);
+        assert!(Query::pointer("/items/1").unwrap().eval(&doc).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,812 @@
+//! The query language of the jq command line tool, for embedding in other programs. A query like
+//! `items[status=active].name` is parsed into stream commands, which select and transform values, and
+//! optionally a print command that says how the command line tool formats the results.
+//!
+//! ```
+//! let query = jq::parse_query("items[].name")?;
+//! let doc = serde_json::json!({"items": [{"name": "a"}, {"name": "b"}]});
+//! let names = jq::evaluate(doc, &query).collect::<jq::Result<Vec<_>>>()?;
+//! assert_eq!(names, ["a", "b"]);
+//! # Ok::<(), jq::Error>(())
+//! ```
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter::empty;
+use std::ops::Range;
+
+use serde_json::{Map, Value};
+
+pub mod canonical;
+pub mod hash;
+pub mod parse;
+
+/// What can go wrong parsing or running a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The query isn't valid. `span` is the byte range of the part of `query` at fault.
+    Parse { message: String, query: String, span: Range<usize> },
+    /// A command was applied to a value it can't handle, like a key to an array
+    Type { command: String, expected: &'static str, found: Value },
+    /// An index past the end of an array
+    Index { index: i64, len: usize },
+    /// A key that isn't in the object, in strict mode
+    Missing { key: String, found: Value },
+    /// A `lookup` in a table that hasn't been bound
+    Table { name: String },
+    /// A command that works on the whole stream of results, like `sort_by`, applied to a single value
+    Stream { command: String },
+}
+
+/// A short description of a value for error messages: its type, and the value itself if it's short
+fn describe(value: &Value) -> String {
+    let kind = match value {
+        Value::Null => return "null".to_string(),
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    };
+    let json = value.to_string();
+    if json.chars().count() <= 40 { format!("{} {}", kind, json) } else { kind.to_string() }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { message, query, span } => {
+                let column = query[..span.start].chars().count();
+                let width = query[span.clone()].chars().count().max(1);
+                write!(f, "Invalid query at byte {}: {}\n  {}\n  {}{}", span.start, message, query, " ".repeat(column), "^".repeat(width))
+            }
+            Error::Type { command, expected, found } => {
+                write!(f, "{}: expected {}, found {}", command, expected, describe(found))
+            }
+            Error::Index { index, len } => write!(f, "Index {} is out of bounds for an array of length {}", index, len),
+            Error::Missing { key, found } => write!(f, "key {}: not found in {}", key, describe(found)),
+            Error::Table { name } => write!(f, "Unknown table {}, bind it with --slurpfile {} FILE", name, name),
+            Error::Stream { command } => write!(f, "{} works on the whole stream of results, not a single value", command),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A parse error for the bytes `span` of `query`
+pub(crate) fn parse_error(query: &str, span: Range<usize>, message: String) -> Error {
+    Error::Parse { message, query: query.to_string(), span }
+}
+
+/// A parsed query
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    pub stream: Vec<StreamCommand>,
+    pub print: PrintCommand,
+}
+
+/// Parse a query. Arguments given separately on the command line are joined with spaces.
+pub fn parse_query(s: &str) -> Result<Query> {
+    let (stream, print) = evaluate_command(s)?;
+    Ok(Query { stream, print })
+}
+
+/// The values `query` produces from `value`, with the default options. The print command isn't applied.
+pub fn evaluate(value: Value, query: &Query) -> impl Iterator<Item=Result<Value>> + '_ {
+    apply_stream(value, &query.stream, Options::default())
+}
+
+/// How stream commands treat values that don't have the shape the query expects
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Mode {
+    /// A missing key or an index out of bounds is null, and a key or index of a value of another type produces
+    /// nothing, so a query over records of different shapes skips the ones it doesn't apply to
+    #[default]
+    Lenient,
+    /// A missing key or an index out of bounds, or either of them applied to the wrong type of value, is an
+    /// error
+    Strict,
+}
+
+/// How stream commands are evaluated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    /// Map a key applied to an array over its elements, so `users.name` is `users[].name`
+    pub map_keys: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { mode: Mode::Lenient, map_keys: true }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StreamCommand {
+    Key(String),
+    /// An index into an array, counting from the end if it's negative
+    Index(i64),
+    Range(Option<i64>, Option<i64>),
+    Filter(String),
+    Put(String, String),
+    Delete(String),
+    /// Drop values whose key, or the whole value if no key is given, was seen before, in this document or an
+    /// earlier one. Values without the key are kept.
+    Dedupe(Option<Vec<StreamCommand>>, Seen),
+    /// Look the value of `field` up in `table`, by the `key` of its records, and set `target` to the record
+    /// found. `target` is `field` itself unless the query names another. Values with no match are left alone.
+    Lookup { target: String, field: String, table: String, key: Vec<StreamCommand>, index: Table },
+    /// Sort every value that reaches it, across documents, by its key, or by the whole value if no key is given
+    SortBy(Option<Vec<StreamCommand>>),
+    /// The `n` values that reach it with the largest keys, largest first, or with the smallest, smallest first
+    Top { n: usize, key: Option<Vec<StreamCommand>>, largest: bool },
+    /// The values that reach it in arrays of this many, in order, the last holding what's left
+    Chunk(usize),
+}
+
+impl StreamCommand {
+    /// Whether the command works on every value that reaches it at once, across documents, rather than on each
+    /// in turn. Such commands can't be applied to a single value: a program running a query over a stream
+    /// of documents splits it at them.
+    pub fn is_whole_stream(&self) -> bool {
+        matches!(self, StreamCommand::SortBy(_) | StreamCommand::Top { .. } | StreamCommand::Chunk(_))
+    }
+}
+
+/// The keys `dedupe` has let through, by the SHA-256 of their canonical form, kept for as long as the command is
+/// so it drops duplicates across documents
+#[derive(Default)]
+pub struct Seen(RefCell<HashSet<Vec<u8>>>);
+
+impl Seen {
+    /// Whether `key` is new, remembering it if it is
+    fn insert(&self, key: &Value) -> bool {
+        self.0.borrow_mut().insert(hash::Algorithm::Sha256.digest(canonical::canonical(key).as_bytes()))
+    }
+}
+
+impl fmt::Debug for Seen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Seen({})", self.0.borrow().len())
+    }
+}
+
+/// Commands are the same whatever they've seen
+impl PartialEq for Seen {
+    fn eq(&self, _: &Seen) -> bool {
+        true
+    }
+}
+
+/// The records of a table `lookup` looks values up in, by the canonical form of their key, once it's bound
+#[derive(Default)]
+pub struct Table(OnceCell<HashMap<String, Value>>);
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.get() {
+            Some(index) => write!(f, "Table({})", index.len()),
+            None => write!(f, "Table(unbound)"),
+        }
+    }
+}
+
+/// Commands are the same whatever they're bound to
+impl PartialEq for Table {
+    fn eq(&self, _: &Table) -> bool {
+        true
+    }
+}
+
+/// Bind the tables the `lookup` commands of `stream` look values up in, from the records of each by name. Where
+/// records share a key, the first is found.
+pub fn bind_tables(stream: &[StreamCommand], tables: &HashMap<String, Vec<Value>>) -> Result<()> {
+    for command in stream {
+        let StreamCommand::Lookup { table, key, index, .. } = command else {
+            continue;
+        };
+        let records = tables.get(table).ok_or_else(|| Error::Table { name: table.clone() })?;
+        let mut by_key = HashMap::new();
+        for record in records {
+            if let Some(k) = apply_stream_ref(record, key, Options::default()).next().transpose()? {
+                if !k.is_null() {
+                    by_key.entry(canonical::canonical(&k)).or_insert_with(|| record.clone());
+                }
+            }
+        }
+        // A command bound already keeps its table
+        let _ = index.0.set(by_key);
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PrintCommand {
+    Yaml,
+    Toml,
+    Xml(String, String),
+    Pretty,
+    Json,
+    Compact,
+    Seq,
+    Canonical,
+    Keys,
+    Len,
+    Csv(Vec<(String, String)>, bool),
+    Tsv(Vec<(String, String)>, bool),
+    Markdown(Vec<(String, String)>, bool),
+    Table(Vec<(String, String)>),
+    Shell,
+    Gron,
+    Paths,
+    Tree(Option<usize>),
+    Hash(hash::Algorithm),
+    Template(String),
+}
+
+impl PrintCommand {
+    pub fn turn_off_headers(&mut self) {
+        if let PrintCommand::Csv(_, print_headers)
+            | PrintCommand::Tsv(_, print_headers)
+            | PrintCommand::Markdown(_, print_headers) = self {
+            *print_headers = false;
+        }
+    }
+
+    /// Whether this is tabular output whose columns still have to be derived from the data
+    pub fn needs_headers(&self) -> bool {
+        matches!(self, PrintCommand::Csv(headers, _)
+            | PrintCommand::Tsv(headers, _)
+            | PrintCommand::Markdown(headers, _) if headers.is_empty())
+    }
+
+    /// Derive the columns of tabular output from the union of keys of `values`, in order of first appearance.
+    /// Arrays contribute the keys of each of their elements. Columns that were already set are left alone.
+    pub fn add_headers<'a>(&mut self, values: impl IntoIterator<Item=&'a Value>) {
+        let (PrintCommand::Csv(headers, _)
+            | PrintCommand::Tsv(headers, _)
+            | PrintCommand::Markdown(headers, _)
+            | PrintCommand::Table(headers)) = self else {
+            return;
+        };
+        if !headers.is_empty() {
+            return;
+        }
+        let mut seen = HashSet::new();
+        for value in values {
+            let rows = match value {
+                Value::Array(a) => a.as_slice(),
+                z => std::slice::from_ref(z),
+            };
+            for key in rows.iter().filter_map(Value::as_object).flat_map(|o| o.keys()) {
+                if seen.insert(key) {
+                    headers.push((key.clone(), key.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Parse a query into its stream commands and print command, which is `Pretty` if the query doesn't end in one
+pub fn evaluate_command(s: &str) -> Result<(Vec<StreamCommand>, PrintCommand)> {
+    let ast = parse::parse(s)?;
+    let stream = ast.stream.into_iter().map(|c| c.node).collect();
+    Ok((stream, ast.print.map_or(PrintCommand::Pretty, |p| p.node)))
+}
+
+fn parse_json(s: &str) -> Value {
+    serde_json::from_str(s).unwrap_or(Value::String(s.to_string()))
+}
+
+fn equal(value: &Value, other: &str) -> bool {
+    match value {
+        Value::String(s) => s == other,
+        Value::Number(n) => n.to_string() == other,
+        Value::Bool(b) => b.to_string() == other,
+        Value::Null => other == "null",
+        _ => false,
+    }
+}
+
+/// The order of values for sorting: null, false, true, numbers, strings, arrays, and then objects. Arrays compare
+/// element by element, and objects by their sorted keys and then by their values in that order.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(false) => 1,
+            Value::Bool(true) => 2,
+            Value::Number(_) => 3,
+            Value::String(_) => 4,
+            Value::Array(_) => 5,
+            Value::Object(_) => 6,
+        }
+    }
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.as_f64().unwrap_or(f64::NAN).total_cmp(&b.as_f64().unwrap_or(f64::NAN)),
+        },
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a.iter().zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => {
+            let mut a_keys: Vec<&String> = a.keys().collect();
+            let mut b_keys: Vec<&String> = b.keys().collect();
+            a_keys.sort();
+            b_keys.sort();
+            a_keys.cmp(&b_keys).then_with(|| a_keys.iter()
+                .map(|k| compare(&a[k.as_str()], &b[k.as_str()]))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal))
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// The position of index `i` in an array of length `len`, where negative indices count from the end, or None if
+/// it's out of bounds
+pub fn resolve_index(i: i64, len: usize) -> Option<usize> {
+    let i = if i < 0 { i + len as i64 } else { i };
+    usize::try_from(i).ok().filter(|&i| i < len)
+}
+
+fn normalize(n: i64, arr: &[Value]) -> usize {
+    (if n < 0 {
+        arr.len() as i64 + n
+    } else {
+        n
+    }).max(0) as usize
+}
+
+/// The values `stream_command` produces from `obj`
+pub fn apply_stream(obj: Value, stream_command: &[StreamCommand], options: Options) -> Box<dyn Iterator<Item=Result<Value>> + '_> {
+    Box::new(apply(Cow::Owned(obj), stream_command, options).map(|v| v.map(Cow::into_owned)))
+}
+
+/// Like `apply_stream`, but borrowing `obj`: values selected from it are borrowed too, and only values the
+/// commands change are copied
+pub fn apply_stream_ref<'a: 's, 's>(obj: &'a Value, stream_command: &'s [StreamCommand], options: Options) -> Box<dyn Iterator<Item=Result<Cow<'a, Value>>> + 's> {
+    apply(Cow::Borrowed(obj), stream_command, options)
+}
+
+/// A child of `obj`, borrowed from it when it's borrowed, and moved out of it when it's owned
+fn take<'a>(obj: Cow<'a, Value>, index: impl serde_json::value::Index) -> Option<Cow<'a, Value>> {
+    match obj {
+        Cow::Borrowed(v) => v.get(index).map(Cow::Borrowed),
+        Cow::Owned(mut v) => v.get_mut(index).map(|v| Cow::Owned(v.take())),
+    }
+}
+
+/// The elements of an array, borrowed or moved out of it the same way as `take`
+fn elements<'a>(arr: Cow<'a, Value>) -> Box<dyn Iterator<Item=Cow<'a, Value>> + 'a> {
+    match arr {
+        Cow::Borrowed(Value::Array(arr)) => Box::new(arr.iter().map(Cow::Borrowed)),
+        Cow::Owned(Value::Array(arr)) => Box::new(arr.into_iter().map(Cow::Owned)),
+        _ => Box::new(empty()),
+    }
+}
+
+fn apply<'a: 's, 's>(obj: Cow<'a, Value>, stream_command: &'s [StreamCommand], options: Options) -> Box<dyn Iterator<Item=Result<Cow<'a, Value>>> + 's> {
+    Box::new(Apply { stack: vec![Work::One(obj, stream_command)], options })
+}
+
+/// The values stream commands produce, worked out with a stack of what's left to do rather than by recursion, so
+/// that values nested thousands of levels deep don't overflow the native stack
+struct Apply<'a, 's> {
+    stack: Vec<Work<'a, 's>>,
+    options: Options,
+}
+
+enum Work<'a, 's> {
+    /// A value and the commands left to apply to it
+    One(Cow<'a, Value>, &'s [StreamCommand]),
+    /// Values that each have the same commands left to apply to them
+    Each(Box<dyn Iterator<Item=Cow<'a, Value>> + 's>, &'s [StreamCommand]),
+}
+
+/// What applying commands to a value comes to, short of applying them to the elements of an array
+enum Step<'a, 's> {
+    Done(Result<Cow<'a, Value>>),
+    Nothing,
+    Each(Box<dyn Iterator<Item=Cow<'a, Value>> + 's>, &'s [StreamCommand]),
+}
+
+impl<'a: 's, 's> Iterator for Apply<'a, 's> {
+    type Item = Result<Cow<'a, Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                Work::One(obj, stream_command) => match step(obj, stream_command, self.options) {
+                    Step::Done(v) => return Some(v),
+                    Step::Nothing => {}
+                    Step::Each(values, stream_command) => self.stack.push(Work::Each(values, stream_command)),
+                },
+                Work::Each(mut values, stream_command) => {
+                    if let Some(v) = values.next() {
+                        // The rest of the values come after everything this one produces
+                        self.stack.push(Work::Each(values, stream_command));
+                        self.stack.push(Work::One(v, stream_command));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn step<'a: 's, 's>(mut obj: Cow<'a, Value>, mut stream_command: &'s [StreamCommand], options: Options) -> Step<'a, 's> {
+    let fail = |command: String, expected: &'static str, found: Cow<Value>| -> Step<'a, 's> {
+        Step::Done(Err(Error::Type { command, expected, found: found.into_owned() }))
+    };
+    while !stream_command.is_empty() {
+        let command = &stream_command[0];
+        let current = stream_command;
+        stream_command = &stream_command[1..];
+        match command {
+            StreamCommand::Key(s) => {
+                obj = match (obj.as_ref(), options.mode) {
+                    (Value::Array(_), _) if options.map_keys => {
+                        return Step::Each(elements(obj), current);
+                    }
+                    (Value::Object(o), Mode::Strict) if !o.contains_key(s) => {
+                        return Step::Done(Err(Error::Missing { key: s.clone(), found: obj.into_owned() }));
+                    }
+                    (Value::Object(_), _) => take(obj, s.as_str()).unwrap_or(Cow::Owned(Value::Null)),
+                    (Value::Null, Mode::Lenient) => obj,
+                    (_, Mode::Lenient) => return Step::Nothing,
+                    (_, Mode::Strict) => return fail(format!("key {}", s), "an object", obj),
+                };
+            }
+            StreamCommand::Filter(f) => {
+                // a=5, a=b
+                // a like foo
+                // a > 5
+                // > 5
+                match obj.as_ref() {
+                    Value::Array(_) => {
+                        let Some((key, value)) = f.split_once('=') else {
+                            return Step::Done(Err(parse_error(f, 0..f.len(), format!("Invalid filter {}", f))));
+                        };
+                        let values = elements(obj).filter_map(move |v| take(v, key).filter(|v| equal(v, value)));
+                        return Step::Each(Box::new(values), stream_command);
+                    }
+                    Value::Object(o) => {
+                        let Some((key, value)) = f.split_once('=') else {
+                            return Step::Done(Err(parse_error(f, 0..f.len(), format!("Invalid filter {}", f))));
+                        };
+                        let matches = match o.get(key) {
+                            Some(v) => equal(v, value),
+                            None => value == "null",
+                        };
+                        if !matches {
+                            return Step::Nothing;
+                        }
+                    }
+                    _ => return fail(format!("filter [{}]", f), "an array or object", obj),
+                }
+            }
+            StreamCommand::Put(k, v) => {
+                let mut o = match obj.into_owned() {
+                    Value::Object(o) => o,
+                    Value::Null => Map::new(),
+                    other => return fail(format!("put {}", k), "an object", Cow::Owned(other)),
+                };
+                o.insert(k.clone(), parse_json(v));
+                obj = Cow::Owned(Value::Object(o));
+            }
+            StreamCommand::Delete(d) => {
+                match obj.as_ref() {
+                    // Nothing to delete, so nothing to copy
+                    Value::Object(o) if !o.contains_key(d) => {}
+                    Value::Object(_) => {
+                        if let Value::Object(o) = obj.to_mut() {
+                            o.shift_remove(d);
+                        }
+                    }
+                    _ => return fail(format!("delete {}", d), "an object", obj),
+                }
+            }
+            StreamCommand::Lookup { target, field, table, index, .. } => {
+                let Some(index) = index.0.get() else {
+                    return Step::Done(Err(Error::Table { name: table.clone() }));
+                };
+                let Value::Object(o) = obj.as_ref() else {
+                    return fail(format!("lookup {}", field), "an object", obj);
+                };
+                let found = o.get(field).filter(|v| !v.is_null()).and_then(|v| index.get(&canonical::canonical(v)));
+                if let Some(found) = found.cloned() {
+                    if let Value::Object(o) = obj.to_mut() {
+                        o.insert(target.clone(), found);
+                    }
+                }
+            }
+            StreamCommand::SortBy(_) => return Step::Done(Err(Error::Stream { command: "sort_by".to_string() })),
+            StreamCommand::Chunk(_) => return Step::Done(Err(Error::Stream { command: "chunk".to_string() })),
+            &StreamCommand::Top { largest, .. } => {
+                let command = if largest { "top" } else { "bottom" };
+                return Step::Done(Err(Error::Stream { command: command.to_string() }));
+            }
+            StreamCommand::Dedupe(key, seen) => {
+                let key = match key {
+                    Some(key) => apply(Cow::Borrowed(obj.as_ref()), key, options).next().transpose(),
+                    None => Ok(Some(Cow::Borrowed(obj.as_ref()))),
+                };
+                match key {
+                    Err(e) => return Step::Done(Err(e)),
+                    Ok(Some(key)) if !key.is_null() && !seen.insert(&key) => return Step::Nothing,
+                    Ok(_) => {}
+                }
+            }
+            &StreamCommand::Index(i) => {
+                obj = match (obj.as_ref(), options.mode) {
+                    (Value::Array(arr), mode) => match resolve_index(i, arr.len()) {
+                        Some(i) => take(obj, i).unwrap_or(Cow::Owned(Value::Null)),
+                        None if mode == Mode::Lenient => Cow::Owned(Value::Null),
+                        None => return Step::Done(Err(Error::Index { index: i, len: arr.len() })),
+                    },
+                    (Value::Null, Mode::Lenient) => obj,
+                    (_, Mode::Lenient) => return Step::Nothing,
+                    (_, Mode::Strict) => return fail(format!("index {}", i), "an array", obj),
+                };
+            }
+            &StreamCommand::Range(start, end) => {
+                let Value::Array(arr) = obj.as_ref() else {
+                    let range = |n: Option<i64>| n.map_or(String::new(), |n| n.to_string());
+                    return fail(format!("range [{}..{}]", range(start), range(end)), "an array", obj);
+                };
+                let start = start.map_or(0, |start| normalize(start, arr));
+                let end = end.map(|end| normalize(end, arr));
+                let it = elements(obj).skip(start);
+                let it: Box<dyn Iterator<Item=Cow<'a, Value>>> = match end {
+                    Some(end) => Box::new(it.take(end.saturating_sub(start))),
+                    None => Box::new(it),
+                };
+                return Step::Each(it, stream_command);
+            }
+        }
+    }
+    Step::Done(Ok(obj))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_command() {
+        let (commands, _) = evaluate_command("foo").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("foo".to_string())]);
+
+        let (commands, _) = evaluate_command(".keys").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("keys".to_string())]);
+
+        let (_, print) = evaluate_command("info paths").unwrap();
+        assert_eq!(print, PrintCommand::Paths);
+        let (commands, print) = evaluate_command(".paths").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("paths".to_string())]);
+        assert_eq!(print, PrintCommand::Pretty);
+
+        let (_, print) = evaluate_command("rows table(name, n as count)").unwrap();
+        assert_eq!(print, PrintCommand::Table(vec![
+            ("name".to_string(), "name".to_string()),
+            ("n".to_string(), "count".to_string()),
+        ]));
+        let (commands, print) = evaluate_command(".table").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("table".to_string())]);
+        assert_eq!(print, PrintCommand::Pretty);
+
+        let (commands, print) = evaluate_command("packages[] hash").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("packages".to_string()), StreamCommand::Range(None, None)]);
+        assert_eq!(print, PrintCommand::Hash(hash::Algorithm::Sha256));
+        let (_, print) = evaluate_command("hash(sha512)").unwrap();
+        assert_eq!(print, PrintCommand::Hash(hash::Algorithm::Sha512));
+        let (commands, print) = evaluate_command("packages[] .hash").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("packages".to_string()), StreamCommand::Range(None, None), StreamCommand::Key("hash".to_string())]);
+        assert_eq!(print, PrintCommand::Pretty);
+
+        let (commands, _) = evaluate_command(".a.b.c.").unwrap();
+        assert_eq!(commands, vec![
+            StreamCommand::Key("a".to_string()),
+            StreamCommand::Key("b".to_string()),
+            StreamCommand::Key("c".to_string()),
+        ]);
+
+        let (commands, print) = evaluate_command("foo, keys").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("foo".to_string())]);
+        assert_eq!(print, PrintCommand::Keys);
+
+        let (_, print) = evaluate_command("tree(2)").unwrap();
+        assert_eq!(print, PrintCommand::Tree(Some(2)));
+        let (_, print) = evaluate_command("tree").unwrap();
+        assert_eq!(print, PrintCommand::Tree(None));
+        let (commands, print) = evaluate_command(".tree").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("tree".to_string())]);
+        assert_eq!(print, PrintCommand::Pretty);
+
+        let (_, print) = evaluate_command("tsv(a, b as c)").unwrap();
+        assert_eq!(print, PrintCommand::Tsv(vec![
+            ("a".to_string(), "a".to_string()),
+            ("b".to_string(), "c".to_string()),
+        ], true));
+    }
+
+    #[test]
+    fn test_header_union() {
+        let (_, mut print) = evaluate_command("csv").unwrap();
+        assert!(print.needs_headers());
+        let rows = serde_json::json!([{"a": 1}, {"b": 2, "a": 3}]);
+        print.add_headers([&rows, &serde_json::json!({"c": 4})]);
+        assert_eq!(print, PrintCommand::Csv(vec![
+            ("a".to_string(), "a".to_string()),
+            ("b".to_string(), "b".to_string()),
+            ("c".to_string(), "c".to_string()),
+        ], true));
+        assert!(!print.needs_headers());
+    }
+
+    #[test]
+    fn test_put_on_null() {
+        let (commands, _) = evaluate_command("put name=alice age=30").unwrap();
+        let results: Vec<Value> = apply_stream(Value::Null, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![serde_json::json!({"name": "alice", "age": 30})]);
+    }
+
+    #[test]
+    fn test_eval_command() {
+        let (commands, _) = evaluate_command("[0..5]").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(Some(0), Some(5))]);
+        let (commands, _) = evaluate_command("[..5]").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(None, Some(5))]);
+        let (commands, _) = evaluate_command("[..-5]").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(None, Some(-5))]);
+        let (commands, _) = evaluate_command("[-5..]").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(Some(-5), None)]);
+        let (commands, _) = evaluate_command("..5").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(None, Some(5))]);
+        let (commands, _) = evaluate_command("5..").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(Some(5), None)]);
+        let (commands, _) = evaluate_command("-5..").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Range(Some(-5), None)]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(evaluate_command("put name"), Err(Error::Parse { .. })));
+        let err = evaluate_command("a[1..x]").unwrap_err();
+        assert!(matches!(&err, Error::Parse { span, .. } if *span == (5..6)));
+        assert_eq!(err.to_string(), "Invalid query at byte 5: Unexpected `x`, expected `]`\n  a[1..x]\n       ^");
+        let err = evaluate_command("hash(md4)").unwrap_err();
+        assert!(matches!(err, Error::Parse { span, .. } if span == (5..8)));
+        assert!(matches!(evaluate_command("a[0"), Err(Error::Parse { span, .. }) if span == (1..2)));
+        assert!(matches!(evaluate_command("a]"), Err(Error::Parse { span, .. }) if span == (1..2)));
+
+        let (commands, _) = evaluate_command("a.b").unwrap();
+        let err = apply_stream(serde_json::json!({"a": [1, 2]}), &commands, Options { mode: Mode::Strict, map_keys: false }).next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "key b: expected an object, found an array [1,2]");
+
+        let (commands, _) = evaluate_command("[5]").unwrap();
+        let err = apply_stream(serde_json::json!([1]), &commands, Options { mode: Mode::Strict, map_keys: false }).next().unwrap().unwrap_err();
+        assert_eq!(err, Error::Index { index: 5, len: 1 });
+        let results: Vec<Value> = apply_stream(serde_json::json!([1]), &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![Value::Null]);
+
+        let (commands, _) = evaluate_command("[-1]").unwrap();
+        let results: Vec<Value> = apply_stream(serde_json::json!([1, 2]), &commands, Options { mode: Mode::Strict, map_keys: false }).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![serde_json::json!(2)]);
+    }
+
+    #[test]
+    fn test_mode() {
+        let docs = serde_json::json!([{"a": {"b": 1}}, {"a": "x"}, {"a": null}, {}]);
+        let (commands, _) = evaluate_command("[].a.b").unwrap();
+        let lenient: Vec<Value> = apply_stream(docs.clone(), &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(lenient, vec![serde_json::json!(1), Value::Null, Value::Null]);
+
+        let (commands, _) = evaluate_command("[].b").unwrap();
+        let strict: Vec<Result<Value>> = apply_stream(docs, &commands, Options { mode: Mode::Strict, map_keys: false }).collect();
+        assert!(matches!(&strict[0], Err(Error::Missing { key, .. }) if key == "b"));
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let doc = serde_json::json!({"users": [{"name": "a"}, {"name": "b"}, [{"name": "c"}]]});
+        let (commands, _) = evaluate_command("users.name").unwrap();
+        let mapped: Vec<Value> = apply_stream(doc.clone(), &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(mapped, ["a", "b", "c"]);
+        let options = Options { map_keys: false, ..Options::default() };
+        assert_eq!(apply_stream(doc, &commands, options).count(), 0);
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let (commands, _) = evaluate_command("events[] dedupe(id)").unwrap();
+        let doc = serde_json::json!({"events": [{"id": 1}, {"id": 2}, {"id": 1.0, "retry": true}, {}]});
+        let first: Vec<Value> = apply_stream(doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(first, vec![serde_json::json!({"id": 1}), serde_json::json!({"id": 2}), serde_json::json!({})]);
+        // What the command has seen carries over to the next document
+        let doc = serde_json::json!({"events": [{"id": 2}, {"id": 3}, {}]});
+        let second: Vec<Value> = apply_stream(doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(second, vec![serde_json::json!({"id": 3}), serde_json::json!({})]);
+
+        let (commands, _) = evaluate_command("dedupe").unwrap();
+        let docs = [serde_json::json!({"a": 1, "b": 2}), serde_json::json!({"b": 2, "a": 1}), serde_json::json!({"a": 1})];
+        let kept: Vec<Value> = docs.into_iter().flat_map(|doc| apply_stream(doc, &commands, Options::default())).collect::<Result<_>>().unwrap();
+        assert_eq!(kept, vec![serde_json::json!({"a": 1, "b": 2}), serde_json::json!({"a": 1})]);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let (commands, _) = evaluate_command("orders[] lookup(user=user_id, users, id), lookup(sku, products, sku)").unwrap();
+        let doc = serde_json::json!({"orders": [{"user_id": 1, "sku": "a"}, {"user_id": 3, "sku": "b"}]});
+        assert_eq!(apply_stream(doc.clone(), &commands, Options::default()).next().unwrap(), Err(Error::Table { name: "users".into() }));
+
+        let tables = HashMap::from([
+            ("users".to_string(), vec![serde_json::json!({"id": 1, "name": "ann"}), serde_json::json!({"id": 1, "name": "dup"})]),
+            ("products".to_string(), vec![serde_json::json!({"sku": "a", "price": 5})]),
+        ]);
+        bind_tables(&commands, &tables).unwrap();
+        let results: Vec<Value> = apply_stream(doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![
+            serde_json::json!({"user_id": 1, "sku": {"sku": "a", "price": 5}, "user": {"id": 1, "name": "ann"}}),
+            serde_json::json!({"user_id": 3, "sku": "b"}),
+        ]);
+        assert!(bind_tables(&commands, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut values = vec![
+            serde_json::json!({"a": 1}), serde_json::json!([1, 2]), serde_json::json!("b"), serde_json::json!(2.5),
+            serde_json::json!(-1), serde_json::json!(true), serde_json::json!(false), Value::Null, serde_json::json!([1]),
+            serde_json::json!("a"), serde_json::json!({"a": 0, "b": 0}), serde_json::json!({"a": 0}),
+        ];
+        values.sort_by(compare);
+        assert_eq!(values, vec![
+            Value::Null, serde_json::json!(false), serde_json::json!(true), serde_json::json!(-1), serde_json::json!(2.5),
+            serde_json::json!("a"), serde_json::json!("b"), serde_json::json!([1]), serde_json::json!([1, 2]),
+            serde_json::json!({"a": 0}), serde_json::json!({"a": 1}), serde_json::json!({"a": 0, "b": 0}),
+        ]);
+        let (commands, _) = evaluate_command("items sort_by(ts)").unwrap();
+        assert_eq!(apply_stream(Value::Null, &commands, Options::default()).next().unwrap(), Err(Error::Stream { command: "sort_by".into() }));
+    }
+
+    #[test]
+    fn test_apply_stream_ref() {
+        let doc = serde_json::json!({"items": [{"id": 1, "tags": ["x"]}, {"id": 2}], "n": null});
+        let (commands, _) = evaluate_command("items[1..].id").unwrap();
+        let results: Vec<Cow<Value>> = apply_stream_ref(&doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert!(matches!(results.as_slice(), [Cow::Borrowed(v)] if std::ptr::eq(*v, &doc["items"][1]["id"])));
+
+        let (commands, _) = evaluate_command("items[0], delete missing").unwrap();
+        let results: Vec<Cow<Value>> = apply_stream_ref(&doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert!(matches!(results.as_slice(), [Cow::Borrowed(_)]));
+
+        let (commands, _) = evaluate_command("items[0], put a=1, delete tags").unwrap();
+        let results: Vec<Cow<Value>> = apply_stream_ref(&doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert!(matches!(results.as_slice(), [Cow::Owned(_)]));
+        assert_eq!(results[0].as_ref(), &serde_json::json!({"id": 1, "a": 1}));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let mut doc = serde_json::json!([{"a": 1}, {"a": 2}]);
+        for _ in 0..100_000 {
+            doc = Value::Array(vec![doc]);
+        }
+        let (commands, _) = evaluate_command("a").unwrap();
+        let results: Vec<Cow<Value>> = apply_stream_ref(&doc, &commands, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results.iter().map(Cow::as_ref).collect::<Vec<_>>(), [&serde_json::json!(1), &serde_json::json!(2)]);
+        // Dropping the document would recurse as deeply as it's nested
+        while let Value::Array(mut arr) = doc {
+            doc = arr.pop().unwrap();
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::{self, Read};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Parse a number of bytes for `--max-size`, with an optional `K`, `M` or `G` suffix for powers of 1024
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 30),
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().map_err(|_| format!("Invalid size {}: expected a number of bytes, like 500K or 2G", s))?;
+    n.checked_mul(1 << shift).ok_or_else(|| format!("Invalid size {}: too large", s))
+}
+
+/// A reader that fails once more than `max` bytes have been read from it, for `--max-size`
+pub struct Limited<R> {
+    inner: R,
+    max: u64,
+    read: u64,
+}
+
+impl<R: Read> Limited<R> {
+    pub fn new(inner: R, max: u64) -> Self {
+        Limited { inner, max, read: 0 }
+    }
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reading one byte past the limit tells input that's exactly the limit from input that's over it
+        let allowed = (self.max.saturating_add(1) - self.read).min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..allowed])?;
+        self.read += n as u64;
+        if self.read > self.max {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Input is larger than --max-size of {} bytes", self.max)));
+        }
+        Ok(n)
+    }
+}
+
+/// How deeply arrays and objects are nested in `value`: 0 for a scalar, 1 for an array of scalars
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Array(arr) => 1 + arr.iter().map(depth).max().unwrap_or(0),
+        Value::Object(obj) => 1 + obj.values().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Fail if `value` is nested deeper than `--max-depth`
+pub fn check_depth(value: Value, max: usize) -> Result<Value> {
+    if depth(&value) > max {
+        return Err(anyhow!("Input is nested deeper than --max-depth of {}", max));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("2K"), Ok(2048));
+        assert_eq!(parse_size("1g"), Ok(1 << 30));
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1.5M").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut text = String::new();
+        Limited::new("12345".as_bytes(), 5).read_to_string(&mut text).unwrap();
+        assert_eq!(text, "12345");
+        let err = Limited::new("123456".as_bytes(), 5).read_to_string(&mut String::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Input is larger than --max-size of 5 bytes");
+
+        assert_eq!(depth(&json!(1)), 0);
+        assert_eq!(depth(&json!({"a": [1, {"b": 2}], "c": {}})), 3);
+        assert!(check_depth(json!([[1]]), 2).is_ok());
+        assert!(check_depth(json!([[[1]]]), 2).is_err());
+    }
+}
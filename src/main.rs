@@ -1,40 +1,272 @@
 #![allow(unused)]
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env::args;
 use std::fs::File;
 use std::io;
 use std::io::{stdout, IsTerminal, Read, Write};
 use std::iter::{empty, once};
 use std::ops::Index;
+use std::rc::Rc;
 
-use anyhow::{anyhow, Result};
-use clap::{Args, Parser, Subcommand, ValueEnum};
-use colored_json::ToColoredJson;
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use colored_json::{ColorMode, Output, Styler, ToColoredJson};
 use regex::Regex;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use regex::regex;
+use jq::{apply_stream, apply_stream_ref, evaluate_command, hash, Mode, Options, PrintCommand, StreamCommand};
+use config::ColorChoice;
+use yaml::YamlStyle;
 
+mod alias;
+mod base64;
+mod binary;
+mod clipboard;
+mod complete;
+mod config;
+mod diff;
+mod dupes;
+mod elements;
+mod encoding;
+mod envsubst;
+mod events;
+mod explore;
+mod failures;
+mod fast;
+mod follow;
+mod front_matter;
+mod gron;
+mod html;
+mod in_place;
+mod inputs;
+mod input_error;
+mod join;
+mod json;
+mod k8s;
+mod lang;
+mod limits;
+mod merge;
+mod merge_patch;
+mod parquet_out;
+mod passthrough;
+mod post;
+mod profile;
+mod queries;
+mod patch;
+mod pick;
+mod pointer;
+mod skip_errors;
+mod sort;
+mod split;
+mod sqlite;
+mod stages;
+mod stats;
+mod table;
+mod tee;
+mod trace;
+mod tree;
+mod validate;
+mod xlsx;
+mod xml;
+mod yaml;
+mod yaml_1_1;
+mod yaml_edit;
+
+const EXIT_CODES: &str = "\
+Exit status:
+  0  Success
+  1  The last result was false or null with --exit-status, or another failure
+  2  Invalid command line flags
+  3  The query couldn't be parsed
+  4  No results with --exit-status
+  5  The query failed on the input, e.g. with --strict
+  6  The input couldn't be parsed";
 
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, disable_help_subcommand = true, after_help = EXIT_CODES)]
 struct Cli {
+    #[command(subcommand)]
+    subcommand: Option<Sub>,
+
+    /// The query. Its leading `.` is optional, but a key named like a subcommand, followed by anything the
+    /// subcommand takes, runs the subcommand: `.stats` always selects the key
     command: Vec<String>,
 
+    /// Input files to read in turn, after `--`, whatever they're named and whether or not stdin is a terminal,
+    /// e.g. `jq -H metadata.name -- configs/*`
+    #[clap(last = true, value_name = "FILES", conflicts_with_all = ["null_input", "follow", "paste", "in_place", "front_matter"])]
+    files: Vec<String>,
+
+    /// Read default flags from this TOML file instead of ~/.config/jq/config.toml. Supported keys are
+    /// indent, tab, color (auto/always/never), output (pretty/json/compact/yaml/toml/xml), sort-keys,
+    /// ascii-output, and a [theme] table styling key, string, number, bool, null and brackets
+    #[clap(long, value_name = "FILE")]
+    config: Option<String>,
+
     /// Parse the input as YAML
     #[clap(short, long)]
     yaml: bool,
 
+    /// Read plain `yes`, `no`, `on`, `off`, `y` and `n` in YAML input as booleans, as YAML 1.1 does, with a
+    /// warning for each. Otherwise YAML is read as 1.2, where they're strings, so the country code `NO` isn't false
+    #[clap(long = "yaml-1.1", requires = "yaml", conflicts_with = "yaml_1_2")]
+    yaml_1_1: bool,
+
+    /// Read YAML input as YAML 1.2, which is the default
+    #[clap(long = "yaml-1.2", requires = "yaml")]
+    yaml_1_2: bool,
+
+    /// Keep reading this NDJSON file as it grows, like `tail -f`, printing the results for each new document
+    /// as it arrives
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["in_place", "bulk", "yaml", "ungron", "front_matter"])]
+    follow: Option<String>,
+
+    /// Parse the front matter of a Markdown file, fenced by `---` (YAML) or `+++` (TOML). With --in-place,
+    /// the result is written back as the file's front matter, keeping the body after it
+    #[clap(long)]
+    front_matter: bool,
+
+    /// Parse the input as gron assignment lines (`json.a[0] = 1;`), such as the output of the gron command
+    #[clap(long)]
+    ungron: bool,
+
     /// Output the result as YAML
     #[clap(short = 'Y', long)]
     yaml_output: bool,
 
+    /// Output pretty-printed JSON, or YAML with -Y, as HTML with inline-styled syntax highlighting,
+    /// for pasting into wikis and dashboards
+    #[clap(long)]
+    html_output: bool,
+
+    /// Output the result as TOML. The result must be an object, as TOML has no top-level arrays or scalars
+    #[clap(short = 'T', long)]
+    toml_output: bool,
+
+    /// Output the result as XML
+    #[clap(long)]
+    xml_output: bool,
+
+    /// Name of the root element for XML output
+    #[clap(long, default_value = "root")]
+    xml_root: String,
+
+    /// Name of the element wrapping each array entry for XML output
+    #[clap(long, default_value = "item")]
+    xml_item: String,
+
+    /// Render each result through a template, replacing `{path}` placeholders with the value at that
+    /// path, e.g. `--template '{name} <{email}>'`. Write `{{` and `}}` for literal braces
+    #[clap(long)]
+    template: Option<String>,
+
     /// Output the result as JSON. The default pretty prints the results, unpacks arrays,
     /// and prints unquoted strings
     #[clap(short = 'J', long)]
     json_output: bool,
 
+    /// Output each result as single-line minified JSON. Unlike json-output, multiple results are never
+    /// collected into an array
+    #[clap(short, long)]
+    compact: bool,
+
+    /// Output JSON Lines: every result from every input document as compact JSON on its own line
+    #[clap(long)]
+    jsonl: bool,
+
+    /// Output an RFC 7464 JSON text sequence: each result is prefixed with an ASCII record separator
+    #[clap(long)]
+    seq_output: bool,
+
+    /// Output canonical JSON (RFC 8785): sorted keys, normalized numbers and no whitespace, for signing and
+    /// stable hashing
+    #[clap(long)]
+    canonical: bool,
+
+    /// Sort object keys recursively, for deterministic output. By default keys keep their input order
+    #[clap(short = 'S', long)]
+    sort_keys: bool,
+
+    /// Number of spaces to indent pretty-printed JSON and YAML. 0 prints JSON on a single line
+    #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=7))]
+    indent: u8,
+
+    /// Indent pretty-printed JSON with a tab. YAML forbids tab indentation, so it keeps using --indent
+    #[clap(long)]
+    tab: bool,
+
+    /// Escape every non-ASCII character in JSON output as \uXXXX
+    #[clap(short, long)]
+    ascii_output: bool,
+
+    /// Never colorize output
+    #[clap(short = 'M', long, conflicts_with = "color_output")]
+    monochrome: bool,
+
+    /// Colorize output even when stdout isn't a terminal, e.g. when piping into `less -R`. By default
+    /// output is colored only on a terminal, and never when the NO_COLOR environment variable is set
+    #[clap(short = 'C', long)]
+    color_output: bool,
+
+    /// When to colorize output. `always` is the same as -C, and `never` the same as -M
+    #[clap(long, value_enum, value_name = "WHEN", conflicts_with_all = ["monochrome", "color_output"])]
+    color: Option<ColorChoice>,
+
+    /// Field delimiter for csv output, e.g. ';' for locales that use decimal commas. Accepts `\t` for tab
+    #[clap(long, default_value = ",", value_parser = parse_delimiter)]
+    csv_delimiter: u8,
+
+    /// When csv output quotes fields
+    #[clap(long, value_enum, default_value_t = CsvQuote::Necessary)]
+    csv_quote: CsvQuote,
+
+    /// Line terminator for csv output
+    #[clap(long, value_enum, default_value_t = CsvTerminator::Lf)]
+    csv_terminator: CsvTerminator,
+
+    /// How csv output writes null and missing values
+    #[clap(long, default_value = "null")]
+    csv_null: String,
+
+    /// Layout of YAML collections
+    #[clap(long, value_enum, default_value_t = YamlLayout::Block)]
+    yaml_style: YamlLayout,
+
+    /// Number of spaces to indent YAML, if different from --indent
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..=9))]
+    yaml_indent: Option<u8>,
+
+    /// Indent YAML sequences under their parent key instead of aligning the dashes with the key
+    #[clap(long)]
+    yaml_indent_sequences: bool,
+
+    /// Quote YAML strings that YAML 1.1 readers would take for booleans or numbers, like `no`, `on` or `1_000`
+    #[clap(long)]
+    yaml_quote_ambiguous: bool,
+
+    /// Quote YAML strings with double quotes rather than single quotes
+    #[clap(long)]
+    yaml_double_quotes: bool,
+
+    /// Begin each YAML document with an explicit `---`
+    #[clap(long)]
+    yaml_document_start: bool,
+
+    /// Terminate each result with a NUL byte instead of a newline, so that raw strings containing
+    /// whitespace can be piped safely into `xargs -0`
+    #[clap(long)]
+    raw_output0: bool,
+
+    /// Read the input as strings separated by NUL bytes, each one an input of its own, such as the file names
+    /// printed by `find -print0` or by --raw-output0
+    #[clap(long, conflicts_with_all = ["null_input", "yaml", "stream", "ungron", "fast", "skip_errors", "dupes",
+        "front_matter"])]
+    raw_input0: bool,
+
     /// An alias for json-output
     #[clap(short, long)]
     raw: bool,
@@ -43,483 +275,2046 @@ struct Cli {
     #[clap(short, long)]
     bulk: bool,
 
-    /// When you read data streaming and
+    /// Run the query once with `null` as its input instead of reading any, to build JSON from scratch,
+    /// e.g. `jq -n put name=alice age=30`
+    #[clap(short, long, conflicts_with_all = ["follow", "in_place", "front_matter", "ungron"])]
+    null_input: bool,
+
+    /// Exit with status 1 if the last result is false or null, or 4 if there are no results, so queries can be
+    /// used directly as shell conditions
+    #[clap(short, long)]
+    exit_status: bool,
+
+    /// Print nothing, for when only the exit status matters, e.g. with --exit-status
+    #[clap(long, conflicts_with_all = ["output", "in_place", "copy", "interactive", "pick", "post"])]
+    quiet: bool,
+
+    /// Read the input as a stream of `[path, leaf]` events, each followed by a `[path]` event when the
+    /// array or object containing it ends, like `jq --stream`. JSON input is never held in memory whole,
+    /// so this works on documents larger than memory
+    #[clap(long, conflicts_with_all = ["null_input", "front_matter", "ungron", "in_place"])]
+    stream: bool,
+
+    /// Parse JSON input with simd-json, which is faster on large inputs. Documents can be one to a line, as in
+    /// NDJSON, or spread over many lines. Only the part of each document that the keys and indexes the query
+    /// starts with select is built, which makes plucking a field from large documents faster still. Needs jq
+    /// built with the `simd` feature
+    #[clap(long, conflicts_with_all = ["stream", "yaml"])]
+    fast: bool,
+
+    /// Parse the input as MessagePack, CBOR or BSON: a sequence of documents in the format. Byte strings, which
+    /// JSON has none of, become `{"$bytes": "<base64>"}` objects
+    #[clap(long, value_enum, value_name = "FORMAT",
+        conflicts_with_all = ["yaml", "stream", "fast", "skip_errors", "ungron", "front_matter", "raw_input0", "follow"])]
+    binary: Option<binary::Format>,
+
+    /// What to do with objects in JSON input that have the same key more than once. Without it, the last value
+    /// is kept silently
+    #[clap(long, value_enum, conflicts_with_all = ["stream", "yaml", "fast"])]
+    dupes: Option<dupes::Dupes>,
+
+    /// Decode input that isn't UTF-8: `latin1`, `utf16`, or `lossy` to replace invalid bytes in UTF-8, with a
+    /// warning saying where. A UTF-8 byte order mark is skipped either way
+    #[clap(long, value_enum, conflicts_with = "follow")]
+    encoding: Option<encoding::Encoding>,
+
+    /// Fail on input more than this many bytes long, rather than reading it all, e.g. `--max-size 100M`.
+    /// Takes a K, M or G suffix
+    #[clap(long, value_name = "BYTES", value_parser = limits::parse_size)]
+    max_size: Option<u64>,
+
+    /// Fail on input documents with arrays and objects nested more than this deep
+    #[clap(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Skip input documents that can't be parsed, with a warning for each and a count at the end, rather than
+    /// failing. JSON input is read one document to a line, as NDJSON, and YAML is split at `---`
+    #[clap(long, conflicts_with_all = ["stream", "fast", "dupes", "null_input", "front_matter", "ungron"])]
+    skip_errors: bool,
+
+    /// Start each line of output with the name of the file its result came from, like `grep -H`, for a query
+    /// over many files, e.g. `jq -H configs/*.json metadata.name` or `jq -H metadata.name -- configs/*`
+    #[clap(short = 'H', long, conflicts_with_all = ["keyed", "wrap_array", "join", "slurp", "copy", "post", "interactive",
+        "pick", "sqlite_out", "xlsx_out", "parquet_out", "in_place"])]
+    with_filename: bool,
+
+    /// Wrap each result in an object with the name of the file it came from, `{"file": ..., "value": ...}`
+    #[clap(long, conflicts_with = "slurp")]
+    keyed: bool,
+
+    /// Read the records of a JSON, YAML or TOML file into a table, for `lookup(field, NAME, key)` to look values
+    /// up in, e.g. `--slurpfile users users.ndjson` then `lookup(user=user_id, users, id)`
+    #[clap(long, num_args = 2, value_names = ["NAME", "FILE"])]
+    slurpfile: Vec<String>,
+
+    /// Roughly how much memory `sort_by` holds results in before sorting them and spilling them to temporary
+    /// files, which are merged at the end, e.g. `--sort-memory 1G`. Takes a K, M or G suffix
+    #[clap(long, value_name = "BYTES", value_parser = limits::parse_size, default_value = "256M")]
+    sort_memory: u64,
+
+    /// Read every input document into a single array before running the query, so it can operate across
+    /// documents, e.g. `len` of an NDJSON stream
+    #[clap(short, long, conflicts_with = "follow")]
+    slurp: bool,
+
+    /// Expand `${VAR}` and `${VAR:-default}` placeholders in the input with environment variables before
+    /// parsing it, e.g. to render a templated config file. `$${` is a literal `${`
+    #[clap(long, conflicts_with_all = ["follow", "stream"])]
+    envsubst: bool,
+
+    /// Run a named query against each input document, e.g. `-q jobs=jobs,len`. Can be
+    /// repeated, all in one pass over the input: each document becomes an object of the queries' results,
+    /// which the main query then runs on
+    #[clap(short = 'q', long = "query", value_name = "NAME=QUERY")]
+    queries: Vec<String>,
+
+    /// Base64-decode the values under `.data` of Kubernetes Secret manifests, so they can be read and edited
+    /// as text. With --in-place, they're encoded again when the file is written
+    #[clap(long)]
+    k8s_secrets: bool,
+
+    /// Apply this RFC 6902 JSON Patch (JSON, YAML or TOML) to each input before running the query,
+    /// e.g. `jq --patch fix.json -i config.json`
+    #[clap(long, value_name = "FILE")]
+    patch: Option<String>,
+
+    /// Apply this RFC 7386 JSON Merge Patch (JSON, YAML or TOML) to each input before running the query.
+    /// Keys set to `null` in the patch are removed
+    #[clap(long, value_name = "FILE", conflicts_with = "patch")]
+    merge_patch: Option<String>,
+
+    /// Write the results to this file instead of stdout, formatted by its extension unless an output flag
+    /// or print command says otherwise: .json, .yaml/.yml, .toml, .xml, .csv, .tsv, .md, and .ndjson/.jsonl
+    /// for one compact JSON value per line
+    #[clap(short, long, value_name = "FILE",
+        conflicts_with_all = ["in_place", "copy", "post", "interactive", "pick", "sqlite_out", "parquet_out", "xlsx_out"])]
+    output: Option<String>,
+
+    /// Flush the output after the results of each input document, instead of when the buffer fills, for
+    /// pipelines that watch it as it's written. Always the case with --follow, or when stdout is a terminal
+    #[clap(long)]
+    unbuffered: bool,
+
+    /// Also write the results to this file, e.g. to capture a pipeline while watching it
+    #[clap(long, value_name = "FILE")]
+    tee: Option<String>,
+
+    /// Format of the --tee file. Defaults to YAML for .yaml/.yml, pretty JSON for .json, and otherwise one
+    /// JSON value per line
+    #[clap(long, value_enum, requires = "tee")]
+    tee_format: Option<tee::TeeFormat>,
+
+    /// Print every result, across all input documents, as one array
+    #[clap(long, conflicts_with = "join")]
+    wrap_array: bool,
+
+    /// Print the results on one line separated by SEP, strings raw and anything else as compact JSON,
+    /// e.g. `--join ,`
+    #[clap(long, value_name = "SEP", conflicts_with = "raw_output0")]
+    join: Option<String>,
+
+    /// Stop after this many results, without reading the rest of the input
+    #[clap(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Select from each input with an RFC 9535 JSONPath expression, e.g. `--jsonpath '$.items[*].name'`.
+    /// A query on the command line then runs on each value it selects
+    #[clap(long, value_name = "EXPR")]
+    jsonpath: Option<String>,
+
+    /// Select from each input with an RFC 6901 JSON Pointer, e.g. `--pointer /items/0/name`. A query on the
+    /// command line then runs on the value it selects. The `paths` command lists the pointers of a document
+    #[clap(long, value_name = "POINTER", conflicts_with = "jsonpath")]
+    pointer: Option<String>,
+
+    /// Select from each input with a JMESPath expression, e.g. the --query of an AWS CLI command. A query on
+    /// the command line then runs on its result
+    #[clap(long, value_name = "EXPR", conflicts_with_all = ["jsonpath", "pointer"])]
+    jmespath: Option<String>,
+
+    /// Make a missing key or an out of bounds index an error, as well as a key of something other than an
+    /// object or an index of something other than an array. Otherwise missing keys and indices are null, and
+    /// the rest produce nothing, so records of other shapes are skipped
+    #[clap(long)]
+    strict: bool,
+
+    /// Don't map a key applied to an array over its elements. By default `users.name` is `users[].name`
+    #[clap(long)]
+    no_map_keys: bool,
+
+    /// Log each value going into and coming out of every step of the query to stderr, truncated, to see
+    /// where data gets filtered out
+    #[clap(long)]
+    trace: bool,
+
+    /// Report to stderr the time spent reading and parsing input, in each step of the query, and writing
+    /// output, and the peak memory use
+    #[clap(long, conflicts_with = "trace")]
+    profile: bool,
+
+    /// Print how the query was parsed, the stream commands in order and the final print command, instead of
+    /// running it
+    #[clap(long)]
+    explain: bool,
+
+    /// Browse the result in a full-screen explorer with collapsible nodes and incremental search (`/`).
+    /// Press `y` to copy the selector of the highlighted node
+    #[clap(long)]
+    interactive: bool,
+
+    /// Build a selector interactively: pick keys and indices step by step, narrowing them down by fuzzy search,
+    /// with a preview of what's selected. The finished selector is shown on stderr and run on the input
+    #[clap(long, conflicts_with = "interactive")]
+    pick: bool,
+
+    /// Send each result as the JSON body of a POST request to this URL, and print the response bodies.
+    /// With --wrap-array, all results are sent in one request
+    #[clap(long, value_name = "URL")]
+    post: Option<String>,
+
+    /// A header for --post requests, e.g. `--header 'Authorization: Bearer ...'`. Can be repeated
+    #[clap(long, value_name = "HEADER", requires = "post")]
+    header: Vec<String>,
+
+    /// Write the results as rows of a table in this SQLite database instead of printing them.
+    /// The table is created if needed, with column types inferred from the results
+    #[clap(long)]
+    sqlite_out: Option<String>,
+
+    /// Name of the table written by --sqlite-out
+    #[clap(long, default_value = "data", requires = "sqlite_out")]
+    table: String,
+
+    /// Write the results as rows of a Parquet file instead of printing them
+    #[clap(long)]
+    parquet_out: Option<String>,
+
+    /// Number of leading results used to infer the --parquet-out schema
+    #[clap(long, default_value_t = 1000, requires = "parquet_out")]
+    parquet_sample: usize,
+
+    /// Write each result as a MessagePack, CBOR or BSON document instead of printing it. `{"$bytes": "<base64>"}`
+    /// objects are written as byte strings, and BSON results must be objects
+    #[clap(long, value_enum, value_name = "FORMAT",
+        conflicts_with_all = ["in_place", "copy", "post", "interactive", "pick", "sqlite_out", "parquet_out", "xlsx_out"])]
+    binary_output: Option<binary::Format>,
+
+    /// Write the results to an Excel worksheet with a header row instead of printing them.
+    /// Results that are arrays contribute one row per element
+    #[clap(long)]
+    xlsx_out: Option<String>,
+
+    /// Read the input from the system clipboard
+    #[clap(long, conflicts_with_all = ["null_input", "follow", "in_place"])]
+    paste: bool,
+
+    /// Write each result to a file in --out-dir named after its value of this key, e.g. one file per customer
+    /// with `--split-by customer.id`. Characters that aren't safe in file names are replaced with `_`, and the
+    /// extension follows the output flags: .ndjson with -c, .yaml with -Y, .toml, or .json
+    #[clap(long, value_name = "KEY", requires = "out_dir",
+        conflicts_with_all = ["output", "in_place", "copy", "post", "interactive", "pick", "sqlite_out", "parquet_out",
+            "xlsx_out", "with_filename", "wrap_array", "join"])]
+    split_by: Option<String>,
+
+    /// The directory --split-by writes to, created if need be. Files in it named like a partition are replaced
+    #[clap(long, value_name = "DIR", requires = "split_by")]
+    out_dir: Option<String>,
+
+    /// Put the results on the system clipboard instead of printing them, as JSON, or YAML or TOML with
+    /// the output flags
+    #[clap(long, conflicts_with = "in_place")]
+    copy: bool,
+
+    /// Edit this file in place: read it, run the query, and replace it with the results. The file is
+    /// replaced atomically, so it's never left half-written, and locked while it's edited, so another jq
+    /// editing it at the same time fails rather than losing its changes. With -y, only the parts of the YAML
+    /// that changed are rewritten, keeping the comments and formatting of the rest
     #[clap(short, long)]
     in_place: Option<String>,
+
+    /// Keep a copy of the original file with this suffix when editing in place, e.g. `--backup .bak`
+    #[clap(long, value_name = "SUFFIX", requires = "in_place")]
+    backup: Option<String>,
+
+    /// Print a unified diff of what editing in place would change, without writing anything
+    #[clap(long, visible_alias = "diff", requires = "in_place", conflicts_with = "backup")]
+    dry_run: bool,
+
+    /// When editing in place, wait for another jq editing the file to finish rather than failing
+    #[clap(long, requires = "in_place")]
+    wait: bool,
+
+    /// When editing in place, leave the file as it is and fail if something else changes it before the results
+    /// are written, such as a program that doesn't lock it
+    #[clap(long, requires = "in_place", conflicts_with = "dry_run")]
+    no_clobber: bool,
 }
 
-#[derive(Debug, PartialEq)]
-enum StreamCommand {
-    Key(String),
-    Index(usize),
-    Range(Option<i64>, Option<i64>),
-    Filter(String),
-    Put(String, String),
-    Delete(String),
+impl Cli {
+    /// Whether the documents the query runs on are anything but the JSON documents of the input, each as it's
+    /// read: parsed as something else, or changed by [`Cli::transforms_documents`]
+    fn transforms_input(&self) -> bool {
+        self.null_input || self.front_matter || self.ungron || self.raw_input0 || self.stream || self.yaml || self.fast
+            || self.skip_errors || self.dupes.is_some() || self.binary.is_some() || self.bulk || self.transforms_documents()
+    }
+
+    /// Whether anything but the query looks at the documents of the input once they're parsed: to check, change
+    /// or select from them first, or to gather them together
+    fn transforms_documents(&self) -> bool {
+        self.max_depth.is_some() || self.slurp || self.patch.is_some() || self.merge_patch.is_some() || self.k8s_secrets
+            || !self.queries.is_empty() || self.jsonpath.is_some() || self.pointer.is_some() || self.jmespath.is_some()
+    }
+
+    /// Whether output is flushed after each input document's results, rather than as its buffer fills: when asked
+    /// to, when following a file, or when it's going to a terminal, where someone is watching
+    fn flushes_each_document(&self) -> bool {
+        self.unbuffered || self.follow.is_some() || (self.output.is_none() && stdout().is_terminal())
+    }
+
+    /// Whether the results are anything but printed as they come: sorted, attributed to their file, gathered,
+    /// counted, traced, or sent somewhere other than the output
+    fn transforms_output(&self) -> bool {
+        self.sort_keys || self.with_filename || self.keyed || self.wrap_array || self.join.is_some()
+            || self.limit.is_some() || self.exit_status || self.profile || self.trace
+            || self.in_place.is_some() || self.tee.is_some() || self.copy || self.interactive || self.pick
+            || self.post.is_some() || self.sqlite_out.is_some() || self.xlsx_out.is_some() || self.parquet_out.is_some()
+            || self.split_by.is_some() || self.binary_output.is_some()
+    }
 }
 
-#[derive(Debug, PartialEq)]
-enum PrintCommand {
-    Yaml,
-    Pretty,
-    Json,
-    Keys,
-    Len,
-    Csv(Vec<(String, String)>, bool),
+#[derive(Subcommand)]
+enum Sub {
+    /// Print a shell completion script, e.g. `jq completions zsh > ~/.zfunc/_jq`
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// Print the paths that complete a partially typed path, from the keys of a JSON, YAML or TOML file.
+    /// Used by the shell completions
+    #[command(name = "__complete-keys", hide = true)]
+    CompleteKeys {
+        file: String,
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        partial: String,
+    },
+    /// Deep-merge JSON, YAML or TOML files, later files overriding earlier ones, and print the result,
+    /// e.g. `jq -Y merge base.yaml prod.yaml`
+    Merge {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// How arrays at the same path are combined
+        #[arg(long, value_enum, default_value_t = merge::ArrayStrategy::Replace)]
+        arrays: merge::ArrayStrategy,
+        /// Key identifying array elements for --arrays merge-by-key
+        #[arg(long, default_value = "name")]
+        merge_key: String,
+    },
+    /// Show the paths added, removed or changed between two JSON, YAML or TOML files, ignoring key order
+    /// and formatting. Exits with status 1 if they differ
+    Diff {
+        a: String,
+        b: String,
+        /// Color the output even when stdout isn't a terminal
+        #[arg(long)]
+        color: bool,
+        /// Print an RFC 6902 JSON Patch that transforms the first file into the second instead
+        #[arg(long, conflicts_with = "color")]
+        patch: bool,
+        /// Print an RFC 7386 JSON Merge Patch that transforms the first file into the second instead
+        #[arg(long, conflicts_with_all = ["color", "patch"])]
+        merge_patch: bool,
+    },
+    /// Join two streams of records on a key: each record of the first, merged with each record of the second with
+    /// the same key, e.g. `jq -c join --on user_id=id orders.ndjson users.ndjson`. Arrays are read as records too. The
+    /// second input is held in memory and the first is streamed past it, so the first should be the larger
+    Join {
+        left: String,
+        right: String,
+        /// The key to join on, which can be a path, or `left_key=right_key` if the inputs name it differently
+        #[arg(long)]
+        on: String,
+        /// Which records of the first input are printed
+        #[arg(long, value_enum, default_value_t = join::How::Inner)]
+        how: join::How,
+    },
+    /// Apply an RFC 6902 JSON Patch to a document and print the result. Fails without output if any
+    /// operation fails, including a `test`
+    Patch {
+        doc: String,
+        patch: String,
+    },
+    /// Profile a JSON, YAML or TOML file: document count, size, nesting depth, and per path the types,
+    /// sizes, key counts and array lengths seen
+    Stats {
+        file: String,
+    },
+    /// Reformat JSON, YAML or TOML files in place with the output flags given before `fmt`, e.g.
+    /// `jq --indent 4 -S fmt data.json`. Files that are already formatted are left untouched, and files that
+    /// can't be read or parsed are listed at the end, with exit status 1
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Don't write anything, but list the files that aren't formatted and exit with status 1 if any
+        #[arg(long)]
+        check: bool,
+        /// Parse every file as this format, instead of choosing by extension (or YAML with -y)
+        #[arg(long, value_enum)]
+        format: Option<validate::Format>,
+    },
+    /// Save queries under a name and run them by it, e.g. `jq alias add failing 'jobs[].steps[status=failed]'`
+    /// then `jq alias run failing ci.json`. Aliases live in ~/.config/jq/aliases.toml, and a project can share
+    /// its own in a checked-in .jq-aliases.toml
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+    /// Check that files parse, without printing them. Files that don't are listed at the end with the line
+    /// and column of the error, and the exit status is 1
+    Validate {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Parse every file as this format, instead of choosing by extension (or YAML with -y)
+        #[arg(long, value_enum)]
+        format: Option<validate::Format>,
+    },
 }
 
-impl PrintCommand {
-    pub fn turn_off_headers(&mut self) {
-        match self {
-            PrintCommand::Csv(_, print_headers) => {
-                *print_headers = false;
-            }
-            _ => {}
-        }
+#[derive(Subcommand)]
+enum AliasCommand {
+    /// Save a query under a name, replacing any alias with that name
+    Add {
+        name: String,
+        query: String,
+        /// Save to this file instead, e.g. `.jq-aliases.toml` to share it with a project
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// Delete a saved query
+    Remove {
+        name: String,
+        /// Delete from this file instead of the user's aliases
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// List the saved queries, including the current project's
+    List,
+    /// Run a saved query, with the flags given before `alias`, e.g. `jq -Y alias run names data.json`
+    Run {
+        name: String,
+        /// Read this file instead of stdin
+        file: Option<String>,
+    },
+}
+
+/// Read a JSON, YAML or TOML file, chosen by its extension. Only the first document of a stream is read.
+fn load_document(path: &str) -> Result<Value> {
+    let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    let text = match text.strip_prefix('\u{feff}') {
+        Some(text) => text.to_string(),
+        None => text,
+    };
+    let ext = std::path::Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let value = match ext.to_ascii_lowercase().as_str() {
+        "yaml" | "yml" => match serde_yaml::Deserializer::from_str(&text).next() {
+            Some(doc) => Value::deserialize(doc)?,
+            None => Value::Null,
+        },
+        "toml" => toml::from_str(&text)?,
+        _ => serde_json::Deserializer::from_str(&text).into_iter::<Value>().next().transpose()?.unwrap_or(Value::Null),
+    };
+    Ok(value)
+}
+
+/// The documents of YAML input, read as YAML 1.2 unless `yaml_1_1`
+fn yaml_documents(mut input: Box<dyn Read>, file: &str, yaml_1_1: bool) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let file = file.to_string();
+    if yaml_1_1 {
+        let docs = yaml_1_1::documents(&text).into_iter().enumerate();
+        return Ok(Box::new(docs.map(move |(i, v)| v.map_err(|e| input_error::yaml(&file, text.as_bytes(), i + 1, e)))));
+    }
+    // The text is kept to show where a parse error is
+    let text: Rc<[u8]> = Rc::from(text.into_bytes());
+    Ok(Box::new(serde_yaml::Deserializer::from_reader(io::Cursor::new(text.clone())).enumerate().map(move |(i, v)| {
+        Value::deserialize(v).map_err(|e| input_error::yaml(&file, &text, i + 1, e.into()))
+    })))
+}
+
+/// Number of leading rows scanned for keys when tabular output has no explicit columns
+const HEADER_SAMPLE_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CsvQuote {
+    /// Quote every field
+    Always,
+    /// Quote only fields containing the delimiter, a quote, or a line break
+    Necessary,
+    /// Quote every field that isn't a number
+    NonNumeric,
+    /// Never quote fields
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum YamlLayout {
+    /// One entry per line, nested by indentation
+    Block,
+    /// Each document on a single line, like `{a: [1, 2]}`
+    Flow,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CsvTerminator {
+    Lf,
+    Crlf,
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        s if s.len() == 1 => Ok(s.as_bytes()[0]),
+        _ => Err("the delimiter must be a single ASCII character".to_string()),
     }
+}
 
-    pub fn add_headers(&mut self, value: &Value) {
-        match value {
-            Value::Array(a) => {
-                self.add_headers(a.first().expect("Empty array"));
-            }
-            Value::Object(o) => {
-                match self {
-                    PrintCommand::Csv(headers, _) => {
-                        if headers.is_empty() {
-                            for key in o.keys() {
-                                headers.push((key.clone(), key.clone()));
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
+/// Whether a word after the input file on the command line is another input file rather than the start of the
+/// query: one named like a JSON, YAML or TOML file, as from a glob. Whether a file by that name exists doesn't
+/// matter, so a key that happens to name a file is still queried, and a missing file is reported. Files named
+/// otherwise are listed after `--`.
+fn is_input_file(word: &str) -> bool {
+    let ext = std::path::Path::new(word).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    matches!(ext.as_str(), "json" | "ndjson" | "jsonl" | "yaml" | "yml" | "toml")
+}
+
+/// The elements of a top-level array that the query starts by selecting, if the array doesn't need to be held
+/// in memory whole to run it: the query starts with `[]`, a filter, or a range that doesn't count from the end
+fn streamed_range(stream: &[StreamCommand]) -> Option<(usize, Option<usize>)> {
+    match stream.first()? {
+        StreamCommand::Filter(_) => Some((0, None)),
+        &StreamCommand::Range(start, end) => {
+            let start = usize::try_from(start.unwrap_or(0)).ok()?;
+            let end = end.map(usize::try_from).transpose().ok()?;
+            Some((start, end))
         }
+        _ => None,
     }
 }
 
-fn split_headers(s: &str) -> Vec<(String, String)> {
-    s.split([',', '\u{29}'])
-        .map(|s| s.split_once('=')
-            .or_else(|| s.split_once(" as "))
-            .or_else(|| s.rsplit_once([']', '.']).map(|t| (s, t.1)))
-            .unwrap_or((s, s))
-        )
-        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+/// Parse each column selector of a tabular print command into the path it evaluates
+fn compile_selectors(selectors: &[String]) -> Result<Vec<(&str, Vec<StreamCommand>)>> {
+    selectors.iter()
+        .map(|s| Ok((s.as_str(), evaluate_command(s)?.0)))
         .collect()
 }
 
-/// a[a=5,b=3]
-/// the
-fn evaluate_command(mut s: &str) -> (Vec<StreamCommand>, PrintCommand) {
-    // s is a comma separated list of commands that operate on json objects
-    // commands is a list of stream commands, and the final command is a print command
-    // stream commands are filter, select, put, delete
-    // print commands are json, pretty, yaml, keys, len, csv
-    // tokenize the input and then parse it.
-    // here are some examples to help you
-    // a.b.c -> select a -> select b -> select c -> (default of print json)
-    // a[b=5].c -> select a -> filter b=5 -> select c -> (default of print json)
-    let mut commands = Vec::new();
-    static TOKENS: &[char] = &[',', '.', '[', ']', '\u{29}'];
-    static DIGITS: &[char] = &['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '-'];
-    while !s.is_empty() {
-        if s.starts_with([']', ',', '\u{29}', ' ']) {
-            s = &s[1..];
-        } else if s.starts_with("..") {
-            let end = s[2..].parse().unwrap();
-            commands.push(StreamCommand::Range(None, Some(end)));
-            s = &s[2 + end.to_string().len()..];
-        } else if s.starts_with('.') {
-            s = &s[1..];
-            let tok = s.split(TOKENS).next().unwrap_or(s);
-            if tok.is_empty() {
-                continue;
-            }
-            commands.push(StreamCommand::Key(tok.to_string()));
-            s = &s[tok.len()..];
-        } else if s.starts_with("keys") {
-            return (commands, PrintCommand::Keys);
-        } else if s.starts_with("len") {
-            return (commands, PrintCommand::Len);
-        } else if s.starts_with("csv") {
-            return if s.len() <= 4 {
-                (commands, PrintCommand::Csv(Vec::new(), true))
-            } else {
-                let mut keys = split_headers(&s[4..]);
-                (commands, PrintCommand::Csv(keys, true))
-            };
-        } else if s.starts_with("put") {
-            s = &s[4..];
-            let put = s.split(',').next().unwrap_or(s);
-            for kv in put.split('\u{29}') {
-                let Some((k, v)) = kv.split_once('=') else {
-                    panic!("Invalid put command: {}", kv);
-                };
-                commands.push(StreamCommand::Put(k.to_string(), v.to_string()));
-            }
-            s = &s[put.len()..];
-        } else if s.starts_with(DIGITS) {
-            let mut tok = s.split(TOKENS).next().unwrap_or(s);
-            if s[tok.len()..].starts_with("..") {
-                let first_token = tok;
-                let start = tok.parse().unwrap();
-                tok = &s[tok.len() + 2..];
-                let tok = tok.split(TOKENS).next().unwrap_or(tok);
-                let end = tok.parse().ok();
-                // its a range
-                commands.push(StreamCommand::Range(Some(start), end));
-                s = &s[first_token.len() + 2 + tok.len()..];
-            } else {
-                commands.push(StreamCommand::Index(tok.parse().unwrap()));
-                s = &s[tok.len()..];
-            }
-        } else if s.starts_with('[') {
-            s = &s[1..];
-            let filter = s.split(']').next().unwrap_or(s);
-            if filter.is_empty() {
-                commands.push(StreamCommand::Range(None, None));
-            } else if filter.starts_with(DIGITS) {
-                if let Some((start, end)) = filter.split_once("..") {
-                    dbg!(start, end);
-                    let start = start.parse().unwrap();
-                    let end = end.parse().ok();
-                    commands.push(StreamCommand::Range(Some(start), end));
-                } else {
-                    let index = filter.parse().unwrap();
-                    commands.push(StreamCommand::Index(index));
-                }
-            } else if filter.starts_with("..") {
-                let end = filter[2..].parse().unwrap();
-                commands.push(StreamCommand::Range(None, Some(end)));
-            } else {
-                for f in filter.split([',', '\u{29}']) {
-                    commands.push(StreamCommand::Filter(f.to_string()));
-                }
-            }
-            s = &s[filter.len()..];
-        } else if s.starts_with("delete") {
-            s = &s[7..];
-            let delete = s.split(',').next().unwrap_or(s);
-            for key in delete.split('\u{29}') {
-                commands.push(StreamCommand::Delete(key.to_string()));
-            }
-            s = &s[delete.len()..];
-        } else {
-            let tok = s.split(TOKENS).next().unwrap_or(s);
-            commands.push(StreamCommand::Key(tok.to_string()));
-            s = &s[tok.len()..];
+/// Look up a column in a row. A key that exists verbatim wins, so keys containing dots keep working.
+/// Otherwise the selector is evaluated as a path, e.g. `user.name` or `metrics[0].value`.
+fn select<'a>(obj: &'a Value, selector: &str, commands: &[StreamCommand]) -> Result<Cow<'a, Value>> {
+    if let Some(v) = obj.get(selector) {
+        return Ok(Cow::Borrowed(v));
+    }
+    // Plain key and index paths can be walked without cloning the row
+    let mut current = obj;
+    for command in commands {
+        current = match command {
+            StreamCommand::Key(k) => current.get(k).unwrap_or(&Value::Null),
+            &StreamCommand::Index(i) => current.as_array()
+                .and_then(|arr| arr.get(jq::resolve_index(i, arr.len())?))
+                .unwrap_or(&Value::Null),
+            _ => return Ok(apply_stream_ref(obj, commands, Options::default()).next().transpose()?.unwrap_or(Cow::Owned(Value::Null))),
+        };
+    }
+    Ok(Cow::Borrowed(current))
+}
+
+/// The cells of tabular output: one row per element of an array, or a single row for an object
+fn table_rows<'a>(obj: &'a Value, columns: &[(&str, Vec<StreamCommand>)]) -> Result<Vec<Vec<Cow<'a, Value>>>> {
+    let row = |obj: &'a Value| columns.iter()
+        .map(|(selector, commands)| select(obj, selector, commands))
+        .collect();
+    match obj {
+        Value::Array(vec) => vec.iter().map(row).collect(),
+        Value::Object(_) => Ok(vec![row(obj)?]),
+        _ => Err(anyhow!("Tabular output needs an array or object, found {}", obj)),
+    }
+}
+
+/// Escape a cell for tab-separated output. Tabs, newlines and backslashes are written as escapes,
+/// so every record stays on one line and columns never shift.
+fn escape_tsv(s: &str) -> Cow<'_, str> {
+    if !s.contains(['\t', '\n', '\r', '\\']) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn tsv_row(cells: &[Cow<Value>]) -> String {
+    cells.iter()
+        .map(|v| match v.as_ref() {
+            Value::String(s) => escape_tsv(s).into_owned(),
+            z => z.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Escape a cell for a Markdown table. Pipes would end the cell early and newlines would end the row.
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+fn markdown_row<'a>(cells: impl Iterator<Item=Cow<'a, str>>) -> String {
+    let mut row = String::from("|");
+    for cell in cells {
+        row.push(' ');
+        row.push_str(&escape_markdown(&cell));
+        row.push_str(" |");
+    }
+    row
+}
+
+fn markdown_cells<'a>(cells: &'a [Cow<Value>]) -> impl Iterator<Item=Cow<'a, str>> {
+    cells.iter().map(|v| match v.as_ref() {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(s) => Cow::Borrowed(s.as_str()),
+        z => Cow::Owned(z.to_string()),
+    })
+}
+
+/// Quote `s` for a POSIX shell. Single quotes disable every expansion, and an embedded single quote
+/// is written by closing the quote, escaping it, and reopening.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Turn a key into a valid shell variable name
+fn shell_ident(key: &str) -> String {
+    let mut ident: String = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+/// Render `obj` through a template, replacing each `{path}` with the value at that path. Strings are
+/// inserted raw, nulls as nothing, and anything else as compact JSON.
+fn render_template(template: &str, obj: &Value) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let c = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        if rest.as_bytes().first() == Some(&c) {
+            out.push(c as char);
+            rest = &rest[1..];
+            continue;
+        }
+        if c == b'}' {
+            return Err(anyhow!("Unmatched `}}` in template: {}", template));
+        }
+        let end = rest.find('}').ok_or_else(|| anyhow!("Unclosed `{{` in template: {}", template))?;
+        let selector = rest[..end].trim();
+        let (commands, _) = evaluate_command(selector)?;
+        match select(obj, selector, &commands)?.as_ref() {
+            Value::Null => {}
+            Value::String(s) => out.push_str(s),
+            z => out.push_str(&z.to_string()),
         }
+        rest = &rest[end + 1..];
     }
-    (commands, PrintCommand::Pretty)
+    out.push_str(rest);
+    Ok(out)
 }
 
-fn parse_json(s: &str) -> Value {
-    serde_json::from_str(s).unwrap_or(Value::String(s.to_string()))
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
 }
 
-fn equal(value: &Value, other: &str) -> bool {
+/// Recursively sort the keys of every object in `value`
+fn sort_keys(value: &mut Value) {
     match value {
-        Value::String(s) => s == other,
-        Value::Number(n) => n.to_string() == other,
-        Value::Bool(b) => b.to_string() == other,
-        Value::Null => other == "null",
-        _ => false,
+        Value::Object(map) => {
+            map.sort_keys();
+            map.values_mut().for_each(sort_keys);
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(sort_keys),
+        _ => {}
     }
 }
 
-fn normalize(n: i64, arr: &Vec<Value>) -> usize {
-    (if n < 0 {
-        arr.len() as i64 + n
-    } else {
-        n
-    }) as usize
-}
-
-fn apply_stream(mut obj: Value, mut stream_command: &[StreamCommand]) -> Box<dyn Iterator<Item=Value> + '_> {
-    while !stream_command.is_empty() {
-        let command = &stream_command[0];
-        stream_command = &stream_command[1..];
-        match command {
-            StreamCommand::Key(s) => {
-                let Value::Object(mut o) = obj else {
-                    panic!("Expected object when using key {}, encountered: {:?}", s, obj);
-                };
-                obj = o.remove(s).unwrap_or(Value::Null);
-            }
-            StreamCommand::Filter(f) => {
-                // a=5, a=b
-                // a like foo
-                // a > 5
-                // > 5
-                match obj {
-                    Value::Array(arr) => {
-                        let Some((key, value)) = f.split_once('=') else {
-                            panic!("Invalid filter: {}", f);
-                        };
-                        let it = arr
-                            .into_iter()
-                            .filter_map(move |v| {
-                                let Value::Object(mut o) = v else {
-                                    return None;
-                                };
-                                let Some(v) = o.remove(key) else {
-                                    return None;
-                                };
-                                Some(v).filter(|v| equal(&v, value))
-                            })
-                            .flat_map(|v| apply_stream(v, stream_command));
-                        return Box::new(it);
-                    }
-                    Value::Object(o) => {
-                        let Some((key, value)) = f.split_once('=') else {
-                            panic!("Invalid filter: {}", f);
-                        };
-                        let Some(v) = o.get(key) else {
-                            if value == "null" {
-                                obj = Value::Object(o);
-                                continue;
-                            } else {
-                                return Box::new(empty());
-                            }
-                        };
-                        if equal(v, value) {
-                            obj = Value::Object(o);
-                            continue;
-                        } else {
-                            return Box::new(empty());
-                        }
-                    }
-                    _ => {
-                        panic!("Expected array or object when using filter {}, encountered: {:?}", f, obj);
-                    }
-                }
-            }
-            StreamCommand::Put(k, v) => {
-                let Value::Object(mut o) = obj else {
-                    panic!("Expected object when using key {}, encountered: {:?}", k, obj);
-                };
-                o.insert(k.clone(), parse_json(v));
-                obj = Value::Object(o);
-            }
-            StreamCommand::Delete(d) => {
-                let Value::Object(mut o) = obj else {
-                    panic!("Expected object when using key {}, encountered: {:?}", d, obj);
-                };
-                o.remove(d);
-                obj = Value::Object(o);
-            }
-            &StreamCommand::Index(i) => {
-                let Value::Array(mut arr) = obj else {
-                    panic!("Expected array when using index {}, encountered: {:?}", i, obj);
-                };
-                obj = arr.remove(i);
-            }
-            &StreamCommand::Range(start, end) => {
-                let Value::Array(mut arr) = obj else {
-                    panic!("Expected array when using range {:?}..{:?}, encountered: {:?}", start, end, obj);
-                };
-                return match (start, end) {
-                    (Some(start), Some(end)) => {
-                        let start = normalize(start, &arr);
-                        let end = normalize(end, &arr);
-                        Box::new(arr.into_iter().skip(start).take(end - start).flat_map(|v| apply_stream(v, stream_command)))
-                    }
-                    (Some(start), None) => {
-                        let start = normalize(start, &arr);
-                        Box::new(arr.into_iter().skip(start).flat_map(|v| apply_stream(v, stream_command)))
-                    }
-                    (None, Some(end)) => {
-                        let end = normalize(end, &arr);
-                        Box::new(arr.into_iter().take(end).flat_map(|v| apply_stream(v, stream_command)))
-                    }
-                    (None, None) => {
-                        Box::new(arr.into_iter().flat_map(|v| apply_stream(v, stream_command)))
-                    }
-                };
+/// Formatting options shared by every print command
+#[derive(Default)]
+struct PrintOptions {
+    color: bool,
+    indent: u8,
+    tab: bool,
+    ascii: bool,
+    raw_output0: bool,
+    html: bool,
+    styler: Styler,
+    yaml: YamlStyle,
+    csv: CsvDialect,
+}
+
+/// The csv flavor written by the csv print command
+struct CsvDialect {
+    delimiter: u8,
+    quote: CsvQuote,
+    terminator: CsvTerminator,
+    null: String,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: b',',
+            quote: CsvQuote::Necessary,
+            terminator: CsvTerminator::Lf,
+            null: "null".to_string(),
+        }
+    }
+}
+
+impl CsvDialect {
+    fn writer<W: Write>(&self, w: W) -> csv::Writer<W> {
+        csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote_style(match self.quote {
+                CsvQuote::Always => csv::QuoteStyle::Always,
+                CsvQuote::Necessary => csv::QuoteStyle::Necessary,
+                CsvQuote::NonNumeric => csv::QuoteStyle::NonNumeric,
+                CsvQuote::Never => csv::QuoteStyle::Never,
+            })
+            .terminator(match self.terminator {
+                CsvTerminator::Lf => csv::Terminator::Any(b'\n'),
+                CsvTerminator::Crlf => csv::Terminator::CRLF,
+            })
+            .from_writer(w)
+    }
+}
+
+/// Turn on ANSI escapes in the Windows console, which only interprets them once asked to. Whether the terminal
+/// supports them.
+#[cfg(windows)]
+fn enable_ansi() -> bool {
+    crossterm::ansi_support::supports_ansi()
+}
+
+#[cfg(not(windows))]
+fn enable_ansi() -> bool {
+    true
+}
+
+impl PrintOptions {
+    fn new(cli: &Cli) -> Self {
+        let choice = match cli.color {
+            Some(choice) => choice,
+            None if cli.monochrome => ColorChoice::Never,
+            None if cli.color_output => ColorChoice::Always,
+            None => ColorChoice::Auto,
+        };
+        let color = match choice {
+            ColorChoice::Never => false,
+            ColorChoice::Always => {
+                enable_ansi();
+                true
             }
+            ColorChoice::Auto => cli.output.is_none() && !cli.quiet && ColorMode::Auto(Output::StdOut).use_color() && enable_ansi(),
+        };
+        PrintOptions {
+            color,
+            indent: cli.indent,
+            tab: cli.tab,
+            ascii: cli.ascii_output,
+            raw_output0: cli.raw_output0,
+            html: cli.html_output,
+            styler: Styler::default(),
+            yaml: YamlStyle {
+                indent: cli.yaml_indent.unwrap_or(cli.indent).max(1) as usize,
+                flow: cli.yaml_style == YamlLayout::Flow,
+                indent_sequences: cli.yaml_indent_sequences,
+                quote_ambiguous: cli.yaml_quote_ambiguous,
+                double_quotes: cli.yaml_double_quotes,
+                document_start: cli.yaml_document_start,
+            },
+            csv: CsvDialect {
+                delimiter: cli.csv_delimiter,
+                quote: cli.csv_quote,
+                terminator: cli.csv_terminator,
+                null: cli.csv_null.clone(),
+            },
+        }
+    }
+
+    /// Whether JSON is printed as it's written, without color, markup or escapes
+    fn is_plain(&self) -> bool {
+        !self.color && !self.html && !self.ascii
+    }
+
+    fn color_mode(&self) -> ColorMode {
+        if self.color {
+            ColorMode::On
+        } else {
+            ColorMode::Off
+        }
+    }
+
+    /// The byte written after each result of the default print command
+    fn terminator(&self) -> u8 {
+        if self.raw_output0 {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+
+    fn json_indent(&self) -> Vec<u8> {
+        if self.tab {
+            b"\t".to_vec()
+        } else {
+            vec![b' '; self.indent as usize]
         }
     }
-    Box::new(once(obj))
 }
 
-fn apply_print(obj: Value, print: &PrintCommand) {
+fn apply_print(mut out: &mut dyn Write, obj: Value, print: &PrintCommand, opts: &PrintOptions) -> Result<()> {
     match print {
+        PrintCommand::Yaml if opts.html => {
+            writeln!(out, "{}", html::yaml(&obj, opts))?;
+        }
         PrintCommand::Yaml => {
-            println!("{}", serde_yaml::to_string(&obj).unwrap());
+            writeln!(out, "{}", yaml::to_string(&obj, &opts.yaml))?;
+        }
+        PrintCommand::Toml => {
+            write!(out, "{}", toml::to_string_pretty(&obj)?)?;
+        }
+        PrintCommand::Xml(root, item) => {
+            write!(out, "{}", xml::to_string(&obj, root, item)?)?;
+        }
+        PrintCommand::Json | PrintCommand::Compact | PrintCommand::Pretty if opts.html => {
+            writeln!(out, "{}", html::json(&obj, opts, *print == PrintCommand::Pretty)?)?;
+        }
+        PrintCommand::Json | PrintCommand::Compact => {
+            json::write_json(&mut out, &obj, opts, false, opts.color_mode())?;
+            writeln!(out)?;
         }
-        PrintCommand::Json => {
-            println!("{}", obj);
+        PrintCommand::Canonical => {
+            writeln!(out, "{}", jq::canonical::canonical(&obj))?;
+        }
+        PrintCommand::Seq => {
+            out.write_all(b"\x1e")?;
+            json::write_json(&mut out, &obj, opts, false, opts.color_mode())?;
+            writeln!(out)?;
         }
         PrintCommand::Pretty => {
             if let Some(s) = obj.as_str() {
-                println!("{}", s);
-            } else {
-                let out = stdout();
-                {
-                    let mut out = out.lock();
-                    colored_json::write_colored_json(&obj, &mut out).unwrap();
-                    write!(out, "\n").unwrap();
-                    out.flush().unwrap();
+                if opts.raw_output0 && s.contains('\0') {
+                    return Err(anyhow!("Cannot print a string containing NUL with --raw-output0"));
+                }
+                if opts.ascii {
+                    json::write_ascii(&mut out, s)?;
+                } else {
+                    out.write_all(s.as_bytes())?;
                 }
+                out.write_all(&[opts.terminator()])?;
+            } else {
+                json::write_json(&mut out, &obj, opts, true, opts.color_mode())?;
+                out.write_all(&[opts.terminator()])?;
             }
         }
         PrintCommand::Keys => {
-            let obj = obj.as_object().expect("Not an object");
+            let obj = obj.as_object().ok_or_else(|| anyhow!("keys: expected an object, found {}", obj))?;
             for key in obj.keys() {
-                println!("{}", key);
+                writeln!(out, "{}", key)?;
             }
         }
         PrintCommand::Len => {
             let len = match obj {
                 Value::Array(arr) => arr.len(),
                 Value::Object(obj) => obj.len(),
-                _ => panic!("Not an array or object"),
+                _ => return Err(anyhow!("len: expected an array or object, found {}", obj)),
             };
-            println!("{}", len);
+            writeln!(out, "{}", len)?;
         }
         PrintCommand::Csv(pairs, print_headers) => {
-            let (selectors, headers): (Vec<_>, Vec<_>) = pairs.into_iter().cloned().unzip();
-            let mut csv = csv::Writer::from_writer(stdout());
+            let (selectors, headers): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+            let dialect = &opts.csv;
+            let mut csv = dialect.writer(&mut *out);
             if *print_headers {
-                csv.write_record(headers.iter()).unwrap();
-            }
-            match &obj {
-                Value::Array(vec) => {
-                    for obj in vec {
-                        let values = selectors.iter()
-                            .map(|k| {
-                                let v = obj.get(k).unwrap_or(&Value::Null);
-                                match v {
-                                    Value::String(s) => Cow::Borrowed(s.as_bytes()),
-                                    z => Cow::Owned(serde_json::to_vec(z).unwrap())
-                                }
-                            })
-                            .collect::<Vec<_>>();
-                        csv.write_record(values).unwrap();
-                    }
-                }
-                Value::Object(map) => {
-                    let values = selectors.iter()
-                        .map(|k| {
-                            let v = obj.get(k).unwrap_or(&Value::Null);
-                            match v {
-                                Value::String(s) => Cow::Borrowed(s.as_bytes()),
-                                z => Cow::Owned(serde_json::to_vec(z).unwrap())
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    csv.write_record(values).unwrap();
-                }
-                _ => {
-                    panic!("Not an array or object");
-                }
+                csv.write_record(headers.iter())?;
             }
+            for row in table_rows(&obj, &compile_selectors(&selectors)?)? {
+                let values = row.iter().map(|v| match v.as_ref() {
+                    Value::String(s) => Cow::Borrowed(s.as_bytes()),
+                    Value::Null => Cow::Borrowed(dialect.null.as_bytes()),
+                    z => Cow::Owned(z.to_string().into_bytes())
+                });
+                csv.write_record(values)?;
+            }
+            // Dropping the writer would flush it too, but without saying if that failed
+            csv.flush()?;
         }
-    }
-}
-
-fn main() -> Result<()> {
-    // munge the args to insert -- before any negative numbers to fix clap's parsing
-    let mut args: Vec<String> = args().collect();
-    for i in 0..args.len() {
-        if args[i] == "--" {
-            break;
+        PrintCommand::Tsv(pairs, print_headers) => {
+            let (selectors, headers): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+            if *print_headers {
+                writeln!(out, "{}", headers.iter().map(|h| escape_tsv(h)).collect::<Vec<_>>().join("\t"))?;
+            }
+            for row in table_rows(&obj, &compile_selectors(&selectors)?)? {
+                writeln!(out, "{}", tsv_row(&row))?;
+            }
         }
-        let re = regex!(r#"^-\d+"#);
-        if re.is_match(&args[i]) {
-            args.insert(i, "--".to_string());
-            break;
+        PrintCommand::Markdown(pairs, print_headers) => {
+            let (selectors, headers): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+            if *print_headers {
+                writeln!(out, "{}", markdown_row(headers.iter().map(|h| Cow::Borrowed(h.as_str()))))?;
+                writeln!(out, "{}", markdown_row(headers.iter().map(|_| Cow::Borrowed("---"))))?;
+            }
+            for row in table_rows(&obj, &compile_selectors(&selectors)?)? {
+                writeln!(out, "{}", markdown_row(markdown_cells(&row)))?;
+            }
+        }
+        PrintCommand::Shell => {
+            let Value::Object(map) = obj else {
+                return Err(anyhow!("sh requires an object, encountered: {}", obj));
+            };
+            for (k, v) in map {
+                let value = match v {
+                    Value::Null => String::new(),
+                    Value::String(s) => s,
+                    z => z.to_string(),
+                };
+                writeln!(out, "export {}={}", shell_ident(&k), shell_quote(&value))?;
+            }
+        }
+        PrintCommand::Tree(depth) => {
+            write!(out, "{}", tree::render(&obj, *depth))?;
+        }
+        PrintCommand::Template(template) => {
+            writeln!(out, "{}", render_template(template, &obj)?)?;
+        }
+        PrintCommand::Hash(algorithm) => {
+            writeln!(out, "{}", hash::hash(&obj, *algorithm))?;
+        }
+        PrintCommand::Gron => {
+            for line in gron::gron(&obj) {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        PrintCommand::Paths => {
+            for path in pointer::paths(&obj) {
+                writeln!(out, "{}", path)?;
+            }
+        }
+        PrintCommand::Table(pairs) => {
+            if pairs.is_empty() {
+                let rows = match &obj {
+                    Value::Array(vec) => vec.iter().map(|v| vec![Cow::Borrowed(v)]).collect(),
+                    z => vec![vec![Cow::Borrowed(z)]],
+                };
+                write!(out, "{}", table::render(&["value".to_string()], &rows, opts.color))?;
+            } else {
+                let (selectors, mut headers): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+                let mut rows = table_rows(&obj, &compile_selectors(&selectors)?)?;
+                // Rows that aren't objects have nothing to fill the columns with, so like xlsx output they're
+                // shown whole in a column of their own
+                if let Value::Array(vec) = &obj {
+                    if vec.iter().any(|v| !v.is_object()) {
+                        headers.push("value".to_string());
+                        for (row, v) in rows.iter_mut().zip(vec) {
+                            row.push(if v.is_object() { Cow::Owned(Value::Null) } else { Cow::Borrowed(v) });
+                        }
+                    }
+                }
+                write!(out, "{}", table::render(&headers, &rows, opts.color))?;
+            }
         }
     }
-    let mut cli = Cli::parse_from(args);
+    Ok(())
+}
 
-    let mut input: Box<dyn Read> = if io::stdin().is_terminal() {
-        if cli.command.is_empty() {
-            Cli::parse_from(vec![env!("CARGO_BIN_NAME"), "--help"]);
-            panic!("No command provided");
-        } else if let Some(i) = &cli.in_place {
-            let file = File::open(&i).unwrap();
-            Box::new(io::BufReader::new(file))
-        } else {
-            let filename = cli.command.remove(0);
-            let file = File::open(&filename).unwrap();
-            Box::new(io::BufReader::new(file))
+/// Every document in `text` written back out in `format`, pretty-printed with the output options, and ending
+/// in a newline
+fn reformat(text: &str, format: validate::Format, cli: &Cli, opts: &PrintOptions) -> Result<String> {
+    let docs: Vec<Value> = match format {
+        validate::Format::Json => serde_json::Deserializer::from_str(text).into_iter().collect::<Result<_, _>>()?,
+        validate::Format::Yaml => serde_yaml::Deserializer::from_str(text)
+            .map(Value::deserialize)
+            .collect::<Result<_, _>>()?,
+        validate::Format::Toml => vec![toml::from_str(text)?],
+    };
+    let mut out = Vec::new();
+    for (i, mut doc) in docs.into_iter().enumerate() {
+        if cli.sort_keys {
+            sort_keys(&mut doc);
         }
+        match format {
+            validate::Format::Json => {
+                json::write_json(&mut out, &doc, opts, true, ColorMode::Off)?;
+                writeln!(out)?;
+            }
+            validate::Format::Yaml => {
+                if i > 0 && !opts.yaml.document_start {
+                    writeln!(out, "---")?;
+                }
+                out.write_all(yaml::to_string(&doc, &opts.yaml).as_bytes())?;
+            }
+            validate::Format::Toml => out.write_all(toml::to_string_pretty(&doc)?.as_bytes())?,
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+/// Write a result as a document of a file: YAML, TOML, canonical or compact JSON according to the output
+/// flags, and pretty JSON otherwise
+fn write_document<W: Write>(out: &mut W, obj: &Value, yaml: bool, cli: &Cli, opts: &PrintOptions) -> Result<()> {
+    if yaml {
+        out.write_all(yaml::to_string(obj, &opts.yaml).as_bytes())?;
+    } else if cli.toml_output {
+        out.write_all(toml::to_string_pretty(obj)?.as_bytes())?;
+    } else if cli.canonical {
+        writeln!(out, "{}", jq::canonical::canonical(obj))?;
+    } else if cli.json_output || cli.compact || cli.jsonl {
+        json::write_json(out, obj, opts, false, ColorMode::Off)?;
+        writeln!(out)?;
     } else {
-        let stdin = io::stdin();
-        Box::new(stdin.lock())
-    };
+        json::write_json(out, obj, opts, true, ColorMode::Off)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
 
-    if cli.bulk || cli.in_place.is_some() {
-        let mut buf = String::new();
-        input.read_to_string(&mut buf).expect("Failed to read input");
-        input = Box::new(io::Cursor::new(buf));
+/// Replace `dest` with `contents`, or with --dry-run, print what would change
+fn finish_in_place(cli: &Cli, opts: &PrintOptions, dest: &str, lock: Option<&in_place::Lock>, contents: &[u8]) -> Result<()> {
+    if cli.dry_run {
+        print!("{}", in_place::diff(dest, contents, opts.color)?);
+        Ok(())
+    } else {
+        if let Some(lock) = lock {
+            lock.unchanged()?;
+        }
+        in_place::write(dest, contents, cli.backup.as_deref())
     }
+}
 
-    let command = cli.command.join("\u{29}");
-    let (stream, mut print) = evaluate_command(&command);
+/// The print command for an --output file, chosen by its extension
+fn format_of_path(path: &str, cli: &Cli) -> Option<PrintCommand> {
+    let ext = std::path::Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "json" => PrintCommand::Pretty,
+        "yaml" | "yml" => PrintCommand::Yaml,
+        "toml" => PrintCommand::Toml,
+        "xml" => PrintCommand::Xml(cli.xml_root.clone(), cli.xml_item.clone()),
+        "csv" => PrintCommand::Csv(Vec::new(), true),
+        "tsv" => PrintCommand::Tsv(Vec::new(), true),
+        "md" => PrintCommand::Markdown(Vec::new(), true),
+        "ndjson" | "jsonl" => PrintCommand::Compact,
+        _ => return None,
+    })
+}
+
+/// Print the result of a subcommand with the output flags
+fn print_value(cli: &Cli, obj: Value, opts: &PrintOptions) -> Result<()> {
+    let mut out = output(cli)?;
+    apply_print(&mut out, obj, &output_format(cli, PrintCommand::Pretty), opts)?;
+    Ok(out.flush()?)
+}
+
+/// Where results are printed: the --output file, or stdout
+fn output(cli: &Cli) -> Result<Box<dyn Write>> {
+    if cli.quiet {
+        return Ok(Box::new(io::sink()));
+    }
+    match &cli.output {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| anyhow!("Failed to create {}: {}", path, e))?;
+            Ok(Box::new(io::BufWriter::new(file)))
+        }
+        None => Ok(Box::new(io::BufWriter::new(stdout()))),
+    }
+}
+
+/// The print command selected by the output flags, when the query doesn't end in one
+fn output_format(cli: &Cli, mut print: PrintCommand) -> PrintCommand {
     if print == PrintCommand::Pretty {
+        if let Some(inferred) = cli.output.as_deref().and_then(|path| format_of_path(path, cli)) {
+            print = inferred;
+        }
         if cli.yaml_output {
             print = PrintCommand::Yaml;
         }
+        if cli.toml_output {
+            print = PrintCommand::Toml;
+        }
+        if cli.xml_output {
+            print = PrintCommand::Xml(cli.xml_root.clone(), cli.xml_item.clone());
+        }
         if cli.json_output {
             print = PrintCommand::Json;
         }
         if cli.raw {
             print = PrintCommand::Json;
         }
+        if cli.compact || cli.jsonl {
+            print = PrintCommand::Compact;
+        }
+        if cli.seq_output {
+            print = PrintCommand::Seq;
+        }
+        if cli.canonical {
+            print = PrintCommand::Canonical;
+        }
+        if let Some(template) = &cli.template {
+            print = PrintCommand::Template(template.clone());
+        }
+    }
+    print
+}
+
+fn main() {
+    if let Err(e) = run(args().collect()) {
+        // Whatever was reading the output has stopped, like `head`, so there's no one to tell, and no point
+        // reading more input
+        if is_broken_pipe(&e) {
+            std::process::exit(0);
+        }
+        if let Some(Exit(code)) = e.downcast_ref() {
+            std::process::exit(*code);
+        }
+        eprintln!("{}: {:#}", env!("CARGO_BIN_NAME"), e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+/// The last result was false or null with --exit-status, or a check like `validate` or `fmt --check` failed.
+/// Also any error not covered by another code, like a file that can't be read
+const EXIT_FALSY: i32 = 1;
+/// The query couldn't be parsed
+const EXIT_QUERY: i32 = 3;
+/// The query produced no results with --exit-status
+const EXIT_NO_OUTPUT: i32 = 4;
+/// The query failed on a value, e.g. with --strict
+const EXIT_RUNTIME: i32 = 5;
+/// The input couldn't be parsed
+const EXIT_INPUT: i32 = 6;
+
+/// A run that did what it was asked, but exits with this code to say how it went, like --exit-status when the
+/// last result is false
+#[derive(Debug)]
+struct Exit(i32);
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// The exit code for an error, by its cause. Usage errors exit with 2 from clap before getting this far.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<jq::Error>() {
+            return match e {
+                jq::Error::Parse { .. } | jq::Error::Table { .. } => EXIT_QUERY,
+                _ => EXIT_RUNTIME,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<serde_json::Error>() {
+            if !e.is_io() {
+                return EXIT_INPUT;
+            }
+        }
+        if cause.is::<input_error::InputError>() || cause.is::<serde_yaml::Error>() || cause.is::<toml::de::Error>()
+            || cause.is::<std::string::FromUtf8Error>() {
+            return EXIT_INPUT;
+        }
+    }
+    EXIT_FALSY
+}
+
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| match (e.downcast_ref::<io::Error>(), e.downcast_ref::<serde_json::Error>()) {
+        (Some(e), _) => e.kind() == io::ErrorKind::BrokenPipe,
+        (_, Some(e)) => e.io_error_kind() == Some(io::ErrorKind::BrokenPipe),
+        _ => false,
+    })
+}
+
+/// Parse the command line, letting a query start with a negative index like `-2..`, which clap would take for a flag
+fn parse_args(mut args: Vec<String>) -> Result<(Cli, clap::ArgMatches)> {
+    // munge the args to insert -- before any negative numbers to fix clap's parsing
+    let mut munged = false;
+    for i in 0..args.len() {
+        if args[i] == "--" {
+            break;
+        }
+        let re = regex!(r#"^-\d+"#);
+        if re.is_match(&args[i]) {
+            args.insert(i, "--".to_string());
+            munged = true;
+            break;
+        }
+    }
+    let matches = match Cli::command().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        Err(e) => match subcommand_as_key(&args, &e) {
+            Some(matches) => matches,
+            None => e.exit(),
+        },
+    };
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    if munged {
+        // The words after the inserted -- are still the query, and files only follow a -- typed after them
+        let mut words = std::mem::take(&mut cli.files).into_iter();
+        cli.command.extend(words.by_ref().take_while(|word| word != "--"));
+        cli.files = words.collect();
+    }
+    Ok((cli, matches))
+}
+
+/// A subcommand's name without the arguments it needs is a key to select instead, like `stats` in
+/// `echo '{"stats": 1}' | jq stats`: the command line parsed again with it written as `.stats`
+fn subcommand_as_key(args: &[String], e: &clap::Error) -> Option<clap::ArgMatches> {
+    use clap::error::ErrorKind;
+    if !matches!(e.kind(), ErrorKind::MissingRequiredArgument | ErrorKind::MissingSubcommand
+        | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand) {
+        return None;
+    }
+    let command = Cli::command();
+    let names: Vec<&str> = command.get_subcommands().flat_map(|sub| sub.get_name_and_visible_aliases()).collect();
+    args.iter().enumerate().skip(1)
+        .take_while(|(_, arg)| *arg != "--")
+        .filter(|(_, arg)| names.contains(&arg.as_str()))
+        .find_map(|(i, arg)| {
+            let mut args = args.to_vec();
+            args[i] = format!(".{}", arg);
+            Cli::command().try_get_matches_from(args).ok()
+        })
+}
+
+fn run(args: Vec<String>) -> Result<()> {
+    let (mut cli, matches) = parse_args(args)?;
+    match &cli.subcommand {
+        Some(Sub::Completions { shell }) => {
+            let bin = env!("CARGO_BIN_NAME");
+            clap_complete::generate(*shell, &mut Cli::command(), bin, &mut stdout());
+            if let Some(snippet) = complete::snippet(*shell, bin) {
+                print!("{}", snippet);
+            }
+            return Ok(());
+        }
+        Some(Sub::CompleteKeys { file, partial }) => {
+            let doc = load_document(file)?;
+            for key in complete::keys(&doc, partial) {
+                println!("{}", key);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    if let Some(Sub::Alias { command }) = &cli.subcommand {
+        let run = match command {
+            AliasCommand::Add { name, query, file } => return alias::add(file.as_deref(), name, query),
+            AliasCommand::Remove { name, file } => return alias::remove(file.as_deref(), name),
+            AliasCommand::List => {
+                for (name, query) in alias::all()? {
+                    println!("{}\t{}", name, query);
+                }
+                return Ok(());
+            }
+            AliasCommand::Run { name, .. } => vec![alias::find(name)?],
+        };
+        cli.command = run;
+    }
+    if let Some(Sub::Validate { files, format }) = &cli.subcommand {
+        let mut failures = failures::Failures::default();
+        for file in files {
+            let format = match format {
+                Some(format) => *format,
+                None if cli.yaml => validate::Format::Yaml,
+                None => validate::Format::of(file),
+            };
+            let error = match std::fs::read_to_string(file) {
+                Ok(text) => validate::check(&text, format).err().map(|e| e.to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            match error {
+                Some(e) => failures.add(file, e),
+                None => failures.ok(),
+            }
+        }
+        if !failures.is_empty() {
+            eprintln!("{}", failures);
+            return Err(Exit(EXIT_FALSY).into());
+        }
+        return Ok(());
+    }
+    let config = config::Config::load(cli.config.as_deref())?;
+    config.apply(&mut cli, &matches)?;
+    let opts = PrintOptions { styler: config.styler()?, ..PrintOptions::new(&cli) };
+
+    if let Some(Sub::Merge { files, arrays, merge_key }) = &cli.subcommand {
+        let mut merged = Value::Null;
+        for file in files {
+            merge::merge(&mut merged, load_document(file)?, *arrays, merge_key);
+        }
+        if cli.sort_keys {
+            sort_keys(&mut merged);
+        }
+        return print_value(&cli, merged, &opts);
+    }
+
+    if let Some(Sub::Join { left, right, on, how }) = &cli.subcommand {
+        let join = join::Join::new(on, *how, inputs::records(right, cli.yaml)?)?;
+        let print = output_format(&cli, PrintCommand::Pretty);
+        let mut out = output(&cli)?;
+        for record in inputs::records(left, cli.yaml)? {
+            for mut joined in join.join(record?)? {
+                if cli.sort_keys {
+                    sort_keys(&mut joined);
+                }
+                apply_print(&mut out, joined, &print, &opts)?;
+            }
+        }
+        return Ok(out.flush()?);
     }
-    let deserializer: Box<dyn Iterator<Item=Result<Value>>> = if cli.yaml {
-        Box::new(serde_yaml::Deserializer::from_reader(input).map(|v| {
-            Value::deserialize(v).map_err(anyhow::Error::from)
+
+    if let Some(Sub::Diff { a, b, color, patch, merge_patch }) = &cli.subcommand {
+        let (a, b) = (load_document(a)?, load_document(b)?);
+        let changed = if *patch {
+            let ops = diff::patch(&a, &b);
+            let changed = ops.as_array().is_some_and(|ops| !ops.is_empty());
+            print_value(&cli, ops, &opts)?;
+            changed
+        } else if *merge_patch {
+            let changed = a != b;
+            print_value(&cli, merge_patch::generate(&a, &b), &opts)?;
+            changed
+        } else {
+            let changes = diff::diff(&a, &b);
+            for change in &changes {
+                println!("{}", diff::render(change, *color || opts.color));
+            }
+            !changes.is_empty()
+        };
+        if changed {
+            return Err(Exit(EXIT_FALSY).into());
+        }
+        return Ok(());
+    }
+
+    if let Some(Sub::Stats { file }) = &cli.subcommand {
+        let text = std::fs::read_to_string(file).map_err(|e| anyhow!("Failed to read {}: {}", file, e))?;
+        let mut stats = stats::Stats::default();
+        match if cli.yaml { validate::Format::Yaml } else { validate::Format::of(file) } {
+            validate::Format::Json => for doc in serde_json::Deserializer::from_str(&text).into_iter::<Value>() {
+                stats.add(&doc?);
+            },
+            validate::Format::Yaml => for doc in serde_yaml::Deserializer::from_str(&text) {
+                stats.add(&Value::deserialize(doc)?);
+            },
+            validate::Format::Toml => stats.add(&toml::from_str(&text)?),
+        }
+        return print_value(&cli, stats.report(text.len()), &opts);
+    }
+
+    if let Some(Sub::Fmt { files, check, format }) = &cli.subcommand {
+        // A file that can't be read, parsed or written doesn't stop the rest being formatted
+        let mut failures = failures::Failures::default();
+        let mut unformatted = false;
+        for file in files {
+            let text = match std::fs::read_to_string(file) {
+                Ok(text) => text,
+                Err(e) => {
+                    failures.add(file, format!("Failed to read: {}", e));
+                    continue;
+                }
+            };
+            let format = match format {
+                Some(format) => *format,
+                None if cli.yaml => validate::Format::Yaml,
+                None => validate::Format::of(file),
+            };
+            let formatted = match reformat(&text, format, &cli, &opts) {
+                Ok(formatted) => formatted,
+                Err(e) => {
+                    failures.add(file, format!("{:#}", e));
+                    continue;
+                }
+            };
+            if formatted == text {
+                failures.ok();
+                continue;
+            }
+            unformatted = true;
+            if *check {
+                println!("{}", file);
+            } else if let Err(e) = in_place::write(file, formatted.as_bytes(), None) {
+                failures.add(file, format!("{:#}", e));
+                continue;
+            }
+            failures.ok();
+        }
+        if !failures.is_empty() {
+            eprintln!("{}", failures);
+            return Err(Exit(EXIT_FALSY).into());
+        }
+        if *check && unformatted {
+            return Err(Exit(EXIT_FALSY).into());
+        }
+        return Ok(());
+    }
+
+    if let Some(Sub::Patch { doc, patch }) = &cli.subcommand {
+        let mut patched = patch::apply(load_document(doc)?, &load_document(patch)?)?;
+        if cli.sort_keys {
+            sort_keys(&mut patched);
+        }
+        return print_value(&cli, patched, &opts);
+    }
+
+    if cli.explain {
+        let (stream, print) = evaluate_command(&cli.command.join(" "))?;
+        for command in &stream {
+            println!("{:?}", command);
+        }
+        println!("=> {:?}", output_format(&cli, print));
+        return Ok(());
+    }
+
+    // What the input is called in parse errors, or the input files when there's more than one
+    let mut file = "<stdin>".to_string();
+    let mut files = Vec::new();
+    // Held until the file edited in place has been replaced
+    let mut lock = None;
+    let mut input: Box<dyn Read> = if cli.null_input {
+        Box::new(io::empty())
+    } else if let Some(path) = &cli.follow {
+        file = path.clone();
+        Box::new(io::BufReader::new(follow::Follow::open(path)?))
+    } else if cli.paste {
+        file = "<clipboard>".to_string();
+        Box::new(io::Cursor::new(clipboard::paste()?))
+    } else if let Some(path) = &cli.in_place {
+        file = path.clone();
+        let locked = in_place::Lock::acquire(path, cli.dry_run, cli.wait, cli.no_clobber)?;
+        let f = locked.file()?;
+        lock = Some(locked);
+        Box::new(io::BufReader::new(f))
+    } else if let Some(Sub::Alias { command: AliasCommand::Run { file: Some(path), .. } }) = &cli.subcommand {
+        file = path.clone();
+        let f = File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+        Box::new(io::BufReader::new(f))
+    } else if !cli.files.is_empty() {
+        if let [path] = cli.files.as_slice() {
+            file = path.clone();
+            let f = File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+            Box::new(io::BufReader::new(f))
+        } else {
+            files = std::mem::take(&mut cli.files);
+            Box::new(io::empty())
+        }
+    } else if io::stdin().is_terminal() {
+        if cli.command.is_empty() {
+            Cli::command().print_help()?;
+            return Ok(());
+        } else {
+            let filename = cli.command.remove(0);
+            // Further words naming files are inputs too, read in turn, as from a glob
+            while cli.command.first().is_some_and(|word| is_input_file(word)) {
+                files.push(cli.command.remove(0));
+            }
+            if !files.is_empty() {
+                files.insert(0, filename);
+                Box::new(io::empty())
+            } else {
+                let f = File::open(&filename).map_err(|e| anyhow!("Failed to open {}: {}", filename, e))?;
+                file = filename;
+                Box::new(io::BufReader::new(f))
+            }
+        }
+    } else {
+        let stdin = io::stdin();
+        Box::new(stdin.lock())
+    };
+
+    let command = cli.command.join(" ");
+    let (mut stream, print) = evaluate_command(&command)?;
+    // Commands from the first that works on the whole stream of results, like sort_by, are run over every
+    // result together, once the rest of the query has been run on each document
+    let stages = stream.split_off(stages::start(&stream));
+    if !stages.is_empty() && (cli.with_filename || cli.keyed) {
+        return Err(anyhow!("-H and --keyed can't say which file results came from after a command that works on the whole stream of them, like sort_by"));
+    }
+    let mut tables = HashMap::new();
+    for pair in cli.slurpfile.chunks(2) {
+        tables.insert(pair[0].clone(), inputs::records(&pair[1], false)?.collect::<Result<Vec<_>>>()?);
+    }
+    jq::bind_tables(&stream, &tables)?;
+    let mut print = output_format(&cli, print);
+    let profile = cli.profile.then(|| profile::Profile::new(&stream));
+    let options = Options {
+        mode: if cli.strict { Mode::Strict } else { Mode::Lenient },
+        map_keys: !cli.no_map_keys,
+    };
+
+    // Limits, decoding and the like apply to each input file on its own
+    let prepare = |mut input: Box<dyn Read>| -> Result<Box<dyn Read>> {
+        if let Some(max) = cli.max_size {
+            input = Box::new(limits::Limited::new(input, max));
+        }
+        if !cli.null_input {
+            input = encoding::decode(input, cli.encoding)?;
+        }
+        if cli.envsubst {
+            let mut text = String::new();
+            input.read_to_string(&mut text)?;
+            input = Box::new(io::Cursor::new(envsubst::expand(&text, |name| std::env::var(name).ok())?));
+        }
+        if cli.bulk {
+            let mut buf = String::new();
+            let mut read = || input.read_to_string(&mut buf);
+            match &profile {
+                Some(profile) => profile.time_parse(read),
+                None => read(),
+            }?;
+            input = Box::new(io::Cursor::new(buf));
+        }
+        Ok(input)
+    };
+    input = prepare(input)?;
+
+    let unbuffered = cli.flushes_each_document();
+
+    // Pulling a path out of each line of NDJSON copies it from the input, rather than building every document
+    // only to print part of it
+    let passthrough = print == PrintCommand::Compact && opts.is_plain() && options.mode == Mode::Lenient
+        && !stream.is_empty() && stream.iter().all(|c| matches!(c, StreamCommand::Key(_) | StreamCommand::Index(_))) && stages.is_empty()
+        && !cli.transforms_input() && !cli.transforms_output() && files.is_empty() && !unbuffered;
+    if passthrough {
+        let mut reader = io::BufReader::new(input);
+        let mut out = output(&cli)?;
+        let rest = passthrough::run(&mut reader, &stream, &mut out, |out, doc| {
+            for v in apply_stream(doc, &stream, options) {
+                apply_print(out, v?, &print, &opts)?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        match rest {
+            // The rest isn't NDJSON: the line read goes back in front of it, and it's read as usual
+            Some(first) => input = Box::new(io::Cursor::new(first).chain(reader)),
+            None => return Ok(()),
+        }
+    }
+
+    // Anything that needs whole documents, or groups results by document, reads them whole
+    let range = streamed_range(&stream)
+        .filter(|_| !cli.transforms_input() && print != PrintCommand::Json && !matches!(print, PrintCommand::Table(_)));
+    if range.is_some() {
+        // Elements come wrapped in arrays of their own, which the first command unwraps, so a range has
+        // already been applied
+        if let StreamCommand::Range(start, end) = &mut stream[0] {
+            (*start, *end) = (None, None);
+        }
+    }
+    // --fast builds only the part of each document the query reaches, unless something else looks at all of it,
+    // or --trace prints each step's input
+    let fast_path = if cli.transforms_documents() || cli.trace { Vec::new() } else { fast::path(&stream) };
+    let documents = |mut input: Box<dyn Read>, file: &str| -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+        let docs: Box<dyn Iterator<Item=Result<Value>>> = if cli.ungron {
+            let mut buf = String::new();
+            input.read_to_string(&mut buf)?;
+            Box::new(once(gron::ungron(&buf)))
+        } else if cli.raw_input0 {
+            Box::new(io::BufRead::split(io::BufReader::new(input), 0).map(|chunk| {
+                let chunk = String::from_utf8(chunk?).context("Input separated by NUL isn't UTF-8")?;
+                Ok(Value::String(chunk))
+            }))
+        } else if cli.stream && cli.yaml {
+            // YAML documents are parsed whole, then broken into events
+            Box::new(yaml_documents(input, file, cli.yaml_1_1)?.flat_map(|v| {
+                match v {
+                    Ok(v) => events::of_value(&v).into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            }))
+        } else if cli.stream {
+            Box::new(events::Events::new(input))
+        } else if cli.skip_errors && cli.yaml {
+            let mut text = String::new();
+            input.read_to_string(&mut text)?;
+            skip_errors::yaml_documents(text, file, cli.yaml_1_1)
+        } else if cli.skip_errors {
+            skip_errors::json_lines(io::BufReader::new(input), file)
+        } else if cli.yaml {
+            yaml_documents(input, file, cli.yaml_1_1)?
+        } else if let Some(format) = cli.binary {
+            binary::documents(input, format)?
+        } else if cli.fast {
+            fast::documents(io::BufReader::new(input), fast_path.clone())?
+        } else if let Some(range) = range {
+            Box::new(elements::Elements::new(input, range))
+        } else {
+            // Parse errors say which document they're in, and show the input where it went wrong
+            let recorder = input_error::Recorder::new(input, file);
+            let docs: Box<dyn Iterator<Item=Result<Value>>> = match cli.dupes {
+                Some(dupes) => dupes::documents(recorder.clone(), dupes),
+                None => Box::new(serde_json::Deserializer::from_reader(recorder.clone()).into_iter::<Value>().map(|v| {
+                    v.map_err(anyhow::Error::from)
+                })),
+            };
+            Box::new(docs.enumerate().map(move |(i, v)| v.map_err(|e| recorder.json(i + 1, e))))
+        };
+        Ok(match cli.max_depth {
+            Some(max) => Box::new(docs.map(move |v| limits::check_depth(v?, max))),
+            None => docs,
+        })
+    };
+
+    // The file that results come from, for --with-filename and --keyed
+    let source: Rc<RefCell<Rc<str>>> = Rc::new(RefCell::new(file.as_str().into()));
+    let mut front_matter = None;
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = if cli.null_input {
+        Box::new(once(Ok(Value::Null)))
+    } else if cli.front_matter {
+        if !files.is_empty() {
+            return Err(anyhow!("--front-matter reads a single file"));
+        }
+        let mut buf = String::new();
+        input.read_to_string(&mut buf)?;
+        let doc = front_matter::split(&buf);
+        let metadata = front_matter::parse(&doc);
+        front_matter = Some((doc.format, doc.body.to_string()));
+        Box::new(once(metadata))
+    } else if !files.is_empty() {
+        Box::new(inputs::Files::new(files, source.clone(), |path| {
+            let f = File::open(path).map_err(|e| anyhow!("Failed to open: {}", e))?;
+            documents(prepare(Box::new(io::BufReader::new(f)))?, path)
         }))
     } else {
-        Box::new(serde_json::Deserializer::from_reader(input).into_iter::<Value>().map(|v| {
-            v.map_err(anyhow::Error::from)
+        documents(input, &file)?
+    };
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = if cli.slurp {
+        Box::new(once(deserializer.collect::<Result<Vec<_>>>().map(Value::Array)))
+    } else {
+        deserializer
+    };
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = match &cli.patch {
+        Some(path) => {
+            let ops = load_document(path)?;
+            Box::new(deserializer.map(move |v| patch::apply(v?, &ops)))
+        }
+        None => deserializer,
+    };
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = if cli.k8s_secrets {
+        Box::new(deserializer.map(|v| {
+            let mut v = v?;
+            k8s::decode_secrets(&mut v)?;
+            Ok(v)
         }))
+    } else {
+        deserializer
+    };
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = match &cli.merge_patch {
+        Some(path) => {
+            let patch = load_document(path)?;
+            Box::new(deserializer.map(move |v| {
+                let mut v = v?;
+                merge_patch::apply(&mut v, &patch);
+                Ok(v)
+            }))
+        }
+        None => deserializer,
+    };
+    let deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = if cli.queries.is_empty() {
+        deserializer
+    } else {
+        let queries = queries::Queries::parse(&cli.queries)?;
+        Box::new(deserializer.map(move |v| queries.eval(&v?, options)))
+    };
+
+    // Results are grouped by the input document they came from. With --limit, no more input is read once
+    // enough results have been produced.
+    let profiling = profile.as_ref();
+    let mut deserializer: Box<dyn Iterator<Item=Result<Value>> + '_> = match profiling {
+        Some(profile) => Box::new(profile.time_iter(deserializer)),
+        None => deserializer,
+    };
+    // --limit applies to the results of the whole stream commands, if there are any, once they've run
+    let limit = cli.limit.unwrap_or(usize::MAX);
+    let mut remaining = if stages.is_empty() { limit } else { usize::MAX };
+    let lang = match (&cli.jsonpath, &cli.pointer, &cli.jmespath) {
+        (Some(expr), _, _) => Some(lang::Query::json_path(expr)?),
+        (_, Some(expr), _) => Some(lang::Query::pointer(expr)?),
+        (_, _, Some(expr)) => Some(lang::Query::jmespath(expr)?),
+        _ => None,
+    };
+    let current_file = source.clone();
+    let results = std::iter::from_fn(move || {
+        if remaining == 0 {
+            return None;
+        }
+        let obj = match deserializer.next()? {
+            Ok(obj) => obj,
+            Err(e) => return Some(Err(e)),
+        };
+        let objs = match &lang {
+            Some(query) => match query.eval(&obj) {
+                Ok(objs) => objs,
+                Err(e) => return Some(Err(e)),
+            },
+            None => vec![obj],
+        };
+        let mut doc = Vec::new();
+        for obj in objs {
+            let results = match profiling {
+                Some(profile) => profile.apply_stream(obj, &stream, options),
+                None if cli.trace => trace::apply_stream_traced(obj, &stream, options),
+                None => apply_stream(obj, &stream, options),
+            };
+            let limit = remaining - doc.len();
+            for v in results.take(limit) {
+                let mut v = match v {
+                    Ok(v) => v,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                if cli.sort_keys {
+                    sort_keys(&mut v);
+                }
+                if cli.keyed {
+                    let file: &str = &current_file.borrow();
+                    v = serde_json::json!({"file": file, "value": v});
+                }
+                doc.push(v);
+            }
+        }
+        remaining -= doc.len();
+        Some(Ok(doc))
+    });
+    let results: Box<dyn Iterator<Item=Result<Vec<Value>>> + '_> = if stages.is_empty() {
+        Box::new(results)
+    } else {
+        let values = results.flat_map(|doc| -> Box<dyn Iterator<Item=Result<Value>>> {
+            match doc {
+                Ok(doc) => Box::new(doc.into_iter().map(Ok)),
+                Err(e) => Box::new(once(Err(e))),
+            }
+        });
+        let values = stages::apply(Box::new(values), &stages, options, cli.sort_memory);
+        Box::new(values.take(limit).map(|v| v.map(|v| vec![v])))
     };
+    let mut tee = cli.tee.as_deref().map(|path| tee::Tee::create(path, cli.tee_format)).transpose()?;
+    let opts_ref = &opts;
+    let mut results = results.map(move |doc| -> Result<Vec<Value>> {
+        let doc = doc?;
+        if let Some(tee) = &mut tee {
+            tee.write(&doc, opts_ref)?;
+        }
+        Ok(doc)
+    });
+
+    if let (Some(dest), Some((format, body))) = (&cli.in_place, &front_matter) {
+        let mut all = Vec::new();
+        for doc in results {
+            all.extend(doc?);
+        }
+        let [obj] = <[Value; 1]>::try_from(all)
+            .map_err(|r| anyhow!("Front matter must be a single result, but the query produced {}", r.len()))?;
+        let out = front_matter::render(*format, &obj, body, &opts.yaml)?;
+        return finish_in_place(&cli, &opts, dest, lock.as_ref(), out.as_bytes());
+    }
+
+    // Output goes straight into the temporary file that replaces the input, so files of any size can be edited,
+    // except where all of it is needed first: for a diff, or to edit a YAML document in place
+    if let (Some(dest), false, false) = (&cli.in_place, cli.yaml, cli.dry_run) {
+        let mut file = in_place::Replacement::create(dest)?;
+        for doc in results {
+            for mut obj in doc? {
+                if cli.k8s_secrets {
+                    k8s::encode_secrets(&mut obj);
+                }
+                write_document(&mut file, &obj, false, &cli, &opts)?;
+            }
+        }
+        if let Some(lock) = &lock {
+            lock.unchanged()?;
+        }
+        return file.commit(cli.backup.as_deref());
+    }
 
     if let Some(dest) = &cli.in_place {
-        let mut file = File::create(&dest).unwrap();
-        for obj in deserializer {
-            let obj = obj?;
-            let mut it = apply_stream(obj, &stream).peekable();
-            for obj in it {
-                if cli.yaml {
-                    serde_yaml::to_writer(&mut file, &obj).unwrap();
-                } else if cli.json_output {
-                    serde_json::to_writer(&mut file, &obj).unwrap();
-                } else {
-                    serde_json::to_writer_pretty(&mut file, &obj).unwrap();
+        let mut objs = Vec::new();
+        for doc in results {
+            for mut obj in doc? {
+                if cli.k8s_secrets {
+                    k8s::encode_secrets(&mut obj);
+                }
+                objs.push(obj);
+            }
+        }
+        // Edit a YAML document rather than rewriting it, so comments and formatting outside the changes survive
+        if let (true, [obj]) = (cli.yaml, objs.as_slice()) {
+            let original = std::fs::read_to_string(dest)?;
+            if let Some(edited) = yaml_edit::edit(&original, obj, &opts.yaml) {
+                return finish_in_place(&cli, &opts, dest, lock.as_ref(), edited.as_bytes());
+            }
+        }
+        let mut file = Vec::new();
+        for obj in &objs {
+            write_document(&mut file, obj, cli.yaml, &cli, &opts)?;
+        }
+        return finish_in_place(&cli, &opts, dest, lock.as_ref(), &file);
+    }
+
+    if let (Some(key), Some(dir)) = (&cli.split_by, &cli.out_dir) {
+        let ext = if cli.yaml_output {
+            "yaml"
+        } else if cli.toml_output {
+            "toml"
+        } else if cli.compact || cli.jsonl {
+            "ndjson"
+        } else {
+            "json"
+        };
+        let mut split = split::Split::new(dir, key, ext, options)?;
+        for doc in results {
+            for obj in doc? {
+                let file = split.writer(&obj)?;
+                // Each result is a document of its own in a partition holding several
+                if cli.yaml_output {
+                    file.write_all(b"---\n")?;
                 }
+                write_document(file, &obj, cli.yaml_output, &cli, &opts)?;
             }
         }
+        return split.finish();
+    }
+
+    if cli.copy {
+        let mut text = Vec::new();
+        let mut count = 0;
+        for doc in results {
+            for obj in doc? {
+                write_document(&mut text, &obj, cli.yaml_output, &cli, &opts)?;
+                count += 1;
+            }
+        }
+        clipboard::copy(&text)?;
+        eprintln!("Copied {} {} to the clipboard", count, if count == 1 { "result" } else { "results" });
         return Ok(());
     }
 
-    for obj in deserializer {
-        let obj = obj?;
-        let mut it = apply_stream(obj, &stream).peekable();
-        let Some(first) = it.next() else {
-            continue;
+    if cli.interactive {
+        let mut all = Vec::new();
+        for doc in results {
+            all.extend(doc?);
+        }
+        let value = if all.len() == 1 { all.remove(0) } else { Value::Array(all) };
+        return explore::run(&value);
+    }
+
+    if cli.pick {
+        let mut all = Vec::new();
+        for doc in results {
+            all.extend(doc?);
+        }
+        let Some(selector) = pick::run(&all)? else {
+            return Ok(());
         };
-        if print == PrintCommand::Json && it.peek().is_some() {
-            let mut vec = Vec::new();
-            vec.push(first);
-            vec.extend(it);
-            apply_print(Value::Array(vec), &print);
-        } else {
-            print.add_headers(&first);
-            apply_print(first, &print);
-            print.turn_off_headers();
-            for obj in it {
-                apply_print(obj, &print);
+        eprintln!("{} '{}'", env!("CARGO_BIN_NAME"), selector);
+        let (commands, _) = evaluate_command(&selector)?;
+        for obj in all {
+            for obj in apply_stream(obj, &commands, options) {
+                apply_print(&mut stdout(), obj?, &print, &opts)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(url) = &cli.post {
+        let poster = post::Poster::new(url, &cli.header)?;
+        let mut send = |value: &Value| -> Result<()> {
+            let body = poster.send(value)?;
+            if !body.is_empty() {
+                let mut out = stdout().lock();
+                out.write_all(body.as_bytes())?;
+                if !body.ends_with('\n') {
+                    writeln!(out)?;
+                }
+            }
+            Ok(())
+        };
+        if cli.wrap_array {
+            let mut all = Vec::new();
+            for doc in results {
+                all.extend(doc?);
+            }
+            return send(&Value::Array(all));
+        }
+        for doc in results {
+            for obj in doc? {
+                send(&obj)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(path) = &cli.sqlite_out {
+        let mut sink = sqlite::SqliteSink::open(path, &cli.table)?;
+        for doc in results {
+            for obj in doc? {
+                sink.push(obj)?;
+            }
+        }
+        return sink.finish();
+    }
+
+    if let Some(path) = &cli.xlsx_out {
+        let mut rows = Vec::new();
+        for doc in results {
+            for obj in doc? {
+                match obj {
+                    Value::Array(arr) => rows.extend(arr),
+                    z => rows.push(z),
+                }
+            }
+        }
+        return xlsx::write(path, &rows);
+    }
+
+    if let Some(path) = &cli.parquet_out {
+        let mut sink = parquet_out::ParquetSink::create(path, cli.parquet_sample)?;
+        for doc in results {
+            for obj in doc? {
+                sink.push(obj)?;
+            }
+        }
+        return sink.finish();
+    }
+
+    if let Some(format) = cli.binary_output {
+        let mut out = output(&cli)?;
+        for doc in results {
+            for obj in doc? {
+                binary::write(&mut out, &obj, format)?;
+            }
+        }
+        return Ok(out.flush()?);
+    }
+
+    let mut out = output(&cli)?;
+    // Whether the last result printed was neither false nor null, or None if nothing was, for --exit-status
+    let mut truthy = None;
+    if cli.wrap_array {
+        let mut all = Vec::new();
+        for doc in results {
+            all.extend(doc?);
+        }
+        truthy = Some(true);
+        let arr = Value::Array(all);
+        print.add_headers([&arr]);
+        apply_print(&mut out, arr, &print, &opts)?;
+    } else if let Some(separator) = &cli.join {
+        let mut first = true;
+        for doc in results {
+            for obj in doc? {
+                if !first {
+                    out.write_all(separator.as_bytes())?;
+                }
+                first = false;
+                truthy = Some(is_truthy(&obj));
+                match &obj {
+                    Value::String(s) => out.write_all(s.as_bytes())?,
+                    z => json::write_json(&mut out, z, &opts, false, ColorMode::Off)?,
+                }
+            }
+            if unbuffered {
+                out.flush()?;
+            }
+        }
+        if !first {
+            writeln!(out)?;
+        }
+    } else {
+        // Tabular output without explicit columns samples a prefix of the rows before printing anything,
+        // so that the header covers keys the first row lacks
+        // Each document's results are kept with the file it came from, which has moved on by the time sampled
+        // ones are printed
+        let mut results = results.map(|doc| doc.map(|doc| (source.borrow().clone(), doc)));
+        let mut sampled = Vec::new();
+        if print.needs_headers() {
+            let mut rows = 0;
+            while rows < HEADER_SAMPLE_ROWS {
+                let Some(doc) = results.next() else {
+                    break;
+                };
+                let (file, doc) = doc?;
+                rows += doc.iter().map(|v| v.as_array().map_or(1, Vec::len)).sum::<usize>();
+                sampled.push((file, doc));
+            }
+            print.add_headers(sampled.iter().flat_map(|(_, doc)| doc));
+        }
+
+        for doc in sampled.into_iter().map(Ok).chain(results) {
+            let (file, doc) = doc?;
+            let mut it = doc.into_iter().peekable();
+            let Some(first) = it.next() else {
+                continue;
+            };
+            let prefix = match (cli.with_filename, opts.color) {
+                (false, _) => String::new(),
+                (true, false) => format!("{}:", file),
+                (true, true) => format!("\x1b[35m{}\x1b[0m:", file),
+            };
+            let mut prefixed;
+            let w: &mut dyn Write = if cli.with_filename {
+                prefixed = inputs::Prefixed::new(&mut out, prefix.as_bytes());
+                &mut prefixed
+            } else {
+                &mut out
+            };
+            // Tables have to see every row up front to size their columns
+            if (print == PrintCommand::Json || matches!(print, PrintCommand::Table(_))) && it.peek().is_some() {
+                let mut vec = Vec::new();
+                vec.push(first);
+                vec.extend(it);
+                let arr = Value::Array(vec);
+                print.add_headers([&arr]);
+                truthy = Some(true);
+                apply_print(&mut *w, arr, &print, &opts)?;
+            } else {
+                print.add_headers([&first]);
+                truthy = Some(is_truthy(&first));
+                apply_print(&mut *w, first, &print, &opts)?;
+                print.turn_off_headers();
+                for obj in it {
+                    truthy = Some(is_truthy(&obj));
+                    apply_print(&mut *w, obj, &print, &opts)?;
+                }
+            }
+            if unbuffered {
+                out.flush()?;
             }
         }
     }
+    out.flush()?;
+    if cli.exit_status && truthy != Some(true) {
+        return Err(Exit(if truthy.is_none() { EXIT_NO_OUTPUT } else { EXIT_FALSY }).into());
+    }
     Ok(())
 }
 
@@ -527,41 +2322,397 @@ fn main() -> Result<()> {
 mod tests {
     use super::*;
 
+    /// What jq writes when run with `args` on `input`
+    fn jq(args: &[&str], input: &str) -> Result<String> {
+        let dir = tempfile::tempdir()?;
+        let (file, output) = (dir.path().join("input.json"), dir.path().join("output"));
+        std::fs::write(&file, input)?;
+        let mut argv: Vec<String> = ["jq", "-o", output.to_str().unwrap()].into_iter().chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        argv.extend(["--".to_string(), file.to_str().unwrap().to_string()]);
+        run(argv)?;
+        // Subcommands that only check their files print nothing, and never create the output file
+        Ok(std::fs::read_to_string(output).unwrap_or_default())
+    }
+
+    #[test]
+    fn test_compact() {
+        let input = "{\n  \"a\": [1, {\"b\": \"x y\"}]\n}\n{\"a\": [2]}";
+        assert_eq!(jq(&["-c"], input).unwrap(), "{\"a\":[1,{\"b\":\"x y\"}]}\n{\"a\":[2]}\n");
+        // Every result is a line of its own, where -J would collect them into an array
+        assert_eq!(jq(&["-c", "a[]"], input).unwrap(), "1\n{\"b\":\"x y\"}\n2\n");
+    }
+
+    #[test]
+    fn test_raw_output0() {
+        let input = r#"{"names": ["a b", "c\nd", [1]], "bad": "a\u0000b"}"#;
+        assert_eq!(jq(&["--raw-output0", "names[]"], input).unwrap(), "a b\0c\nd\0[\n  1\n]\0");
+        assert!(jq(&["--raw-output0", "bad"], input).is_err());
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let input = "{\"a\": [1, {\"b\": 2}]}\n{\"a\": [\"x\"]}";
+        // One line for every result of every document, strings quoted, even where -J would collect them
+        assert_eq!(jq(&["--jsonl", "-J", "a[]"], input).unwrap(), "1\n{\"b\":2}\n\"x\"\n");
+        assert_eq!(jq(&["--jsonl"], "[3,\n 4]\n[]").unwrap(), "[3,4]\n[]\n");
+    }
+
+    #[test]
+    fn test_color_flags() {
+        // -C colors output that isn't going to a terminal, and -M leaves even compact output plain
+        assert!(jq(&["-C"], "{\"a\": 1}").unwrap().starts_with("\x1b[1m{\x1b[0m\n"));
+        assert_eq!(jq(&["-M", "-c"], "{\"a\": 1}").unwrap(), "{\"a\":1}\n");
+    }
+
+    #[test]
+    fn test_seq_output() {
+        let input = r#"{"a": ["x\ny", {"b": 2}]}"#;
+        assert_eq!(jq(&["--seq-output", "a[]"], input).unwrap(), "\x1e\"x\\ny\"\n\x1e{\"b\":2}\n");
+    }
+
+    #[test]
+    fn test_slurp() {
+        let input = "{\"n\": 3}\n{\"n\": 1}\n{\"n\": 2}";
+        assert_eq!(jq(&["-s", "len"], input).unwrap(), "3\n");
+        assert_eq!(jq(&["-s", "-c"], input).unwrap(), "[{\"n\":3},{\"n\":1},{\"n\":2}]\n");
+        assert_eq!(jq(&["-s", "-c"], "").unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn test_exit_status() {
+        let status = |input| jq(&["-e", "a[]"], input).err().map(|e| e.downcast::<Exit>().unwrap().0);
+        assert_eq!(status(r#"{"a": [null, 0]}"#), None);
+        assert_eq!(status(r#"{"a": [1, false]}"#), Some(EXIT_FALSY));
+        assert_eq!(status(r#"{"a": [null]}"#), Some(EXIT_FALSY));
+        assert_eq!(status(r#"{"a": []}"#), Some(EXIT_NO_OUTPUT));
+    }
+
+    #[test]
+    fn test_diff_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = dir.path().join("other.json");
+        std::fs::write(&other, r#"{"a": 1, "b": [2]}"#).unwrap();
+        let other = other.to_str().unwrap();
+        assert_eq!(jq(&["-c", "diff", "--patch", other], r#"{"b": [2], "a": 1}"#).unwrap(), "[]\n");
+        let changed = jq(&["-c", "diff", "--patch", other], r#"{"a": 1}"#).unwrap_err();
+        assert_eq!(changed.downcast::<Exit>().unwrap().0, EXIT_FALSY);
+    }
+
+    #[test]
+    fn test_validate_status() {
+        assert_eq!(jq(&["validate"], "{\"a\": [1]}\n{}").unwrap(), "");
+        let invalid = jq(&["validate"], "{\"a\": [1}").unwrap_err();
+        assert_eq!(invalid.downcast::<Exit>().unwrap().0, EXIT_FALSY);
+    }
+
+    #[test]
+    fn test_fmt_check_status() {
+        assert_eq!(jq(&["fmt", "--check"], "{\n  \"a\": [\n    1\n  ]\n}\n").unwrap(), "");
+        let unformatted = jq(&["fmt", "--check"], "{\"a\": [1]}").unwrap_err();
+        assert_eq!(unformatted.downcast::<Exit>().unwrap().0, EXIT_FALSY);
+        let invalid = jq(&["fmt", "--check"], "{\"a\": [1}").unwrap_err();
+        assert_eq!(invalid.downcast::<Exit>().unwrap().0, EXIT_FALSY);
+    }
+
+    #[test]
+    fn test_limit() {
+        // Input after the last result needed isn't read, so the broken document at the end is never parsed
+        let input = "{\"a\": [1, 2]}\n{\"a\": [3]}\n{";
+        assert_eq!(jq(&["--limit", "2", "-c", "a[]"], input).unwrap(), "1\n2\n");
+        assert_eq!(jq(&["--limit", "3", "-c", "a[]"], input).unwrap(), "1\n2\n3\n");
+        assert!(jq(&["--limit", "4", "-c", "a[]"], input).is_err());
+    }
+
+    #[test]
+    fn test_wrap_array_and_join() {
+        let input = "{\"a\": [\"x\", 1]}\n{\"a\": [{\"b\": null}]}";
+        assert_eq!(jq(&["--wrap-array", "-c", "a[]"], input).unwrap(), "[\"x\",1,{\"b\":null}]\n");
+        assert_eq!(jq(&["--wrap-array", "-c", "a[]"], "{\"a\": []}").unwrap(), "[]\n");
+        // Strings are joined raw, and other values as compact JSON
+        assert_eq!(jq(&["--join", ", ", "a[]"], input).unwrap(), "x, 1, {\"b\":null}\n");
+        assert_eq!(jq(&["--join", ", ", "a[]"], "{\"a\": []}").unwrap(), "");
+    }
+
+    #[test]
+    fn test_unbuffered() {
+        assert!(Cli::parse_from(["jq", "--unbuffered", "-o", "out.json"]).flushes_each_document());
+        assert!(Cli::parse_from(["jq", "--follow", "log.ndjson", "-o", "out.json"]).flushes_each_document());
+        assert!(!Cli::parse_from(["jq", "-o", "out.json"]).flushes_each_document());
+        // Flushing as it goes doesn't change what's written
+        let input = "{\"a\": [1, 2]}\n{\"a\": [3]}";
+        assert_eq!(jq(&["--unbuffered", "-c", "a[]"], input).unwrap(), jq(&["-c", "a[]"], input).unwrap());
+    }
+
     #[test]
-    fn test_evaluate_command() {
-        let (commands, _) = evaluate_command("foo");
-        assert_eq!(commands, vec![StreamCommand::Key("foo".to_string())]);
-
-        let (commands, _) = evaluate_command(".keys");
-        assert_eq!(commands, vec![StreamCommand::Key("keys".to_string())]);
-
-        let (commands, _) = evaluate_command(".a.b.c.");
-        assert_eq!(commands, vec![
-            StreamCommand::Key("a".to_string()),
-            StreamCommand::Key("b".to_string()),
-            StreamCommand::Key("c".to_string()),
+    fn test_markdown_row() {
+        let obj = serde_json::json!({"a": "x|y", "b": null, "c": 3});
+        let selectors = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let rows = table_rows(&obj, &compile_selectors(&selectors).unwrap()).unwrap();
+        assert_eq!(markdown_row(markdown_cells(&rows[0])), "| x\\|y |  | 3 |");
+
+        let (commands, print) = evaluate_command("mdfile").unwrap();
+        assert_eq!(commands, vec![StreamCommand::Key("mdfile".to_string())]);
+        assert_eq!(print, PrintCommand::Pretty);
+    }
+
+    #[test]
+    fn test_nested_selectors() {
+        let obj = serde_json::json!([
+            {"user": {"name": "a"}, "metrics": [{"value": 1}], "dotted.key": true},
+            {"user": null, "metrics": []},
         ]);
+        let selectors = vec!["user.name".to_string(), "metrics[0].value".to_string(), "dotted.key".to_string()];
+        let rows: Vec<Value> = table_rows(&obj, &compile_selectors(&selectors).unwrap()).unwrap()
+            .into_iter()
+            .map(|row| row.into_iter().map(Cow::into_owned).collect())
+            .collect();
+        assert_eq!(rows, vec![serde_json::json!(["a", 1, true]), serde_json::json!([null, null, null])]);
+    }
+
+    #[test]
+    fn test_table_scalars() {
+        let obj = serde_json::json!([{"a": 1}, 2, "x", [3], {"b": 4}]);
+        let (_, mut print) = evaluate_command("table").unwrap();
+        print.add_headers([&obj]);
+        let mut out = Vec::new();
+        apply_print(&mut out, obj, &print, &PrintOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a  b  value\n─  ─  ─────\n1     \n      2\n      x\n      [3]\n   4  \n");
+    }
 
-        let (commands, print) = evaluate_command("foo, keys");
-        assert_eq!(commands, vec![StreamCommand::Key("foo".to_string())]);
-        assert_eq!(print, PrintCommand::Keys);
+    #[test]
+    fn test_render_template() {
+        let obj = serde_json::json!({"name": "bob", "email": "b@x.io", "tags": ["a"], "n": null});
+        assert_eq!(render_template("{name} <{email}>", &obj).unwrap(), "bob <b@x.io>");
+        assert_eq!(render_template("{{{tags[0]}}} {tags}{n}", &obj).unwrap(), r#"{a} ["a"]"#);
+        assert!(render_template("{name", &obj).is_err());
     }
 
     #[test]
-    fn test_eval_command() {
-        let (commands, _) = evaluate_command("[0..5]");
-        assert_eq!(commands, vec![StreamCommand::Range(Some(0), Some(5))]);
-        let (commands, _) = evaluate_command("[..5]");
-        assert_eq!(commands, vec![StreamCommand::Range(None, Some(5))]);
-        let (commands, _) = evaluate_command("[..-5]");
-        assert_eq!(commands, vec![StreamCommand::Range(None, Some(-5))]);
-        let (commands, _) = evaluate_command("[-5..]");
-        assert_eq!(commands, vec![StreamCommand::Range(Some(-5), None)]);
-        let (commands, _) = evaluate_command("..5");
-        assert_eq!(commands, vec![StreamCommand::Range(None, Some(5))]);
-        let (commands, _) = evaluate_command("5..");
-        assert_eq!(commands, vec![StreamCommand::Range(Some(5), None)]);
-        let (commands, _) = evaluate_command("-5..");
-        assert_eq!(commands, vec![StreamCommand::Range(Some(-5), None)]);
+    fn test_sort_keys() {
+        let mut value = serde_json::json!({"b": 1, "a": [{"d": 1, "c": 2}]});
+        sort_keys(&mut value);
+        assert_eq!(value.to_string(), r#"{"a":[{"c":2,"d":1}],"b":1}"#);
+    }
+
+    #[test]
+    fn test_reformat() {
+        let cli = Cli::parse_from(["jq", "-S"]);
+        let opts = PrintOptions::new(&cli);
+        let json = reformat(r#"{"b":1,"a":[]}"#, validate::Format::Json, &cli, &opts).unwrap();
+        assert_eq!(json, "{\n  \"a\": [],\n  \"b\": 1\n}\n");
+        let yaml = reformat("b:   1\n---\na: x", validate::Format::Yaml, &cli, &opts).unwrap();
+        assert_eq!(yaml, "b: 1\n---\na: x\n");
+    }
+
+    #[test]
+    fn test_output_format() {
+        let cli = Cli::parse_from(["jq", "-o", "out.YML"]);
+        assert_eq!(output_format(&cli, PrintCommand::Pretty), PrintCommand::Yaml);
+        assert_eq!(output_format(&cli, PrintCommand::Keys), PrintCommand::Keys);
+        let cli = Cli::parse_from(["jq", "-o", "out.yaml", "-c"]);
+        assert_eq!(output_format(&cli, PrintCommand::Pretty), PrintCommand::Compact);
+        let cli = Cli::parse_from(["jq", "-o", "out.csv"]);
+        assert!(output_format(&cli, PrintCommand::Pretty).needs_headers());
+        let cli = Cli::parse_from(["jq", "-o", "out.txt"]);
+        assert_eq!(output_format(&cli, PrintCommand::Pretty), PrintCommand::Pretty);
+    }
+
+    #[test]
+    fn test_color() {
+        assert!(PrintOptions::new(&Cli::parse_from(["jq", "--color", "always", "-o", "out.json"])).color);
+        assert!(PrintOptions::new(&Cli::parse_from(["jq", "-C"])).color);
+        assert!(!PrintOptions::new(&Cli::parse_from(["jq", "--color", "never"])).color);
+        assert!(!PrintOptions::new(&Cli::parse_from(["jq", "--color", "auto", "-o", "out.json"])).color);
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(shell_quote("it's $HOME"), r#"'it'\''s $HOME'"#);
+        assert_eq!(shell_ident("db.host-name"), "db_host_name");
+        assert_eq!(shell_ident("1st"), "_1st");
+    }
+
+    #[test]
+    fn test_escape_tsv() {
+        assert_eq!(escape_tsv("plain"), "plain");
+        assert_eq!(escape_tsv("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn test_broken_pipe() {
+        let pipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(is_broken_pipe(&anyhow::Error::from(pipe()).context("writing")));
+        assert!(is_broken_pipe(&serde_json::Error::io(pipe()).into()));
+        assert!(!is_broken_pipe(&anyhow!("Broken pipe")));
+    }
+
+    /// Output whose reader has gone away
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_print_broken_pipe() {
+        let obj = serde_json::json!({"a": "x", "b": [1]});
+        for command in ["", "keys", "len", "csv", "tsv", "md", "table", "tree", "paths", "gron", "sh"] {
+            let (_, mut print) = evaluate_command(command).unwrap();
+            print.add_headers([&obj]);
+            let e = apply_print(&mut Closed, obj.clone(), &print, &PrintOptions::default()).unwrap_err();
+            assert!(is_broken_pipe(&e), "{}: {:#}", command, e);
+        }
+        for print in [PrintCommand::Compact, PrintCommand::Json, PrintCommand::Yaml, PrintCommand::Seq] {
+            let e = apply_print(&mut Closed, obj.clone(), &print, &PrintOptions::default()).unwrap_err();
+            assert!(is_broken_pipe(&e), "{:?}: {:#}", print, e);
+        }
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let query = evaluate_command("a[").unwrap_err();
+        assert_eq!(exit_code(&anyhow::Error::from(query).context("in --query")), EXIT_QUERY);
+        let input = serde_json::from_str::<Value>("{").unwrap_err();
+        assert_eq!(exit_code(&input.into()), EXIT_INPUT);
+        let utf8 = String::from_utf8(vec![0xff]).context("not UTF-8").unwrap_err();
+        assert_eq!(exit_code(&utf8), EXIT_INPUT);
+        let runtime = apply_stream(serde_json::json!(1), &evaluate_command("a").unwrap().0, Options { mode: Mode::Strict, map_keys: true })
+            .next().unwrap().unwrap_err();
+        assert_eq!(exit_code(&runtime.into()), EXIT_RUNTIME);
+        assert_eq!(exit_code(&anyhow!("Failed to open x")), EXIT_FALSY);
+    }
+
+    #[test]
+    fn test_transforms() {
+        let input: &[(&str, &[&str])] = &[
+            ("null_input", &["-n"]),
+            ("front_matter", &["--front-matter"]),
+            ("ungron", &["--ungron"]),
+            ("raw_input0", &["--raw-input0"]),
+            ("stream", &["--stream"]),
+            ("yaml", &["-y"]),
+            ("fast", &["--fast"]),
+            ("skip_errors", &["--skip-errors"]),
+            ("dupes", &["--dupes", "first"]),
+            ("binary", &["--binary", "cbor"]),
+            ("max_depth", &["--max-depth", "5"]),
+            ("slurp", &["-s"]),
+            ("bulk", &["-b"]),
+            ("patch", &["--patch", "ops.json"]),
+            ("merge_patch", &["--merge-patch", "patch.json"]),
+            ("k8s_secrets", &["--k8s-secrets"]),
+            ("queries", &["-q", "n=a"]),
+            ("jsonpath", &["--jsonpath", "$.a"]),
+            ("pointer", &["--pointer", "/a"]),
+            ("jmespath", &["--jmespath", "a"]),
+        ];
+        let output: &[(&str, &[&str])] = &[
+            ("sort_keys", &["-S"]),
+            ("with_filename", &["-H"]),
+            ("keyed", &["--keyed"]),
+            ("wrap_array", &["--wrap-array"]),
+            ("join", &["--join", ","]),
+            ("limit", &["--limit", "1"]),
+            ("exit_status", &["-e"]),
+            ("profile", &["--profile"]),
+            ("trace", &["--trace"]),
+            ("in_place", &["-i", "data.json"]),
+            ("tee", &["--tee", "copy.json"]),
+            ("copy", &["--copy"]),
+            ("interactive", &["--interactive"]),
+            ("pick", &["--pick"]),
+            ("post", &["--post", "http://localhost"]),
+            ("sqlite_out", &["--sqlite-out", "out.db"]),
+            ("xlsx_out", &["--xlsx-out", "out.xlsx"]),
+            ("parquet_out", &["--parquet-out", "out.parquet"]),
+            ("split_by", &["--split-by", "k", "--out-dir", "out"]),
+            ("binary_output", &["--binary-output", "msgpack"]),
+        ];
+        // Flags that change neither, or whose effect is decided elsewhere: by the print command or options they
+        // make, by the input being read through them before it's parsed, or by the flag they modify
+        let other = [
+            "command", "files", "config", "yaml_1_1", "yaml_1_2", "follow", "paste", "encoding", "max_size", "envsubst",
+            "yaml_output", "html_output", "toml_output", "xml_output", "xml_root", "xml_item", "template", "json_output",
+            "compact", "jsonl", "seq_output", "canonical", "raw", "indent", "tab", "ascii_output", "monochrome",
+            "color_output", "color", "csv_delimiter", "csv_quote", "csv_terminator", "csv_null", "yaml_style",
+            "yaml_indent", "yaml_indent_sequences", "yaml_quote_ambiguous", "yaml_double_quotes", "yaml_document_start",
+            "raw_output0", "quiet", "output", "unbuffered", "strict", "no_map_keys", "explain", "slurpfile",
+            "sort_memory", "tee_format", "header", "table", "parquet_sample", "out_dir", "backup", "dry_run", "wait",
+            "no_clobber",
+        ];
+        // Of the input flags, those that look at parsed documents, so --fast can't leave parts of them out
+        let documents = ["max_depth", "slurp", "patch", "merge_patch", "k8s_secrets", "queries", "jsonpath", "pointer", "jmespath"];
+        let plain = Cli::parse_from(["jq"]);
+        assert!(!plain.transforms_input() && !plain.transforms_output() && !plain.transforms_documents());
+        for (id, args) in input {
+            let cli = Cli::parse_from(["jq"].iter().chain(args.iter()));
+            assert!(cli.transforms_input() && !cli.transforms_output(), "{}", id);
+            assert_eq!(cli.transforms_documents(), documents.contains(id), "{}", id);
+        }
+        for (id, args) in output {
+            let cli = Cli::parse_from(["jq"].iter().chain(args.iter()));
+            assert!(cli.transforms_output() && !cli.transforms_input(), "{}", id);
+        }
+        // A flag added without saying which it is fails here
+        let mut classified: Vec<&str> = input.iter().chain(output).map(|(id, _)| *id).chain(other).collect();
+        classified.sort();
+        let mut ids: Vec<String> = Cli::command().get_arguments().map(|a| a.get_id().to_string())
+            .filter(|id| id != "help" && id != "version")
+            .collect();
+        ids.sort();
+        assert_eq!(ids, classified);
+    }
+
+    #[test]
+    fn test_input_files() {
+        // Tests run in the crate's directory, where a key that names a file is still a key
+        assert!(std::path::Path::new("Justfile").is_file());
+        assert!(!is_input_file("Justfile"));
+        assert!(!is_input_file("spec.replicas"));
+        assert!(is_input_file("deploy/web.YAML"));
+        assert!(is_input_file("missing.json"));
+
+        let cli = Cli::parse_from(["jq", "-H", "metadata.name", "--", "configs/a", "configs/b"]);
+        assert_eq!(cli.command, ["metadata.name"]);
+        assert_eq!(cli.files, ["configs/a", "configs/b"]);
+    }
+
+    fn args(args: &[&str]) -> Cli {
+        parse_args(args.iter().map(|a| a.to_string()).collect()).unwrap().0
+    }
+
+    #[test]
+    fn test_negative_index() {
+        // With no files, the whole of `-2..` is the query, run on stdin
+        let cli = args(&["jq", "-2.."]);
+        assert_eq!(cli.command, ["-2.."]);
+        assert!(cli.files.is_empty());
+        let cli = args(&["jq", "-c", "a", "-1", "--", "x.json"]);
+        assert_eq!(cli.command, ["a", "-1"]);
+        assert_eq!(cli.files, ["x.json"]);
+
+        assert_eq!(jq(&["-c", "-2.."], "[1,2,3,4,5,6]").unwrap(), "5\n6\n");
+        assert_eq!(jq(&["-c", "a", "-1"], r#"{"a": [1, 2]}"#).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_subcommand_as_key() {
+        for name in ["stats", "merge", "diff", "join", "patch", "fmt", "validate", "alias", "completions"] {
+            let cli = args(&["jq", "-c", name]);
+            assert!(cli.subcommand.is_none());
+            assert_eq!(cli.command, [format!(".{}", name)]);
+        }
+        assert!(matches!(args(&["jq", "stats", "data.json"]).subcommand, Some(Sub::Stats { .. })));
+        // With a leading dot it's always a key
+        assert_eq!(jq(&[".stats"], r#"{"stats": {"n": 1}}"#).unwrap(), "{\n  \"n\": 1\n}\n");
+        assert_eq!(jq(&["-c", ".stats", "n"], r#"{"stats": {"n": 1}}"#).unwrap(), "1\n");
     }
 }
@@ -0,0 +1,70 @@
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// How `merge` combines two arrays at the same path
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArrayStrategy {
+    /// The later array replaces the earlier one
+    Replace,
+    /// The later array's elements are appended to the earlier one
+    Append,
+    /// Objects with the same value of the merge key are merged, and the rest are appended
+    MergeByKey,
+}
+
+/// Deep-merge `over` into `base`. Objects are merged key by key, arrays according to `arrays`, and any other
+/// value in `over` replaces the one in `base`.
+pub fn merge(base: &mut Value, over: Value, arrays: ArrayStrategy, key: &str) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (k, v) in over {
+                match base.get_mut(&k) {
+                    Some(existing) => merge(existing, v, arrays, key),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(over)) if arrays == ArrayStrategy::Append => base.extend(over),
+        (Value::Array(base), Value::Array(over)) if arrays == ArrayStrategy::MergeByKey => {
+            for item in over {
+                let existing = item.get(key)
+                    .and_then(|id| base.iter_mut().find(|b| b.get(key) == Some(id)));
+                match existing {
+                    Some(existing) => merge(existing, item, arrays, key),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let base = json!({"a": {"x": 1, "y": 2}, "list": [{"name": "web", "port": 80}, {"name": "db"}], "s": "old"});
+        let over = json!({"a": {"y": 3, "z": 4}, "list": [{"name": "web", "port": 8080}, {"name": "cache"}], "s": null});
+
+        let mut replaced = base.clone();
+        merge(&mut replaced, over.clone(), ArrayStrategy::Replace, "name");
+        assert_eq!(replaced, json!({
+            "a": {"x": 1, "y": 3, "z": 4},
+            "list": [{"name": "web", "port": 8080}, {"name": "cache"}],
+            "s": null,
+        }));
+
+        let mut appended = base.clone();
+        merge(&mut appended, over.clone(), ArrayStrategy::Append, "name");
+        assert_eq!(appended["list"].as_array().unwrap().len(), 4);
+
+        let mut keyed = base;
+        merge(&mut keyed, over, ArrayStrategy::MergeByKey, "name");
+        assert_eq!(keyed["list"], json!([{"name": "web", "port": 8080}, {"name": "db"}, {"name": "cache"}]));
+    }
+}
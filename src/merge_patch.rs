@@ -0,0 +1,67 @@
+use serde_json::{Map, Value};
+
+/// Apply an RFC 7386 JSON Merge Patch: objects are merged key by key, a `null` removes the key, and any
+/// other value, arrays included, replaces the target.
+pub fn apply(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(map) = target else { unreachable!() };
+    for (k, v) in patch {
+        if v.is_null() {
+            map.shift_remove(k);
+        } else {
+            apply(map.entry(k.clone()).or_insert(Value::Null), v);
+        }
+    }
+}
+
+/// A merge patch that transforms `a` into `b`. Merge patches can't set a value to `null`, so a `null` in
+/// `b` comes out as removing the key.
+pub fn generate(a: &Value, b: &Value) -> Value {
+    let (Value::Object(a), Value::Object(b)) = (a, b) else {
+        return b.clone();
+    };
+    let mut patch = Map::new();
+    for k in a.keys().filter(|k| !b.contains_key(*k)) {
+        patch.insert(k.clone(), Value::Null);
+    }
+    for (k, w) in b {
+        match a.get(k) {
+            Some(v) if v == w => {}
+            Some(v) => {
+                patch.insert(k.clone(), generate(v, w));
+            }
+            None => {
+                patch.insert(k.clone(), w.clone());
+            }
+        }
+    }
+    Value::Object(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let a = json!({"title": "Goodbye!", "author": {"given": "John", "family": "Doe"}, "tags": ["a", "b"]});
+        let patch = json!({"title": "Hello!", "phone": "+01", "author": {"family": null}, "tags": ["c"]});
+        let b = json!({"title": "Hello!", "author": {"given": "John"}, "tags": ["c"], "phone": "+01"});
+        let mut patched = a.clone();
+        apply(&mut patched, &patch);
+        assert_eq!(patched, b);
+
+        let generated = generate(&a, &b);
+        assert_eq!(generated, patch);
+        let mut patched = a;
+        apply(&mut patched, &generated);
+        assert_eq!(patched, b);
+    }
+}
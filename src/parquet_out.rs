@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::mem;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use serde_json::{Map, Value};
+
+/// Rows buffered into each row group
+const ROW_GROUP_SIZE: usize = 10_000;
+
+/// Column types, ordered so that each can losslessly hold the values of the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ColumnType {
+    Boolean,
+    Int64,
+    Double,
+    Utf8,
+}
+
+impl ColumnType {
+    fn of(v: &Value) -> Option<ColumnType> {
+        match v {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnType::Boolean),
+            Value::Number(n) if n.is_i64() => Some(ColumnType::Int64),
+            Value::Number(_) => Some(ColumnType::Double),
+            _ => Some(ColumnType::Utf8),
+        }
+    }
+
+    /// The narrowest type holding values of both `a` and `b`. Booleans only widen to strings.
+    fn widen(a: ColumnType, b: ColumnType) -> ColumnType {
+        match (a, b) {
+            (a, b) if a == b => a,
+            (ColumnType::Boolean, _) | (_, ColumnType::Boolean) => ColumnType::Utf8,
+            (a, b) => a.max(b),
+        }
+    }
+
+    fn parquet_type(self, name: &str) -> Result<Type> {
+        let builder = match self {
+            ColumnType::Boolean => Type::primitive_type_builder(name, PhysicalType::BOOLEAN),
+            ColumnType::Int64 => Type::primitive_type_builder(name, PhysicalType::INT64),
+            ColumnType::Double => Type::primitive_type_builder(name, PhysicalType::DOUBLE),
+            ColumnType::Utf8 => Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                .with_converted_type(ConvertedType::UTF8),
+        };
+        Ok(builder.with_repetition(Repetition::OPTIONAL).build()?)
+    }
+}
+
+fn to_row(v: Value) -> Map<String, Value> {
+    match v {
+        Value::Object(o) => o,
+        z => Map::from_iter([("value".to_string(), z)]),
+    }
+}
+
+/// Writes a stream of results to a Parquet file, one row per result. The schema is inferred from the
+/// first `sample` rows, widening a column's type when rows disagree (e.g. integers and floats become doubles).
+pub struct ParquetSink {
+    file: Option<File>,
+    sample: usize,
+    columns: Vec<(String, Option<ColumnType>)>,
+    rows: Vec<Map<String, Value>>,
+    writer: Option<SerializedFileWriter<File>>,
+    dropped: HashSet<String>,
+}
+
+impl ParquetSink {
+    pub fn create(path: &str, sample: usize) -> Result<Self> {
+        Ok(ParquetSink {
+            file: Some(File::create(path)?),
+            sample: sample.max(1),
+            columns: Vec::new(),
+            rows: Vec::new(),
+            writer: None,
+            dropped: HashSet::new(),
+        })
+    }
+
+    fn observe(&mut self, row: &Map<String, Value>) {
+        for (k, v) in row {
+            let ty = ColumnType::of(v);
+            match self.columns.iter_mut().find(|(name, _)| name == k) {
+                Some((_, existing)) => {
+                    *existing = match (*existing, ty) {
+                        (Some(a), Some(b)) => Some(ColumnType::widen(a, b)),
+                        (a, b) => a.or(b),
+                    }
+                }
+                None => self.columns.push((k.clone(), ty)),
+            }
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        let fields = self.columns.iter()
+            .map(|(name, ty)| Ok(Arc::new(ty.unwrap_or(ColumnType::Utf8).parquet_type(name)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let schema = Type::group_type_builder("schema").with_fields(fields).build()?;
+        let props = WriterProperties::builder().build();
+        let file = self.file.take().expect("parquet writer started twice");
+        self.writer = Some(SerializedFileWriter::new(file, Arc::new(schema), Arc::new(props))?);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let rows = mem::take(&mut self.rows);
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let writer = self.writer.as_mut().expect("parquet writer not started");
+        let mut group = writer.next_row_group()?;
+        for (name, ty) in &self.columns {
+            let mut column = group.next_column()?.ok_or_else(|| anyhow!("Parquet schema is missing column {}", name))?;
+            let cells = rows.iter().map(|r| r.get(name).filter(|v| !v.is_null()));
+            let levels: Vec<i16> = cells.clone().map(|v| v.is_some() as i16).collect();
+            let mismatch = |v: &Value| anyhow!(
+                "Value {} in column {} doesn't fit the type inferred from the first rows. Increase --parquet-sample to infer from more rows",
+                v, name,
+            );
+            match ty.unwrap_or(ColumnType::Utf8) {
+                ColumnType::Boolean => {
+                    let values = cells.flatten().map(|v| v.as_bool().ok_or_else(|| mismatch(v))).collect::<Result<Vec<_>>>()?;
+                    column.typed::<BoolType>().write_batch(&values, Some(&levels), None)?;
+                }
+                ColumnType::Int64 => {
+                    let values = cells.flatten().map(|v| v.as_i64().ok_or_else(|| mismatch(v))).collect::<Result<Vec<_>>>()?;
+                    column.typed::<Int64Type>().write_batch(&values, Some(&levels), None)?;
+                }
+                ColumnType::Double => {
+                    let values = cells.flatten().map(|v| v.as_f64().ok_or_else(|| mismatch(v))).collect::<Result<Vec<_>>>()?;
+                    column.typed::<DoubleType>().write_batch(&values, Some(&levels), None)?;
+                }
+                ColumnType::Utf8 => {
+                    let values: Vec<ByteArray> = cells.flatten()
+                        .map(|v| match v {
+                            Value::String(s) => ByteArray::from(s.as_str()),
+                            z => ByteArray::from(z.to_string().as_str()),
+                        })
+                        .collect();
+                    column.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)?;
+                }
+            }
+            column.close()?;
+        }
+        group.close()?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, v: Value) -> Result<()> {
+        let mut row = to_row(v);
+        if self.writer.is_none() {
+            self.observe(&row);
+            self.rows.push(row);
+            if self.rows.len() >= self.sample {
+                self.start()?;
+            }
+            return Ok(());
+        }
+        let columns = &self.columns;
+        for key in row.keys().filter(|k| !columns.iter().any(|(name, _)| name == *k)) {
+            if self.dropped.insert(key.clone()) {
+                eprintln!("Warning: dropping column {} from Parquet output, as it didn't appear in the first {} rows", key, self.sample);
+            }
+        }
+        row.retain(|k, _| columns.iter().any(|(name, _)| name == k));
+        self.rows.push(row);
+        if self.rows.len() >= ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if self.writer.is_none() {
+            self.start()?;
+        }
+        self.flush()?;
+        self.writer.take().unwrap().close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widen() {
+        assert_eq!(ColumnType::widen(ColumnType::Int64, ColumnType::Double), ColumnType::Double);
+        assert_eq!(ColumnType::widen(ColumnType::Boolean, ColumnType::Int64), ColumnType::Utf8);
+        assert_eq!(ColumnType::widen(ColumnType::Double, ColumnType::Utf8), ColumnType::Utf8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use serde_json::json;
+
+        let path = std::env::temp_dir().join(format!("jq-test-{}.parquet", std::process::id()));
+        let mut sink = ParquetSink::create(path.to_str().unwrap(), 2).unwrap();
+        sink.push(json!({"a": 1, "b": "x"})).unwrap();
+        sink.push(json!({"a": 1.5, "b": null})).unwrap();
+        sink.push(json!({"a": 2, "b": [1]})).unwrap();
+        sink.finish().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let rows: Vec<String> = reader.get_row_iter(None).unwrap()
+            .map(|r| r.unwrap().to_string())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows, vec![
+            r#"{a: 1.0, b: "x"}"#,
+            r#"{a: 1.5, b: null}"#,
+            r#"{a: 2.0, b: "[1]"}"#,
+        ]);
+    }
+}
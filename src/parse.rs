@@ -0,0 +1,570 @@
+//! The query parser: a lexer that splits a query into tokens with their byte spans, and a recursive-descent
+//! parser that turns them into an [`Ast`].
+use std::ops::Range;
+
+use crate::{hash, parse_error, Error, PrintCommand, Result, Seen, StreamCommand, Table};
+
+/// A node of the syntax tree, with the byte range of the query it was parsed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Range<usize>,
+}
+
+/// A parsed query: the stream commands in order, and the print command that ends it, if any
+#[derive(Debug, PartialEq)]
+pub struct Ast {
+    pub stream: Vec<Spanned<StreamCommand>>,
+    pub print: Option<Spanned<PrintCommand>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    DotDot,
+    Comma,
+    Eq,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    /// A run of whitespace, which separates arguments
+    Space,
+    Word(String),
+    /// A double-quoted string, with its escapes resolved
+    Str(String),
+}
+
+const PRINT_COMMANDS: &[&str] = &["keys", "len", "csv", "tsv", "md", "table", "tree", "hash", "gron", "paths", "sh"];
+
+fn is_special(c: char) -> bool {
+    matches!(c, '.' | ',' | '=' | '[' | ']' | '(' | ')' | '"') || c.is_whitespace()
+}
+
+fn is_number(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+}
+
+/// Split a query into tokens. A word runs up to the next special character, so keys can contain anything but
+/// those; anything else has to be quoted.
+fn lex(query: &str) -> Result<Vec<(Token, Range<usize>)>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            '.' if chars.next_if(|&(_, c)| c == '.').is_some() => Token::DotDot,
+            '.' => Token::Dot,
+            ',' => Token::Comma,
+            '=' => Token::Eq,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            c if c.is_whitespace() => {
+                while chars.next_if(|&(_, c)| c.is_whitespace()).is_some() {}
+                Token::Space
+            }
+            '"' => {
+                let mut escaped = false;
+                let end = chars.by_ref().find(|&(_, c)| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                });
+                let Some((end, _)) = end else {
+                    return Err(parse_error(query, start..query.len(), "Unterminated string".to_string()));
+                };
+                let s = serde_json::from_str(&query[start..end + 1])
+                    .map_err(|e| parse_error(query, start..end + 1, format!("Invalid string: {}", e)))?;
+                Token::Str(s)
+            }
+            _ => {
+                while chars.next_if(|&(_, c)| !is_special(c)).is_some() {}
+                let end = chars.peek().map_or(query.len(), |&(i, _)| i);
+                Token::Word(query[start..end].to_string())
+            }
+        };
+        let end = chars.peek().map_or(query.len(), |&(i, _)| i);
+        tokens.push((token, start..end));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// The span of the next token, or an empty span at the end of the query
+    fn span(&self) -> Range<usize> {
+        self.tokens.get(self.pos).map_or(self.query.len()..self.query.len(), |(_, span)| span.clone())
+    }
+
+    fn advance(&mut self) -> Range<usize> {
+        let span = self.span();
+        self.pos += 1;
+        span
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w == word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Whether the current word is called with arguments in parentheses, like `top(3, score)`, so a command
+    /// whose name is as likely to be a key is only taken as one when it's written that way
+    fn called(&self) -> bool {
+        matches!(self.tokens.get(self.pos + 1), Some((Token::LParen, _)))
+    }
+
+    /// Skip whitespace and commas between arguments
+    fn skip_separators(&mut self) {
+        while self.eat(&Token::Space) || self.eat(&Token::Comma) {}
+    }
+
+    fn error(&self, span: Range<usize>, message: String) -> Error {
+        parse_error(self.query, span, message)
+    }
+
+    /// An error for the next token, which the parser didn't expect
+    fn unexpected(&self, context: &str) -> Error {
+        let span = self.span();
+        match self.peek() {
+            Some(_) => self.error(span.clone(), format!("Unexpected `{}`{}", &self.query[span], context)),
+            None => self.error(span, format!("Unexpected end of query{}", context)),
+        }
+    }
+
+    fn key(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Word(s) | Token::Str(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Some(s)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Result<i64> {
+        let span = self.span();
+        let Some(Token::Word(word)) = self.peek() else {
+            return Err(self.unexpected(", expected a number"));
+        };
+        let n = word.parse().map_err(|_| self.error(span, format!("Invalid number {:?}", word)))?;
+        self.pos += 1;
+        Ok(n)
+    }
+
+    fn index(&mut self) -> Result<usize> {
+        let span = self.span();
+        let Some(Token::Word(word)) = self.peek() else {
+            return Err(self.unexpected(", expected an index"));
+        };
+        let i = word.parse().map_err(|_| self.error(span, format!("Invalid index {:?}", word)))?;
+        self.pos += 1;
+        Ok(i)
+    }
+
+    /// The end of a range after `..`, which may be left out
+    fn range_end(&mut self) -> Result<Option<i64>> {
+        match self.peek() {
+            Some(Token::Word(w)) if is_number(w) => self.number().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The text of a value that runs until one of `stop` outside of brackets and parentheses, like the `5` of
+    /// `a=5` or the `metrics[0].value` of a column. A quoted string is its contents.
+    fn raw(&mut self, stop: &[Token]) -> (String, Range<usize>) {
+        let start = self.span().start;
+        let first = self.pos;
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::LBracket | Token::LParen => depth += 1,
+                Token::RBracket | Token::RParen if depth > 0 => depth -= 1,
+                t if depth == 0 && stop.contains(t) => break,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        let end = if self.pos == first { start } else { self.tokens[self.pos - 1].1.end };
+        match &self.tokens[first..self.pos] {
+            [(Token::Str(s), _)] => (s.clone(), start..end),
+            _ => (self.query[start..end].to_string(), start..end),
+        }
+    }
+
+    /// The arguments of a command, either in parentheses, like `put(a=1, b=2)`, or separated by whitespace up to
+    /// the next comma, like `put a=1 b=2`
+    fn args<T>(&mut self, mut arg: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut args = Vec::new();
+        if self.peek() == Some(&Token::LParen) {
+            let open = self.advance();
+            loop {
+                self.skip_separators();
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                if self.peek().is_none() {
+                    return Err(self.error(open, "Unclosed `(`".to_string()));
+                }
+                args.push(arg(self)?);
+            }
+        } else {
+            while self.eat(&Token::Space) {
+                if matches!(self.peek(), None | Some(Token::Comma)) {
+                    break;
+                }
+                args.push(arg(self)?);
+            }
+        }
+        Ok(args)
+    }
+
+    /// A path argument of `command`, like the `user.id` of `dedupe(user.id)`, parsed into the commands that select
+    /// it
+    fn path(&mut self, command: &str) -> Result<(Vec<StreamCommand>, Range<usize>)> {
+        let (path, span) = self.raw(&[Token::Space, Token::Comma, Token::RParen]);
+        Ok((self.selector(&path, span.clone(), command)?, span))
+    }
+
+    fn selector(&self, path: &str, span: Range<usize>, command: &str) -> Result<Vec<StreamCommand>> {
+        match parse(path) {
+            Ok(Ast { stream, print: None }) if !stream.is_empty() => Ok(stream.into_iter().map(|c| c.node).collect()),
+            _ => Err(self.error(span, format!("Invalid key {:?} for {}", path, command))),
+        }
+    }
+
+    /// A `key=value` argument of `put`, with the value as written so it can be parsed as JSON
+    fn assignment(&mut self) -> Result<(String, String)> {
+        let start = self.span();
+        let key = self.key();
+        self.eat(&Token::Space);
+        let (Some(key), true) = (key, self.eat(&Token::Eq)) else {
+            return Err(self.error(start, "Invalid put, expected key=value".to_string()));
+        };
+        self.eat(&Token::Space);
+        let (_, span) = self.raw(&[Token::Space, Token::Comma, Token::RParen]);
+        Ok((key, self.query[span].to_string()))
+    }
+
+    /// A `key=value` filter in brackets
+    fn filter(&mut self) -> Result<String> {
+        let start = self.span();
+        let key = self.key();
+        self.eat(&Token::Space);
+        let (Some(key), true) = (key, self.eat(&Token::Eq)) else {
+            return Err(self.error(start, "Invalid filter, expected key=value".to_string()));
+        };
+        self.eat(&Token::Space);
+        let (value, _) = self.raw(&[Token::Space, Token::Comma, Token::RBracket]);
+        Ok(format!("{}={}", key, value))
+    }
+
+    /// A column of tabular output: a selector, optionally renamed with `as` or `=`. Otherwise the header is the
+    /// last key of the selector.
+    fn column(&mut self) -> Result<(String, String)> {
+        let (selector, span) = self.raw(&[Token::Space, Token::Comma, Token::RParen, Token::Eq]);
+        if selector.is_empty() {
+            return Err(self.unexpected(", expected a column"));
+        }
+        let before = self.pos;
+        self.eat(&Token::Space);
+        if self.eat(&Token::Eq) || self.eat_word("as") {
+            self.eat(&Token::Space);
+            let (header, _) = self.raw(&[Token::Space, Token::Comma, Token::RParen]);
+            return Ok((selector, header));
+        }
+        self.pos = before;
+        let quoted = self.query[span].starts_with('"');
+        let header = match selector.rsplit_once([']', '.']) {
+            Some((_, last)) if !quoted => last.to_string(),
+            _ => selector.clone(),
+        };
+        Ok((selector, header))
+    }
+
+    /// Brackets after a value: `[]`, an index, a range, or filters
+    fn bracket(&mut self, stream: &mut Vec<Spanned<StreamCommand>>) -> Result<()> {
+        let open = self.advance();
+        self.eat(&Token::Space);
+        let commands = match self.peek() {
+            Some(Token::RBracket) => vec![StreamCommand::Range(None, None)],
+            Some(Token::DotDot) => {
+                self.advance();
+                vec![StreamCommand::Range(None, Some(self.number()?))]
+            }
+            Some(Token::Word(w)) if is_number(w) => {
+                let start = self.number()?;
+                if self.eat(&Token::DotDot) {
+                    vec![StreamCommand::Range(Some(start), self.range_end()?)]
+                } else {
+                    vec![StreamCommand::Index(start)]
+                }
+            }
+            _ => {
+                let mut filters = Vec::new();
+                while !matches!(self.peek(), None | Some(Token::RBracket)) {
+                    filters.push(StreamCommand::Filter(self.filter()?));
+                    self.skip_separators();
+                }
+                filters
+            }
+        };
+        self.eat(&Token::Space);
+        match self.peek() {
+            Some(Token::RBracket) => {}
+            None => return Err(self.error(open, "Unclosed `[`".to_string())),
+            Some(_) => return Err(self.unexpected(", expected `]`")),
+        }
+        let span = open.start..self.advance().end;
+        stream.extend(commands.into_iter().map(|node| Spanned { node, span: span.clone() }));
+        Ok(())
+    }
+
+    /// The print command `name`, with its arguments
+    fn print(&mut self, name: &str) -> Result<PrintCommand> {
+        let print = match name {
+            "keys" => PrintCommand::Keys,
+            "len" => PrintCommand::Len,
+            "gron" => PrintCommand::Gron,
+            "paths" => PrintCommand::Paths,
+            "sh" => PrintCommand::Shell,
+            "csv" => PrintCommand::Csv(self.args(Self::column)?, true),
+            "tsv" => PrintCommand::Tsv(self.args(Self::column)?, true),
+            "md" => PrintCommand::Markdown(self.args(Self::column)?, true),
+            "table" => PrintCommand::Table(self.args(Self::column)?),
+            "tree" => {
+                let depth = self.args(|p| p.index())?;
+                PrintCommand::Tree(depth.first().copied())
+            }
+            "hash" => {
+                let names = self.args(|p| {
+                    let span = p.span();
+                    let name = p.key().ok_or_else(|| p.unexpected(", expected a hash algorithm"))?;
+                    hash::Algorithm::parse(&name).ok_or_else(|| p.error(span, format!("Unknown hash algorithm {}", name)))
+                })?;
+                PrintCommand::Hash(names.first().copied().unwrap_or(hash::Algorithm::Sha256))
+            }
+            _ => unreachable!("not a print command: {}", name),
+        };
+        Ok(print)
+    }
+
+    fn query(&mut self) -> Result<Ast> {
+        let mut stream = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            let start = self.span().start;
+            let command = match token {
+                Token::Space | Token::Comma => {
+                    self.advance();
+                    continue;
+                }
+                Token::Dot => {
+                    self.advance();
+                    match self.key() {
+                        Some(key) => StreamCommand::Key(key),
+                        None => continue,
+                    }
+                }
+                Token::DotDot => {
+                    self.advance();
+                    StreamCommand::Range(None, Some(self.number()?))
+                }
+                Token::LBracket => {
+                    self.bracket(&mut stream)?;
+                    continue;
+                }
+                Token::Word(w) if PRINT_COMMANDS.contains(&w.as_str()) => {
+                    self.advance();
+                    let node = self.print(&w)?;
+                    let span = start..self.span().start;
+                    self.skip_separators();
+                    if self.peek().is_some() {
+                        return Err(self.unexpected(&format!(" after `{}`", w)));
+                    }
+                    return Ok(Ast { stream, print: Some(Spanned { node, span }) });
+                }
+                Token::Word(w) if w == "put" || w == "delete" => {
+                    self.advance();
+                    let span = self.span();
+                    let commands: Vec<StreamCommand> = if w == "put" {
+                        self.args(Self::assignment)?.into_iter().map(|(k, v)| StreamCommand::Put(k, v)).collect()
+                    } else {
+                        self.args(|p| p.key().ok_or_else(|| p.unexpected(", expected a key")))?
+                            .into_iter().map(StreamCommand::Delete).collect()
+                    };
+                    if commands.is_empty() {
+                        return Err(self.error(span, format!("Expected arguments to {}", w)));
+                    }
+                    let span = start..self.span().start;
+                    stream.extend(commands.into_iter().map(|node| Spanned { node, span: span.clone() }));
+                    continue;
+                }
+                Token::Word(w) if w == "dedupe" || w == "sort_by" => {
+                    self.advance();
+                    let mut keys = self.args(|p| p.path(&w))?;
+                    if let Some((_, span)) = keys.get(1) {
+                        return Err(self.error(span.clone(), format!("{} takes one key", w)));
+                    }
+                    let key = keys.pop().map(|(key, _)| key);
+                    if w == "dedupe" {
+                        StreamCommand::Dedupe(key, Seen::default())
+                    } else {
+                        StreamCommand::SortBy(key)
+                    }
+                }
+                Token::Word(w) if (w == "top" || w == "bottom") && self.called() => {
+                    self.advance();
+                    let span = self.span();
+                    let args = self.args(|p| Ok(p.raw(&[Token::Space, Token::Comma, Token::RParen])))?;
+                    let [(n, n_span), key @ ..] = args.as_slice() else {
+                        return Err(self.error(span, format!("{} takes a count and a key", w)));
+                    };
+                    if let Some((_, span)) = key.get(1) {
+                        return Err(self.error(span.clone(), format!("{} takes a count and a key", w)));
+                    }
+                    let n = n.parse().map_err(|_| self.error(n_span.clone(), format!("Invalid count {:?}", n)))?;
+                    let key = key.first().map(|(key, span)| self.selector(key, span.clone(), &w)).transpose()?;
+                    StreamCommand::Top { n, key, largest: w == "top" }
+                }
+                Token::Word(w) if w == "chunk" && self.called() => {
+                    self.advance();
+                    let span = self.span();
+                    let sizes = self.args(|p| Ok((p.span(), p.index()?)))?;
+                    match sizes.as_slice() {
+                        [(_, n)] if *n > 0 => StreamCommand::Chunk(*n),
+                        [(span, _)] => return Err(self.error(span.clone(), "A chunk must hold at least 1 value".to_string())),
+                        _ => return Err(self.error(span, "chunk takes the number of values in each chunk".to_string())),
+                    }
+                }
+                Token::Word(w) if w == "lookup" => {
+                    self.advance();
+                    let span = self.span();
+                    let args = self.args(|p| Ok(p.raw(&[Token::Space, Token::Comma, Token::RParen])))?;
+                    let [(field, _), (table, _), (key, key_span)] = args.as_slice() else {
+                        let span = args.first().map_or(span, |(_, first)| first.start..args[args.len() - 1].1.end);
+                        return Err(self.error(span, "lookup takes a field, a table and a key".to_string()));
+                    };
+                    let (target, field) = field.split_once('=').unwrap_or((field, field));
+                    StreamCommand::Lookup {
+                        target: target.to_string(),
+                        field: field.to_string(),
+                        table: table.clone(),
+                        key: self.selector(key, key_span.clone(), "lookup")?,
+                        index: Table::default(),
+                    }
+                }
+                Token::Word(w) if is_number(&w) => {
+                    let n = self.number()?;
+                    if self.eat(&Token::DotDot) {
+                        StreamCommand::Range(Some(n), self.range_end()?)
+                    } else {
+                        StreamCommand::Index(n)
+                    }
+                }
+                Token::Word(_) | Token::Str(_) => StreamCommand::Key(self.key().unwrap_or_default()),
+                Token::RBracket => return Err(self.error(self.span(), "Unmatched `]`".to_string())),
+                _ => return Err(self.unexpected("")),
+            };
+            stream.push(Spanned { node: command, span: start..self.span().start });
+        }
+        Ok(Ast { stream, print: None })
+    }
+}
+
+/// Parse a query into its syntax tree
+pub fn parse(query: &str) -> Result<Ast> {
+    let tokens = lex(query)?;
+    Parser { query, tokens, pos: 0 }.query()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(query: &str) -> Vec<StreamCommand> {
+        parse(query).unwrap().stream.into_iter().map(|c| c.node).collect()
+    }
+
+    #[test]
+    fn test_lex() {
+        let tokens: Vec<Token> = lex(r#"a.."b c"[x= 1]"#).unwrap().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(tokens, vec![
+            Token::Word("a".into()), Token::DotDot, Token::Str("b c".into()), Token::LBracket,
+            Token::Word("x".into()), Token::Eq, Token::Space, Token::Word("1".into()), Token::RBracket,
+        ]);
+        assert!(matches!(lex(r#"a."b"#), Err(Error::Parse { span, .. }) if span == (2..4)));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(commands(r#"."a.b"[host=example.com, name="x y"]"#), vec![
+            StreamCommand::Key("a.b".into()),
+            StreamCommand::Filter("host=example.com".into()),
+            StreamCommand::Filter("name=x y".into()),
+        ]);
+        assert_eq!(commands(r#"put(tags=[1,2], n="5") delete a b, c"#), vec![
+            StreamCommand::Put("tags".into(), "[1,2]".into()),
+            StreamCommand::Put("n".into(), "\"5\"".into()),
+            StreamCommand::Delete("a".into()),
+            StreamCommand::Delete("b".into()),
+            StreamCommand::Key("c".into()),
+        ]);
+        assert_eq!(commands("dedupe, events[] dedupe(user.id)"), vec![
+            StreamCommand::Dedupe(None, Seen::default()),
+            StreamCommand::Key("events".into()),
+            StreamCommand::Range(None, None),
+            StreamCommand::Dedupe(Some(vec![StreamCommand::Key("user".into()), StreamCommand::Key("id".into())]), Seen::default()),
+        ]);
+        assert!(matches!(parse("dedupe(a b)"), Err(Error::Parse { span, .. }) if span == (9..10)));
+        assert_eq!(commands("sort_by(ts), sort_by"), vec![
+            StreamCommand::SortBy(Some(vec![StreamCommand::Key("ts".into())])),
+            StreamCommand::SortBy(None),
+        ]);
+        assert_eq!(commands("top(3, score) bottom(1)"), vec![
+            StreamCommand::Top { n: 3, key: Some(vec![StreamCommand::Key("score".into())]), largest: true },
+            StreamCommand::Top { n: 1, key: None, largest: false },
+        ]);
+        assert_eq!(commands("margin.top, bottom"), vec![
+            StreamCommand::Key("margin".into()),
+            StreamCommand::Key("top".into()),
+            StreamCommand::Key("bottom".into()),
+        ]);
+        assert_eq!(commands("top 3"), vec![StreamCommand::Key("top".into()), StreamCommand::Index(3)]);
+        assert!(matches!(parse("top(x, score)"), Err(Error::Parse { span, .. }) if span == (4..5)));
+        assert_eq!(commands("chunk(100)"), vec![StreamCommand::Chunk(100)]);
+        assert!(matches!(parse("chunk(0)"), Err(Error::Parse { span, .. }) if span == (6..7)));
+        assert!(matches!(parse("chunk()"), Err(Error::Parse { span, .. }) if span == (5..6)));
+        assert_eq!(commands("body.chunk"), vec![StreamCommand::Key("body".into()), StreamCommand::Key("chunk".into())]);
+        assert!(matches!(&commands("lookup(user=user_id users id.value)")[0], StreamCommand::Lookup { target, field, table, key, .. }
+            if target == "user" && field == "user_id" && table == "users" && key.len() == 2));
+        assert!(matches!(parse("lookup(a, b)"), Err(Error::Parse { span, .. }) if span == (7..11)));
+        let ast = parse("items[0] csv a.b c as d").unwrap();
+        assert_eq!(ast.stream[1].span, 5..8);
+        assert_eq!(ast.print.unwrap().node, PrintCommand::Csv(vec![
+            ("a.b".into(), "b".into()),
+            ("c".into(), "d".into()),
+        ], true));
+        assert!(matches!(parse("keys foo"), Err(Error::Parse { span, .. }) if span == (5..8)));
+    }
+}
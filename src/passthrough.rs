@@ -0,0 +1,222 @@
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use jq::StreamCommand;
+
+/// Whether the key a map visitor is at is the one being looked for, without allocating it
+struct IsKey<'k>(&'k str);
+
+impl<'de> DeserializeSeed<'de> for IsKey<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for IsKey<'_> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a key")
+    }
+
+    fn visit_str<E>(self, key: &str) -> Result<bool, E> {
+        Ok(key == self.0)
+    }
+}
+
+/// The raw text of one key of an object, or one element of an array, skipping over the rest without
+/// building them
+enum Child<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+impl<'de> Visitor<'de> for Child<'_> {
+    type Value = Option<&'de RawValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object or array")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let Child::Key(key) = self else {
+            return Err(serde::de::Error::custom("expected an array"));
+        };
+        // Like a parsed object, the last of duplicate keys wins
+        let mut found = None;
+        while let Some(is_key) = map.next_key_seed(IsKey(key))? {
+            if is_key {
+                found = Some(map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let Child::Index(index) = self else {
+            return Err(serde::de::Error::custom("expected an object"));
+        };
+        let mut found = None;
+        let mut i = 0;
+        loop {
+            if i == index {
+                found = seq.next_element()?;
+                if found.is_none() {
+                    break;
+                }
+            } else if seq.next_element::<IgnoredAny>()?.is_none() {
+                break;
+            }
+            i += 1;
+        }
+        Ok(found)
+    }
+}
+
+/// The value at `path` in `raw`: `None` for a missing key or an index out of bounds, which is null. Keys of
+/// arrays and anything else that the lenient evaluation of the query would do more with than look up a child
+/// can't be answered here, and are an error, for the caller to fall back to parsing the document.
+fn walk<'a>(mut raw: &'a RawValue, path: &[StreamCommand]) -> Result<Option<&'a RawValue>, ()> {
+    for command in path {
+        let text = raw.get();
+        let child = match command {
+            StreamCommand::Key(key) if text.starts_with('{') => Child::Key(key),
+            &StreamCommand::Index(i) if text.starts_with('[') && i >= 0 => Child::Index(i as usize),
+            StreamCommand::Key(_) | StreamCommand::Index(_) if text == "null" => return Ok(None),
+            _ => return Err(()),
+        };
+        let mut de = serde_json::Deserializer::from_str(text);
+        match de.deserialize_any(child) {
+            Ok(Some(value)) => raw = value,
+            Ok(None) => return Ok(None),
+            Err(_) => return Err(()),
+        }
+    }
+    Ok(Some(raw))
+}
+
+/// Whether raw JSON comes out the same copied as it would parsed and serialized again: no `\u` or `\/` escapes,
+/// which are written as the characters they stand for, and no numbers that aren't written as integers that fit
+/// in 64 bits, which are written back as floats
+fn is_canonical(raw: &str) -> bool {
+    let bytes = raw.as_bytes();
+    let mut in_string = false;
+    let mut digits = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if in_string {
+            match b {
+                b'\\' if matches!(bytes.get(i + 1), Some(b'u' | b'/')) => return false,
+                b'\\' => i += 1,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'.' | b'e' | b'E' => return false,
+                b'-' if bytes.get(i + 1) == Some(&b'0') => return false,
+                b'0'..=b'9' => {
+                    digits += 1;
+                    if digits > 18 {
+                        return false;
+                    }
+                }
+                _ => digits = 0,
+            }
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Write raw JSON on one line, dropping whitespace outside of strings
+fn write_compact(out: &mut dyn Write, raw: &str) -> std::io::Result<()> {
+    let bytes = raw.as_bytes();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if b == b'"' {
+            in_string = true;
+        } else if b.is_ascii_whitespace() {
+            out.write_all(&bytes[start..i])?;
+            start = i + 1;
+        }
+    }
+    out.write_all(&bytes[start..])?;
+    out.write_all(b"\n")
+}
+
+/// Print the value at `path` in each line of NDJSON input compactly, copying it from the input instead of
+/// parsing the document into values and serializing it again. Documents the path can't be looked up in without
+/// parsing them, or whose value would print differently copied, are handed to `fallback` to run the query on.
+/// At a line that isn't a whole document, the input stops being NDJSON, and nothing is read past it: that line
+/// is returned for the caller to put back in front of the rest of the input.
+pub fn run(
+    input: &mut dyn BufRead,
+    path: &[StreamCommand],
+    out: &mut dyn Write,
+    mut fallback: impl FnMut(&mut dyn Write, Value) -> Result<()>,
+) -> Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(doc) = serde_json::from_str::<&RawValue>(&line) else {
+            return Ok(Some(line));
+        };
+        match walk(doc, path) {
+            Ok(Some(value)) if is_canonical(value.get()) => write_compact(out, value.get())?,
+            Ok(None) => out.write_all(b"null\n")?,
+            _ => fallback(out, serde_json::from_str(&line)?)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let input = "{\"a\": {\"b\": [1, {\"c\": \"x \\\" y\"}]}, \"z\": 0}\n\n{\"a\": null}\n{\"a\": [{\"b\": 1}]}\n{\"a\": {\"b\": [0, 1.0]}}\n";
+        let path = [StreamCommand::Key("a".into()), StreamCommand::Key("b".into()), StreamCommand::Index(1)];
+        let mut out = Vec::new();
+        let mut fallbacks = Vec::new();
+        let rest = run(&mut input.as_bytes(), &path, &mut out, |_, v| {
+            fallbacks.push(v);
+            Ok(())
+        }).unwrap();
+        assert_eq!(rest, None);
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"c\":\"x \\\" y\"}\nnull\n");
+        assert_eq!(fallbacks, vec![serde_json::json!({"a": [{"b": 1}]}), serde_json::json!({"a": {"b": [0, 1.0]}})]);
+
+        let mut out = Vec::new();
+        let rest = run(&mut "{\"a\": 1}\n{\n  \"a\": 2\n}\n".as_bytes(), &path[..1], &mut out, |_, _| Ok(())).unwrap();
+        assert_eq!(rest.as_deref(), Some("{\n"));
+        assert_eq!(out, b"1\n");
+    }
+}
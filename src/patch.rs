@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::pointer;
+
+fn add(doc: &mut Value, tokens: &[String], value: Value) -> Result<()> {
+    let Some((last, parent)) = tokens.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+    match pointer::get_mut(doc, parent) {
+        Some(Value::Object(map)) => {
+            map.insert(last.clone(), value);
+        }
+        Some(Value::Array(arr)) if last == "-" => arr.push(value),
+        Some(Value::Array(arr)) => {
+            let i = pointer::index(last, arr.len() + 1)?;
+            arr.insert(i, value);
+        }
+        _ => return Err(anyhow!("No object or array at /{}", parent.join("/"))),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, tokens: &[String]) -> Result<Value> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return Ok(std::mem::take(doc));
+    };
+    let removed = match pointer::get_mut(doc, parent) {
+        Some(Value::Object(map)) => map.shift_remove(last),
+        Some(Value::Array(arr)) => {
+            let i = pointer::index(last, arr.len())?;
+            Some(arr.remove(i))
+        }
+        _ => None,
+    };
+    removed.ok_or_else(|| anyhow!("Nothing to remove at /{}", tokens.join("/")))
+}
+
+fn string_field<'a>(op: &'a Value, field: &str) -> Result<&'a str> {
+    op.get(field).and_then(Value::as_str).ok_or_else(|| anyhow!("Patch operation is missing \"{}\": {}", field, op))
+}
+
+fn value_field(op: &Value) -> Result<Value> {
+    op.get("value").cloned().ok_or_else(|| anyhow!("Patch operation is missing \"value\": {}", op))
+}
+
+/// Whether two values are equal as JSON, so `1` and `1.0` are the same number
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+        (Value::Array(x), Value::Array(y)) => x.len() == y.len() && x.iter().zip(y).all(|(a, b)| json_equal(a, b)),
+        (Value::Object(x), Value::Object(y)) => x.len() == y.len()
+            && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| json_equal(v, w))),
+        _ => a == b,
+    }
+}
+
+fn apply_op(doc: &mut Value, op: &Value) -> Result<()> {
+    let path = pointer::parse(string_field(op, "path")?)?;
+    match string_field(op, "op")? {
+        "add" => add(doc, &path, value_field(op)?),
+        "remove" => remove(doc, &path).map(drop),
+        "replace" => {
+            let target = pointer::get_mut(doc, &path)
+                .ok_or_else(|| anyhow!("Nothing to replace at {}", string_field(op, "path").unwrap_or_default()))?;
+            *target = value_field(op)?;
+            Ok(())
+        }
+        "move" => {
+            let from = pointer::parse(string_field(op, "from")?)?;
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err(anyhow!("Cannot move a value into one of its own children: {}", op));
+            }
+            let value = remove(doc, &from)?;
+            add(doc, &path, value)
+        }
+        "copy" => {
+            let from = pointer::parse(string_field(op, "from")?)?;
+            let value = pointer::get(doc, &from).cloned()
+                .ok_or_else(|| anyhow!("Nothing to copy at {}", string_field(op, "from").unwrap_or_default()))?;
+            add(doc, &path, value)
+        }
+        "test" => {
+            let expected = value_field(op)?;
+            match pointer::get(doc, &path) {
+                Some(actual) if json_equal(actual, &expected) => Ok(()),
+                actual => Err(anyhow!("Patch test failed at {}: expected {}, found {}",
+                    string_field(op, "path")?, expected, actual.unwrap_or(&Value::Null))),
+            }
+        }
+        other => Err(anyhow!("Unknown patch operation: {}", other)),
+    }
+}
+
+/// Apply an RFC 6902 JSON Patch, an array of operations, to `doc`. If any operation fails, the whole patch
+/// fails and no partial result is returned.
+pub fn apply(mut doc: Value, patch: &Value) -> Result<Value> {
+    let ops = patch.as_array().ok_or_else(|| anyhow!("A JSON Patch must be an array of operations"))?;
+    for op in ops {
+        apply_op(&mut doc, op)?;
+    }
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply() {
+        let doc = json!({"a": {"b": [1, 2]}, "c": "x"});
+        let patch = json!([
+            {"op": "test", "path": "/c", "value": "x"},
+            {"op": "add", "path": "/a/b/1", "value": 9},
+            {"op": "add", "path": "/a/b/-", "value": 3},
+            {"op": "replace", "path": "/c", "value": "y"},
+            {"op": "copy", "from": "/c", "path": "/d"},
+            {"op": "move", "from": "/a/b", "path": "/b"},
+            {"op": "remove", "path": "/a"},
+        ]);
+        assert_eq!(apply(doc.clone(), &patch).unwrap(), json!({"c": "y", "d": "y", "b": [1, 9, 2, 3]}));
+
+        assert!(apply(doc.clone(), &json!([{"op": "test", "path": "/c", "value": "z"}])).is_err());
+        assert!(apply(doc.clone(), &json!([{"op": "remove", "path": "/missing"}])).is_err());
+        assert!(apply(doc, &json!([{"op": "move", "from": "/a", "path": "/a/b/x"}])).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use std::borrow::Cow;
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::{anyhow, Result};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use serde_json::Value;
+
+use jq::{apply_stream_ref, evaluate_command, Options};
+
+/// Most array indices offered as steps, so huge arrays don't flood the list
+const MAX_INDICES: usize = 1000;
+
+/// Join the steps of a selector, like `items`, `[]`, `name` into `items[].name`
+fn selector(steps: &[String]) -> String {
+    let mut out = String::new();
+    for step in steps {
+        if !out.is_empty() && !step.starts_with('[') {
+            out.push('.');
+        }
+        out.push_str(step);
+    }
+    if out.is_empty() { ".".to_string() } else { out }
+}
+
+/// The values `steps` select from `docs`
+fn select(docs: &[Value], steps: &[String]) -> Vec<Value> {
+    let Ok((commands, _)) = evaluate_command(&selector(steps)) else {
+        return Vec::new();
+    };
+    docs.iter().flat_map(|doc| apply_stream_ref(doc, &commands, Options::default()).filter_map(Result::ok).map(Cow::into_owned).collect::<Vec<_>>()).collect()
+}
+
+/// The steps that can follow a selection: the union of the keys of its objects, in order of first appearance,
+/// and for arrays, `[]` for every element followed by the indices
+fn next_steps(values: &[Value]) -> Vec<String> {
+    let mut steps: Vec<String> = Vec::new();
+    let mut add = |step: String| {
+        if !steps.contains(&step) {
+            steps.push(step);
+        }
+    };
+    for value in values {
+        match value {
+            Value::Object(map) => map.keys().for_each(|k| add(k.clone())),
+            Value::Array(arr) if !arr.is_empty() => {
+                add("[]".to_string());
+                (0..arr.len().min(MAX_INDICES)).for_each(|i| add(format!("[{}]", i)));
+            }
+            _ => {}
+        }
+    }
+    steps
+}
+
+/// A fuzzy match score for `candidate` against `query`, like fzf: every character of the query must appear
+/// in order, ignoring case. Consecutive characters and a match at the start score higher.
+fn score(candidate: &str, query: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.to_lowercase().chars() {
+        let i = pos + candidate[pos..].iter().position(|&c| c == q)?;
+        score += match previous {
+            _ if i == 0 => 10,
+            Some(p) if p + 1 == i => 8,
+            _ => 1,
+        };
+        previous = Some(i);
+        pos = i + 1;
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
+
+/// The steps matching `query`, best first
+fn filter(steps: &[String], query: &str) -> Vec<String> {
+    let mut scored: Vec<(i64, &String)> = steps.iter()
+        .filter_map(|step| score(step, query).map(|s| (s, step)))
+        .collect();
+    if !query.is_empty() {
+        scored.sort_by_key(|(s, _)| -s);
+    }
+    scored.into_iter().map(|(_, step)| step.clone()).collect()
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() > width {
+        s.chars().take(width.saturating_sub(1)).chain(['…']).collect()
+    } else {
+        s.to_string()
+    }
+}
+
+struct Picker<'a> {
+    docs: &'a [Value],
+    steps: Vec<String>,
+    query: String,
+    cursor: usize,
+}
+
+impl Picker<'_> {
+    fn candidates(&self) -> Vec<String> {
+        filter(&next_steps(&select(self.docs, &self.steps)), &self.query)
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let width = width as usize;
+        let candidates = self.candidates();
+        self.cursor = self.cursor.min(candidates.len().saturating_sub(1));
+        // The prompt, the list, a preview of the selection, and the key help
+        let list = (height as usize).saturating_sub(4);
+        let scroll = (self.cursor + 1).saturating_sub(list);
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        let prompt = format!("{} > {}", selector(&self.steps), self.query);
+        queue!(out, SetAttribute(Attribute::Bold), Print(truncate(&prompt, width)), SetAttribute(Attribute::Reset))?;
+        for (line, (i, step)) in candidates.iter().enumerate().skip(scroll).take(list).enumerate() {
+            queue!(out, MoveTo(0, line as u16 + 1))?;
+            let text = truncate(&format!("  {}", step), width);
+            if i == self.cursor {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(text), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(text))?;
+            }
+        }
+        let selected = select(self.docs, &self.steps);
+        let preview = match selected.as_slice() {
+            [one] => one.to_string(),
+            many => Value::Array(many.to_vec()).to_string(),
+        };
+        let help = "tab: add step  backspace: remove step  enter: run  esc: cancel";
+        queue!(out, MoveTo(0, height.saturating_sub(2)), SetAttribute(Attribute::Dim), Print(truncate(&preview, width)))?;
+        queue!(out, MoveTo(0, height.saturating_sub(1)), Print(truncate(help, width)), SetAttribute(Attribute::Reset))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Handle a key press, returning the finished selector or `Some(None)` when cancelled
+    fn key(&mut self, key: KeyEvent) -> Option<Option<String>> {
+        match key.code {
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Some(None),
+            KeyCode::Enter => return Some(Some(selector(&self.steps))),
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor += 1,
+            KeyCode::Tab | KeyCode::Right => {
+                if let Some(step) = self.candidates().get(self.cursor) {
+                    self.steps.push(step.clone());
+                    self.query.clear();
+                    self.cursor = 0;
+                }
+            }
+            KeyCode::Backspace if self.query.is_empty() => {
+                self.steps.pop();
+                self.cursor = 0;
+            }
+            KeyCode::Left => {
+                self.steps.pop();
+                self.query.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.cursor = 0;
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.cursor = 0;
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Build a selector for `docs` interactively, one step at a time: the keys and indices that can come next are
+/// listed and narrowed down by fuzzy search as you type. Returns the selector, or None if cancelled.
+pub fn run(docs: &[Value]) -> Result<Option<String>> {
+    let mut out = io::stderr();
+    if !out.is_terminal() {
+        return Err(anyhow!("--pick requires a terminal on stderr"));
+    }
+    let mut picker = Picker { docs, steps: Vec::new(), query: String::new(), cursor: 0 };
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+    let result = (|| -> Result<Option<String>> {
+        loop {
+            picker.draw(&mut out)?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                if let Some(done) = picker.key(key) {
+                    return Ok(done);
+                }
+            }
+        }
+    })();
+    execute!(out, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_steps() {
+        let docs = vec![json!({"items": [{"name": "a", "id": 1}, {"name": "b", "tags": []}]})];
+        assert_eq!(next_steps(&docs), vec!["items"]);
+        let steps = vec!["items".to_string(), "[]".to_string()];
+        assert_eq!(selector(&steps), "items[]");
+        assert_eq!(next_steps(&select(&docs, &steps)), vec!["name", "id", "tags"]);
+        assert_eq!(next_steps(&select(&docs, &steps[..1])), vec!["[]", "[0]", "[1]"]);
+        assert_eq!(selector(&[]), ".");
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        assert_eq!(score("name", "xyz"), None);
+        assert!(score("name", "nm").is_some());
+        let steps: Vec<String> = ["metadata", "name", "namespace"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(filter(&steps, "nam"), vec!["name", "namespace"]);
+        assert_eq!(filter(&steps, "ma")[0], "metadata");
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Split an RFC 6901 JSON Pointer like `/a/b~1c/0` into its unescaped reference tokens
+pub fn parse(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(anyhow!("JSON Pointer must be empty or start with /: {}", pointer));
+    };
+    Ok(rest.split('/').map(|t| t.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Escape a key as a JSON Pointer reference token
+pub fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// The index an array reference token refers to. Leading zeros and signs aren't allowed.
+pub fn index(token: &str, len: usize) -> Result<usize> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|b| b.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    match token.parse::<usize>() {
+        Ok(i) if valid && i < len => Ok(i),
+        _ => Err(anyhow!("Array index {} is out of bounds or invalid", token)),
+    }
+}
+
+/// The value at `tokens` in `value`, if there is one
+pub fn get<'a>(value: &'a Value, tokens: &[String]) -> Option<&'a Value> {
+    tokens.iter().try_fold(value, |v, t| match v {
+        Value::Object(map) => map.get(t),
+        Value::Array(arr) => index(t, arr.len()).ok().map(|i| &arr[i]),
+        _ => None,
+    })
+}
+
+pub fn get_mut<'a>(value: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens.iter().try_fold(value, |v, t| match v {
+        Value::Object(map) => map.get_mut(t),
+        Value::Array(arr) => {
+            let len = arr.len();
+            index(t, len).ok().map(move |i| &mut arr[i])
+        }
+        _ => None,
+    })
+}
+
+fn walk(out: &mut Vec<String>, prefix: String, value: &Value) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (escape(k), v)).collect(),
+        Value::Array(arr) => arr.iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect(),
+        _ => return,
+    };
+    for (token, child) in children {
+        let path = format!("{}/{}", prefix, token);
+        out.push(path.clone());
+        walk(out, path, child);
+    }
+}
+
+/// The pointer of every value nested in `value`, parents before their children
+pub fn paths(value: &Value) -> Vec<String> {
+    let mut out = Vec::new();
+    walk(&mut out, String::new(), value);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pointer() {
+        let doc = json!({"a/b": [0, {"m~n": 8}], "": 1});
+        assert_eq!(get(&doc, &parse("/a~1b/1/m~0n").unwrap()), Some(&json!(8)));
+        assert_eq!(get(&doc, &parse("/").unwrap()), Some(&json!(1)));
+        assert_eq!(get(&doc, &parse("").unwrap()), Some(&doc));
+        assert_eq!(get(&doc, &parse("/a~1b/01").unwrap()), None);
+        assert!(parse("a").is_err());
+        assert_eq!(escape("a/b~c"), "a~1b~0c");
+        assert_eq!(paths(&json!({"a": [1, {"b/c": 2}]})), vec!["/a", "/a/0", "/a/1", "/a/1/b~1c"]);
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use ureq::Agent;
+
+/// Sends results as JSON request bodies to a URL, for --post
+pub struct Poster {
+    agent: Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+/// Split a header given as `Name: value`
+fn parse_header(header: &str) -> Result<(String, String)> {
+    let (name, value) = header.split_once(':')
+        .ok_or_else(|| anyhow!("Invalid header, expected `Name: value`: {}", header))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+impl Poster {
+    pub fn new(url: &str, headers: &[String]) -> Result<Self> {
+        let agent = Agent::config_builder().http_status_as_error(false).build().into();
+        let headers = headers.iter().map(|h| parse_header(h)).collect::<Result<_>>()?;
+        Ok(Poster { agent, url: url.to_string(), headers })
+    }
+
+    /// POST `value` and return the response body. A response status other than 2xx is an error, with the
+    /// body in its message.
+    pub fn send(&self, value: &Value) -> Result<String> {
+        let mut request = self.agent.post(&self.url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let mut response = request.send_json(value).map_err(|e| anyhow!("POST {} failed: {}", self.url, e))?;
+        let status = response.status();
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        if !status.is_success() {
+            return Err(anyhow!("POST {} failed with {}: {}", self.url, status, body.trim()));
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(parse_header("Authorization: Bearer x:y").unwrap(), ("Authorization".into(), "Bearer x:y".into()));
+        assert!(parse_header("nocolon").is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::Cell;
+use std::iter::once;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use jq::{apply_stream, Options, Result, StreamCommand};
+
+/// Where the time of a run went, for --profile. Whatever isn't spent reading and parsing input or running the
+/// query is counted as output. The report is printed to stderr when the profile is dropped.
+pub struct Profile {
+    started: Instant,
+    parse: Cell<Duration>,
+    stages: Vec<(String, Cell<Duration>)>,
+}
+
+impl Profile {
+    pub fn new(stream: &[StreamCommand]) -> Self {
+        Profile {
+            started: Instant::now(),
+            parse: Cell::default(),
+            stages: stream.iter().map(|c| (format!("{:?}", c), Cell::default())).collect(),
+        }
+    }
+
+    /// Run `f`, counting its time as reading and parsing input
+    pub fn time_parse<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.parse.set(self.parse.get() + start.elapsed());
+        result
+    }
+
+    /// Count the time spent pulling values out of `iter` as reading and parsing input
+    pub fn time_iter<'a, T: 'a>(&'a self, mut iter: impl Iterator<Item=T> + 'a) -> impl Iterator<Item=T> + 'a {
+        std::iter::from_fn(move || self.time_parse(|| iter.next()))
+    }
+
+    /// Like `apply_stream`, but running one command at a time and timing each
+    pub fn apply_stream<'a>(&'a self, obj: Value, stream: &'a [StreamCommand], options: Options) -> Box<dyn Iterator<Item=Result<Value>> + 'a> {
+        let mut it: Box<dyn Iterator<Item=Result<Value>> + 'a> = Box::new(once(Ok(obj)));
+        for (i, (_, stage)) in self.stages.iter().enumerate() {
+            let step = &stream[i..i + 1];
+            it = Box::new(it.flat_map(move |v| {
+                let Ok(v) = v else {
+                    return vec![v];
+                };
+                let start = Instant::now();
+                let output: Vec<Result<Value>> = apply_stream(v, step, options).collect();
+                stage.set(stage.get() + start.elapsed());
+                output
+            }));
+        }
+        it
+    }
+
+    pub fn report(&self) -> String {
+        let total = self.started.elapsed();
+        let stages: Duration = self.stages.iter().map(|(_, stage)| stage.get()).sum();
+        let mut lines = vec![format!("{:>10}  read and parse", format_duration(self.parse.get()))];
+        for (label, stage) in &self.stages {
+            lines.push(format!("{:>10}  {}", format_duration(stage.get()), label));
+        }
+        lines.push(format!("{:>10}  output", format_duration(total.saturating_sub(self.parse.get() + stages))));
+        lines.push(format!("{:>10}  total", format_duration(total)));
+        if let Some(kb) = peak_memory_kb() {
+            let mb = format!("{:.1} MB", kb as f64 / 1024.0);
+            lines.push(format!("{:>10}  peak memory", mb));
+        }
+        lines.join("\n")
+    }
+}
+
+impl Drop for Profile {
+    fn drop(&mut self) {
+        eprintln!("{}", self.report());
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.3} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// The peak resident set size of this process, where the OS reports it (Linux)
+fn peak_memory_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_profile() {
+        let (stream, _) = crate::evaluate_command("a.[]").unwrap();
+        let profile = Profile::new(&stream);
+        let parsed: Vec<Value> = profile.time_iter([json!({"a": [1, 2]})].into_iter()).collect();
+        let results: Vec<Value> = profile.apply_stream(parsed[0].clone(), &stream, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![json!(1), json!(2)]);
+        let report = profile.report();
+        assert_eq!(report.lines().count(), stream.len() + 3 + usize::from(peak_memory_kb().is_some()));
+        assert!(report.contains("read and parse"));
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use jq::{apply_stream_ref, evaluate_command, Options, PrintCommand, StreamCommand};
+
+/// Several named queries run against the same input, for -q. Each input document becomes an object of
+/// the queries' results, so a large input is only parsed once however many things are pulled out of it.
+pub struct Queries(Vec<(String, Vec<StreamCommand>, PrintCommand)>);
+
+impl Queries {
+    /// Parse queries given as `name=query`. Of the print commands, only `len` and `keys` can end one.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let queries = args.iter().map(|arg| {
+            let (name, query) = arg.split_once('=')
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| anyhow!("Invalid query, expected `name=query`: {}", arg))?;
+            let (stream, print) = evaluate_command(query)?;
+            if !matches!(print, PrintCommand::Pretty | PrintCommand::Len | PrintCommand::Keys) {
+                return Err(anyhow!("Query {} can't end in {:?}", name, print));
+            }
+            Ok((name.to_string(), stream, print))
+        }).collect::<Result<_>>()?;
+        Ok(Queries(queries))
+    }
+
+    /// The results of each query against `doc`, by name: the result itself when a query produces exactly
+    /// one, and otherwise an array of them
+    pub fn eval(&self, doc: &Value, options: Options) -> Result<Value> {
+        let mut out = Map::new();
+        for (name, stream, print) in &self.0 {
+            let mut results = apply_stream_ref(doc, stream, options)
+                .map(|v| {
+                    let v = v?;
+                    match (print, v.as_ref()) {
+                        (PrintCommand::Len, Value::Array(arr)) => Ok(Value::from(arr.len())),
+                        (PrintCommand::Len, Value::Object(map)) => Ok(Value::from(map.len())),
+                        (PrintCommand::Keys, Value::Object(map)) => Ok(Value::from_iter(map.keys().cloned())),
+                        (PrintCommand::Pretty, _) => Ok(v.into_owned()),
+                        (_, v) => Err(anyhow!("Query {}: {:?} can't be applied to {}", name, print, v)),
+                    }
+                })
+                .collect::<Result<Vec<Value>>>()?;
+            let value = match results.len() {
+                1 => results.remove(0),
+                _ => Value::Array(results),
+            };
+            out.insert(name.clone(), value);
+        }
+        Ok(Value::Object(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_queries() {
+        let args = ["count=jobs,len", "names=jobs[].name", "none=missing", "keys=jobs[0],keys"].map(String::from);
+        let queries = Queries::parse(&args).unwrap();
+        let doc = json!({"jobs": [{"name": "a"}, {"name": "b"}]});
+        let expected = json!({"count": 2, "names": ["a", "b"], "none": null, "keys": ["name"]});
+        assert_eq!(queries.eval(&doc, Options::default()).unwrap(), expected);
+        assert!(queries.eval(&json!({"jobs": 1}), Options::default()).is_err());
+        assert!(Queries::parse(&["=a".to_string()]).is_err());
+        assert!(Queries::parse(&["a".to_string()]).is_err());
+        assert!(Queries::parse(&["a=b,csv".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::BufRead;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::input_error;
+use crate::yaml_1_1;
+
+/// Documents of the input for `--skip-errors`. Each is parsed or fails on its own, so ones that can't be parsed
+/// are skipped with a warning, and a count of them once done. Reading the input can still fail.
+struct Skipping<I> {
+    docs: I,
+    skipped: usize,
+}
+
+impl<I: Iterator<Item=Result<Result<Value>>>> Iterator for Skipping<I> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        loop {
+            match self.docs.next()? {
+                Ok(Ok(v)) => return Some(Ok(v)),
+                Ok(Err(e)) => {
+                    // Just the first line of the error, without the input around it, as there may be many
+                    let message = format!("{:#}", e);
+                    eprintln!("Warning: skipping {}", message.lines().next().unwrap_or_default());
+                    self.skipped += 1;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<I> Drop for Skipping<I> {
+    fn drop(&mut self) {
+        if self.skipped > 0 {
+            let s = if self.skipped == 1 { "" } else { "s" };
+            eprintln!("Warning: skipped {} document{} that couldn't be parsed", self.skipped, s);
+        }
+    }
+}
+
+/// JSON documents one to a line, as in NDJSON. Blank lines are ignored.
+pub fn json_lines(input: impl BufRead + 'static, file: &str) -> Box<dyn Iterator<Item=Result<Value>>> {
+    let file = file.to_string();
+    let mut offset = 0;
+    let mut document = 0;
+    let docs = input.split(b'\n').enumerate().filter_map(move |(i, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let start = offset;
+        offset += line.len() as u64 + 1;
+        if line.trim_ascii().is_empty() {
+            return None;
+        }
+        document += 1;
+        Some(Ok(serde_json::from_slice(&line).map_err(|e| {
+            input_error::json_line(&file, document, i + 1, start, &line, e).into()
+        })))
+    });
+    Box::new(Skipping { docs, skipped: 0 })
+}
+
+/// Where the documents of YAML `text` are, split at the lines starting with `---` that separate them. Blank
+/// documents are left out.
+fn yaml_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end();
+        if content == "---" || content.starts_with("--- ") || content == "..." {
+            ranges.push((start, offset));
+            start = offset + if content == "..." { line.len() } else { 4.min(line.len()) };
+        }
+        offset += line.len();
+    }
+    ranges.push((start, text.len()));
+    ranges.retain(|&(start, end)| !text[start..end].trim().is_empty());
+    ranges
+}
+
+/// The documents of YAML `text`, each parsed on its own
+pub fn yaml_documents(text: String, file: &str, yaml_1_1: bool) -> Box<dyn Iterator<Item=Result<Value>>> {
+    let file = file.to_string();
+    let docs = yaml_ranges(&text).into_iter().enumerate().map(move |(i, (start, end))| {
+        let doc = &text[start..end];
+        let value = if yaml_1_1 {
+            yaml_1_1::documents_in(&text, doc).into_iter().next().unwrap_or(Ok(Value::Null))
+        } else {
+            serde_yaml::from_str(doc).map_err(anyhow::Error::from)
+        };
+        Ok(value.map_err(|e| match input_error::yaml_at(&file, text.as_bytes(), start, i + 1, &e) {
+            Some(error) => error.into(),
+            None => e,
+        }))
+    });
+    Box::new(Skipping { docs, skipped: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_lines() {
+        let input = "{\"a\": 1}\n{\"a\": \n\n[2]\n{bad}\n3";
+        let docs: Vec<Value> = json_lines(input.as_bytes(), "<stdin>").collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![json!({"a": 1}), json!([2]), json!(3)]);
+    }
+
+    #[test]
+    fn test_yaml_documents() {
+        let text = "a: 1\n---\nb: [1\n--- \nc: 3\n...\n---\n\n";
+        assert_eq!(yaml_ranges(text), vec![(0, 5), (9, 15), (19, 25)]);
+        let docs: Vec<Value> = yaml_documents(text.to_string(), "<stdin>", false).collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![json!({"a": 1}), json!({"c": 3})]);
+    }
+}
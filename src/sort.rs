@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::iter::{once, once_with};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use jq::{apply_stream_ref, compare, Options, StreamCommand};
+
+/// A value to sort, with its key if it's sorted by one rather than by itself
+struct Keyed {
+    key: Option<Value>,
+    value: Value,
+}
+
+impl Keyed {
+    fn key(&self) -> &Value {
+        self.key.as_ref().unwrap_or(&self.value)
+    }
+}
+
+/// Roughly how many bytes of memory `value` takes
+fn weight(value: &Value) -> usize {
+    std::mem::size_of::<Value>() + match value {
+        Value::String(s) => s.len(),
+        Value::Array(arr) => arr.iter().map(weight).sum(),
+        // The key, and the hash and index the map keeps of it
+        Value::Object(o) => o.iter().map(|(k, v)| std::mem::size_of::<String>() + k.len() + 16 + weight(v)).sum(),
+        _ => 0,
+    }
+}
+
+/// Sort `held` and write it to a temporary file, one value to a line, preceded by its key if it has one
+fn spill(held: &mut Vec<Keyed>) -> Result<File> {
+    held.sort_by(|a, b| compare(a.key(), b.key()));
+    let mut out = BufWriter::new(tempfile::tempfile()?);
+    for keyed in held.drain(..) {
+        match &keyed.key {
+            Some(key) => serde_json::to_writer(&mut out, &(key, &keyed.value))?,
+            None => serde_json::to_writer(&mut out, &keyed.value)?,
+        }
+        out.write_all(b"\n")?;
+    }
+    let mut file = out.into_inner()?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// The values of a sorted run written by `spill`, in order
+type Run = Box<dyn Iterator<Item=Result<Keyed>>>;
+
+fn read_run(file: File, keyed: bool) -> Run {
+    let lines = serde_json::Deserializer::from_reader(BufReader::new(file));
+    if keyed {
+        Box::new(lines.into_iter::<(Value, Value)>().map(|r| Ok(r.map(|(key, value)| Keyed { key: Some(key), value })?)))
+    } else {
+        Box::new(lines.into_iter::<Value>().map(|r| Ok(r.map(|value| Keyed { key: None, value })?)))
+    }
+}
+
+/// The next value of a run, in a heap that puts the smallest first, and of equal ones the one from the earliest
+/// run, so the sort is stable
+struct Head {
+    keyed: Keyed,
+    run: usize,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(other.keyed.key(), self.keyed.key()).then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// The values of sorted runs, merged into one sorted stream
+struct Merge {
+    runs: Vec<Run>,
+    heap: BinaryHeap<Head>,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>) -> Result<Self> {
+        let mut heap = BinaryHeap::new();
+        for (run, values) in runs.iter_mut().enumerate() {
+            if let Some(keyed) = values.next() {
+                heap.push(Head { keyed: keyed?, run });
+            }
+        }
+        Ok(Merge { runs, heap })
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        let head = self.heap.pop()?;
+        match self.runs[head.run].next() {
+            Some(Ok(keyed)) => self.heap.push(Head { keyed, run: head.run }),
+            Some(Err(e)) => return Some(Err(e)),
+            None => {}
+        }
+        Some(Ok(head.keyed.value))
+    }
+}
+
+fn sorted(values: impl Iterator<Item=Result<Value>>, key: Option<&[StreamCommand]>, options: Options, budget: u64) -> Result<Box<dyn Iterator<Item=Result<Value>>>> {
+    let mut held = Vec::new();
+    let mut size = 0;
+    let mut runs = Vec::new();
+    for value in values {
+        let value = value?;
+        let key = match key {
+            Some(key) => Some(apply_stream_ref(&value, key, options).next().transpose()?.map_or(Value::Null, Cow::into_owned)),
+            None => None,
+        };
+        size += key.as_ref().map_or(0, weight) + weight(&value);
+        held.push(Keyed { key, value });
+        if size as u64 > budget {
+            runs.push(spill(&mut held)?);
+            size = 0;
+        }
+    }
+    if runs.is_empty() {
+        held.sort_by(|a, b| compare(a.key(), b.key()));
+        return Ok(Box::new(held.into_iter().map(|keyed| Ok(keyed.value))));
+    }
+    if !held.is_empty() {
+        runs.push(spill(&mut held)?);
+    }
+    let runs = runs.into_iter().map(|file| read_run(file, key.is_some())).collect();
+    Ok(Box::new(Merge::new(runs)?))
+}
+
+/// `values` sorted by `key`, or by themselves if there's none, for `sort_by`. Values with the same key keep their
+/// order. Once they take more than about `budget` bytes, they're sorted and written to a temporary file, and the
+/// files are merged at the end, so a stream larger than memory can be sorted.
+pub fn sort<'s>(values: impl Iterator<Item=Result<Value>> + 's, key: Option<&'s [StreamCommand]>, options: Options, budget: u64) -> Box<dyn Iterator<Item=Result<Value>> + 's> {
+    Box::new(once_with(move || sorted(values, key, options, budget)).flat_map(|sorted| match sorted {
+        Ok(sorted) => sorted,
+        Err(e) => Box::new(once(Err(e))),
+    }))
+}
+
+/// A value that's a candidate for `top`, ranked so the heap of them has the one that would be dropped first on
+/// top: the one with the smallest key for `top` and the largest for `bottom`, and of equal ones the latest
+struct Candidate {
+    keyed: Keyed,
+    seq: usize,
+    largest: bool,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_key = compare(self.keyed.key(), other.keyed.key());
+        let by_key = if self.largest { by_key.reverse() } else { by_key };
+        by_key.then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+fn best(values: impl Iterator<Item=Result<Value>>, n: usize, key: Option<&[StreamCommand]>, largest: bool, options: Options) -> Result<Vec<Value>> {
+    let mut heap = BinaryHeap::with_capacity(n.saturating_add(1).min(1 << 16));
+    for (seq, value) in values.enumerate() {
+        let value = value?;
+        let key = match key {
+            Some(key) => Some(apply_stream_ref(&value, key, options).next().transpose()?.map_or(Value::Null, Cow::into_owned)),
+            None => None,
+        };
+        heap.push(Candidate { keyed: Keyed { key, value }, seq, largest });
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    Ok(heap.into_sorted_vec().into_iter().map(|c| c.keyed.value).collect())
+}
+
+/// The `n` of `values` with the largest keys, largest first, or if not `largest` the smallest, smallest first, for
+/// `top` and `bottom`. Of values with the same key, the first are kept. Only `n` values are held at a time.
+pub fn top<'s>(values: impl Iterator<Item=Result<Value>> + 's, n: usize, key: Option<&'s [StreamCommand]>, largest: bool, options: Options) -> Box<dyn Iterator<Item=Result<Value>> + 's> {
+    Box::new(once_with(move || best(values, n, key, largest, options)).flat_map(|best| -> Box<dyn Iterator<Item=Result<Value>>> {
+        match best {
+            Ok(best) => Box::new(best.into_iter().map(Ok)),
+            Err(e) => Box::new(once(Err(e))),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sort() {
+        let values = [json!({"ts": 3, "n": 0}), json!({"ts": 1}), json!({"n": 1}), json!({"ts": 3, "n": 1}), json!({"ts": 2})];
+        let key = [StreamCommand::Key("ts".to_string())];
+        let expected = [json!({"n": 1}), json!({"ts": 1}), json!({"ts": 2}), json!({"ts": 3, "n": 0}), json!({"ts": 3, "n": 1})];
+        // Held in memory, and spilled to a file every couple of values
+        for budget in [u64::MAX, 200] {
+            let sorted: Vec<Value> = sort(values.clone().into_iter().map(Ok), Some(&key), Options::default(), budget)
+                .collect::<Result<_>>().unwrap();
+            assert_eq!(sorted, expected);
+        }
+        let sorted: Vec<Value> = sort([json!("b"), json!(null), json!("a")].into_iter().map(Ok), None, Options::default(), 0)
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(sorted, [json!(null), json!("a"), json!("b")]);
+    }
+
+    #[test]
+    fn test_top() {
+        let values = [json!({"n": "a", "s": 2}), json!({"n": "b", "s": 5}), json!({"n": "c", "s": 2}), json!({"n": "d", "s": 1})];
+        let key = [StreamCommand::Key("s".to_string())];
+        let names = |n, largest| -> Vec<Value> {
+            top(values.clone().into_iter().map(Ok), n, Some(&key), largest, Options::default())
+                .map(|v| v.unwrap()["n"].clone()).collect()
+        };
+        assert_eq!(names(2, true), ["b", "a"]);
+        assert_eq!(names(3, false), ["d", "a", "c"]);
+        assert_eq!(names(9, true), ["b", "a", "c", "d"]);
+        assert!(names(0, true).is_empty());
+    }
+}
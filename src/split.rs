@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use jq::{apply_stream_ref, evaluate_command, Options, StreamCommand};
+
+/// How many partition files are kept open at once. Past this, the one opened longest ago is closed, and
+/// reopened to append to if more results go to it.
+const MAX_OPEN: usize = 64;
+
+/// Names Windows reserves for devices, whatever the extension
+const RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9"];
+
+/// A file name for the partition of results whose key is `key`: a string as it is and anything else as JSON, with
+/// characters that aren't safe in file names replaced with `_`, and no way to name a hidden file or a directory
+/// outside the one written to
+pub fn file_name(key: &Value) -> String {
+    let text = match key {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut name: String = text.chars()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    // Leaving room for the extension within the 255 bytes most file systems allow
+    if name.len() > 200 {
+        let end = (0..=200).rev().find(|&i| name.is_char_boundary(i)).unwrap_or(0);
+        name.truncate(end);
+    }
+    if name.is_empty() || name.starts_with('.') || RESERVED.contains(&name.to_ascii_uppercase().as_str()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Writes each result into the file of its partition, for --split-by: a file in the output directory named after
+/// the value of the key in the result. Files are opened as results come to them.
+pub struct Split {
+    dir: PathBuf,
+    key: Vec<StreamCommand>,
+    ext: &'static str,
+    options: Options,
+    open: HashMap<String, BufWriter<File>>,
+    /// The files open, the one opened longest ago first
+    order: VecDeque<String>,
+    /// The files written to so far, which are appended to if they're reopened
+    written: HashSet<String>,
+}
+
+impl Split {
+    pub fn new(dir: &str, key: &str, ext: &'static str, options: Options) -> Result<Self> {
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("Failed to create {}: {}", dir, e))?;
+        Ok(Split {
+            dir: PathBuf::from(dir),
+            key: evaluate_command(key)?.0,
+            ext,
+            options,
+            open: HashMap::new(),
+            order: VecDeque::new(),
+            written: HashSet::new(),
+        })
+    }
+
+    /// The file `value` is written to
+    pub fn writer(&mut self, value: &Value) -> Result<&mut BufWriter<File>> {
+        let key = apply_stream_ref(value, &self.key, self.options).next().transpose()?;
+        let name = format!("{}.{}", file_name(key.as_deref().unwrap_or(&Value::Null)), self.ext);
+        if !self.open.contains_key(&name) {
+            if self.open.len() >= MAX_OPEN {
+                if let Some(mut oldest) = self.order.pop_front().and_then(|name| self.open.remove(&name)) {
+                    oldest.flush()?;
+                }
+            }
+            let path = self.dir.join(&name);
+            // A file is started afresh the first time, and appended to after that
+            let file = if self.written.insert(name.clone()) {
+                File::create(&path)
+            } else {
+                OpenOptions::new().append(true).open(&path)
+            };
+            let file = file.map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+            self.order.push_back(name.clone());
+            self.open.insert(name.clone(), BufWriter::new(file));
+        }
+        Ok(self.open.get_mut(&name).expect("just opened"))
+    }
+
+    pub fn finish(self) -> Result<()> {
+        for (_, mut file) in self.open {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name(&json!("acme")), "acme");
+        assert_eq!(file_name(&json!("2024-01-02")), "2024-01-02");
+        assert_eq!(file_name(&json!("../etc/passwd")), "_.._etc_passwd");
+        assert_eq!(file_name(&json!("a:b*c\n")), "a_b_c_");
+        assert_eq!(file_name(&json!(12)), "12");
+        assert_eq!(file_name(&Value::Null), "null");
+        assert_eq!(file_name(&json!("")), "_");
+        assert_eq!(file_name(&json!("con")), "_con");
+        assert_eq!(file_name(&json!("é".repeat(150))).len(), 200);
+    }
+
+    #[test]
+    fn test_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let mut split = Split::new(out.to_str().unwrap(), "customer", "ndjson", Options::default()).unwrap();
+        for i in 0..(MAX_OPEN + 2) {
+            for customer in [format!("c{}", i), "first".to_string()] {
+                let value = json!({"customer": customer, "i": i});
+                writeln!(split.writer(&value).unwrap(), "{}", value).unwrap();
+            }
+        }
+        split.finish().unwrap();
+        let first = std::fs::read_to_string(out.join("first.ndjson")).unwrap();
+        assert_eq!(first.lines().count(), MAX_OPEN + 2);
+        assert_eq!(std::fs::read_dir(&out).unwrap().count(), MAX_OPEN + 3);
+    }
+}
@@ -0,0 +1,171 @@
+use std::mem;
+
+use anyhow::Result;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection};
+use serde_json::{Map, Value};
+
+/// Rows buffered before the table is created, so column types come from more than a single record
+const SAMPLE_SIZE: usize = 1000;
+
+/// Declared SQLite column types, ordered so that a wider type can hold every narrower one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ColumnType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl ColumnType {
+    fn of(v: &Value) -> Option<ColumnType> {
+        match v {
+            Value::Null => None,
+            Value::Bool(_) => Some(ColumnType::Integer),
+            Value::Number(n) if n.is_i64() || n.is_u64() => Some(ColumnType::Integer),
+            Value::Number(_) => Some(ColumnType::Real),
+            _ => Some(ColumnType::Text),
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+        }
+    }
+}
+
+fn quote_ident(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+fn to_sql(v: Value) -> SqlValue {
+    match v {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        z => SqlValue::Text(z.to_string()),
+    }
+}
+
+/// Objects map to rows directly. Anything else is stored in a single `value` column.
+fn to_row(v: Value) -> Map<String, Value> {
+    match v {
+        Value::Object(o) => o,
+        z => Map::from_iter([("value".to_string(), z)]),
+    }
+}
+
+/// Writes a stream of results into a SQLite table, one row per result. The table is created
+/// from the first rows, and columns that first appear later are added as they're seen.
+pub struct SqliteSink {
+    conn: Connection,
+    table: String,
+    columns: Vec<(String, Option<ColumnType>)>,
+    pending: Vec<Map<String, Value>>,
+    created: bool,
+}
+
+impl SqliteSink {
+    pub fn open(path: &str, table: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("BEGIN")?;
+        Ok(SqliteSink {
+            conn,
+            table: table.to_string(),
+            columns: Vec::new(),
+            pending: Vec::new(),
+            created: false,
+        })
+    }
+
+    fn observe(&mut self, row: &Map<String, Value>) {
+        for (k, v) in row {
+            let ty = ColumnType::of(v);
+            match self.columns.iter_mut().find(|(name, _)| name == k) {
+                Some((_, existing)) => *existing = (*existing).max(ty),
+                None => self.columns.push((k.clone(), ty)),
+            }
+        }
+    }
+
+    fn create(&mut self) -> Result<()> {
+        let columns = self.columns.iter()
+            .map(|(name, ty)| match ty {
+                Some(ty) => format!("{} {}", quote_ident(name), ty.sql()),
+                None => quote_ident(name),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.conn.execute(&format!("CREATE TABLE IF NOT EXISTS {} ({})", quote_ident(&self.table), columns), [])?;
+        self.created = true;
+        for row in mem::take(&mut self.pending) {
+            self.insert(row)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, row: Map<String, Value>) -> Result<()> {
+        for (k, v) in &row {
+            if self.columns.iter().any(|(name, _)| name == k) {
+                continue;
+            }
+            let ty = ColumnType::of(v);
+            let decl = ty.map(|t| format!(" {}", t.sql())).unwrap_or_default();
+            self.conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}{}", quote_ident(&self.table), quote_ident(k), decl), [])?;
+            self.columns.push((k.clone(), ty));
+        }
+        let names = row.keys().map(|k| quote_ident(k)).collect::<Vec<_>>().join(", ");
+        let placeholders = vec!["?"; row.len()].join(", ");
+        let sql = format!("INSERT INTO {} ({}) VALUES ({})", quote_ident(&self.table), names, placeholders);
+        self.conn.prepare_cached(&sql)?.execute(params_from_iter(row.into_iter().map(|(_, v)| to_sql(v))))?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, v: Value) -> Result<()> {
+        let row = to_row(v);
+        if self.created {
+            return self.insert(row);
+        }
+        self.observe(&row);
+        self.pending.push(row);
+        if self.pending.len() >= SAMPLE_SIZE {
+            self.create()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if !self.created && !self.columns.is_empty() {
+            self.create()?;
+        }
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_widening() {
+        let mut sink = SqliteSink::open(":memory:", "t").unwrap();
+        sink.push(json!({"a": 1, "b": null})).unwrap();
+        sink.push(json!({"a": 1.5, "b": "x", "c": [1]})).unwrap();
+        sink.create().unwrap();
+        sink.push(json!({"d": true})).unwrap();
+        let schema: String = sink.conn
+            .query_row("SELECT sql FROM sqlite_master WHERE name = 't'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(schema, r#"CREATE TABLE "t" ("a" REAL, "b" TEXT, "c" TEXT, "d" INTEGER)"#);
+        let c: String = sink.conn.query_row("SELECT c FROM t WHERE a = 1.5", [], |r| r.get(0)).unwrap();
+        assert_eq!(c, "[1]");
+    }
+}
@@ -0,0 +1,81 @@
+use std::iter::once;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use jq::{apply_stream, Options, StreamCommand};
+
+use crate::sort;
+
+/// Where the part of a query that's applied to each document on its own ends: at the first command that works
+/// on the whole stream of results, like `sort_by`
+pub fn start(stream: &[StreamCommand]) -> usize {
+    stream.iter().position(StreamCommand::is_whole_stream).unwrap_or(stream.len())
+}
+
+/// The results of `stages`, the part of a query from a command that works on the whole stream of results, run
+/// over `values`, the results of the part before it. `sort_memory` is how much `sort_by` holds in memory.
+pub fn apply<'s>(values: Box<dyn Iterator<Item=Result<Value>> + 's>, stages: &'s [StreamCommand], options: Options, sort_memory: u64) -> Box<dyn Iterator<Item=Result<Value>> + 's> {
+    let Some((first, rest)) = stages.split_first() else {
+        return values;
+    };
+    let values = match first {
+        StreamCommand::SortBy(key) => sort::sort(values, key.as_deref(), options, sort_memory),
+        StreamCommand::Top { n, key, largest } => sort::top(values, *n, key.as_deref(), *largest, options),
+        &StreamCommand::Chunk(n) => chunk(values, n),
+        _ => unreachable!("not a whole stream command: {:?}", first),
+    };
+    // The commands up to the next whole stream command are applied to each value
+    let (each, stages) = rest.split_at(start(rest));
+    let values: Box<dyn Iterator<Item=Result<Value>>> = if each.is_empty() {
+        values
+    } else {
+        Box::new(values.flat_map(move |v| -> Box<dyn Iterator<Item=Result<Value>>> {
+            match v {
+                Ok(v) => Box::new(apply_stream(v, each, options).map(|v| Ok(v?))),
+                Err(e) => Box::new(once(Err(e))),
+            }
+        }))
+    };
+    apply(values, stages, options, sort_memory)
+}
+
+/// `values` in arrays of `n`, the last holding what's left, for `chunk`. Each is produced as soon as it's full.
+fn chunk<'s>(mut values: Box<dyn Iterator<Item=Result<Value>> + 's>, n: usize) -> Box<dyn Iterator<Item=Result<Value>> + 's> {
+    Box::new(std::iter::from_fn(move || {
+        let mut chunk = Vec::new();
+        for v in values.by_ref() {
+            match v {
+                Ok(v) => chunk.push(v),
+                Err(e) => return Some(Err(e)),
+            }
+            if chunk.len() == n {
+                break;
+            }
+        }
+        (!chunk.is_empty()).then_some(Ok(Value::Array(chunk)))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jq::evaluate_command;
+    use serde_json::json;
+
+    #[test]
+    fn test_stages() {
+        let (stream, _) = evaluate_command("events[] sort_by(ts) name").unwrap();
+        let (each, stages) = stream.split_at(start(&stream));
+        assert_eq!(each.len(), 2);
+        let docs = [json!({"events": [{"ts": 2, "name": "b"}]}), json!({"events": [{"ts": 1, "name": "a"}, {"ts": 3, "name": "c"}]})];
+        let values = docs.into_iter().flat_map(|doc| apply_stream(doc, each, Options::default())).map(|v| Ok(v?));
+        let results: Vec<Value> = apply(Box::new(values), stages, Options::default(), u64::MAX).collect::<Result<_>>().unwrap();
+        assert_eq!(results, ["a", "b", "c"]);
+
+        let (stages, _) = evaluate_command("chunk(2)").unwrap();
+        let values = (0..5).map(|i| Ok(json!(i)));
+        let results: Vec<Value> = apply(Box::new(values), &stages, Options::default(), u64::MAX).collect::<Result<_>>().unwrap();
+        assert_eq!(results, [json!([0, 1]), json!([2, 3]), json!([4])]);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{json, Value};
+
+#[derive(Default)]
+struct Range {
+    min: usize,
+    max: usize,
+    total: usize,
+    count: usize,
+}
+
+impl Range {
+    fn add(&mut self, n: usize) {
+        if self.count == 0 || n < self.min {
+            self.min = n;
+        }
+        self.max = self.max.max(n);
+        self.total += n;
+        self.count += 1;
+    }
+
+    fn to_json(&self) -> Value {
+        json!({"min": self.min, "max": self.max, "mean": self.total as f64 / self.count as f64})
+    }
+}
+
+#[derive(Default)]
+struct PathStats {
+    count: usize,
+    types: BTreeMap<&'static str, usize>,
+    bytes: usize,
+    keys: Range,
+    lengths: Range,
+}
+
+/// Statistics collected across every value at each path, in order of first appearance. Array elements share
+/// the path of their array with `[]` appended.
+#[derive(Default)]
+pub struct Stats {
+    documents: usize,
+    max_depth: usize,
+    index: HashMap<String, usize>,
+    paths: Vec<(String, PathStats)>,
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+impl Stats {
+    pub fn add(&mut self, doc: &Value) {
+        self.documents += 1;
+        self.walk(".".to_string(), doc, 0);
+    }
+
+    fn walk(&mut self, path: String, value: &Value, depth: usize) {
+        let i = match self.index.get(&path) {
+            Some(&i) => i,
+            None => {
+                self.index.insert(path.clone(), self.paths.len());
+                self.paths.push((path.clone(), PathStats::default()));
+                self.paths.len() - 1
+            }
+        };
+        let stats = &mut self.paths[i].1;
+        stats.count += 1;
+        *stats.types.entry(type_name(value)).or_default() += 1;
+        stats.bytes += serde_json::to_vec(value).map_or(0, |v| v.len());
+        match value {
+            Value::Object(map) => {
+                stats.keys.add(map.len());
+                self.max_depth = self.max_depth.max(depth + 1);
+                for (k, v) in map {
+                    let path = if path == "." { k.clone() } else { format!("{}.{}", path, k) };
+                    self.walk(path, v, depth + 1);
+                }
+            }
+            Value::Array(arr) => {
+                stats.lengths.add(arr.len());
+                self.max_depth = self.max_depth.max(depth + 1);
+                let path = if path == "." { "[]".to_string() } else { format!("{}[]", path) };
+                for v in arr {
+                    self.walk(path.clone(), v, depth + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A report with the document count, the total input size, the deepest nesting, and per path: how often
+    /// it occurs, its types, its size as compact JSON, and the key counts of its objects and lengths of its arrays
+    pub fn report(&self, bytes: usize) -> Value {
+        let paths = self.paths.iter().map(|(path, s)| {
+            let mut entry = json!({"count": s.count, "types": s.types, "bytes": s.bytes});
+            if s.keys.count > 0 {
+                entry["keys"] = s.keys.to_json();
+            }
+            if s.lengths.count > 0 {
+                entry["lengths"] = s.lengths.to_json();
+            }
+            (path.clone(), entry)
+        });
+        json!({
+            "documents": self.documents,
+            "bytes": bytes,
+            "max_depth": self.max_depth,
+            "paths": paths.collect::<serde_json::Map<_, _>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        stats.add(&json!({"id": 1, "tags": ["a", "b"]}));
+        stats.add(&json!({"id": "x", "tags": []}));
+        let report = stats.report(50);
+        assert_eq!(report["documents"], 2);
+        assert_eq!(report["max_depth"], 2);
+        assert_eq!(report["paths"]["id"]["types"], json!({"number": 1, "string": 1}));
+        assert_eq!(report["paths"]["tags"]["lengths"], json!({"min": 0, "max": 2, "mean": 1.0}));
+        assert_eq!(report["paths"]["tags[]"]["count"], 2);
+        assert_eq!(report["paths"]["."]["keys"]["max"], 2);
+        assert_eq!(report["paths"]["tags[]"]["bytes"], 6);
+    }
+}
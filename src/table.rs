@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Cells wider than this are truncated with an ellipsis, so one long description doesn't push
+/// every other column off screen.
+const MAX_CELL_WIDTH: usize = 40;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Text of a single cell. Strings print raw, nulls print empty, and everything else prints as compact JSON.
+fn cell(v: &Value) -> Cow<'_, str> {
+    match v {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(s) => Cow::Borrowed(s.as_str()),
+        z => Cow::Owned(z.to_string()),
+    }
+}
+
+fn truncate(s: &str, width: usize) -> Cow<'_, str> {
+    let s = if s.contains(['\n', '\r', '\t']) {
+        Cow::Owned(s.replace("\r\n", " ").replace(['\n', '\r', '\t'], " "))
+    } else {
+        Cow::Borrowed(s)
+    };
+    if s.width() <= width {
+        return s;
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        used += w;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    Cow::Owned(truncated)
+}
+
+/// Render rows of cells as an aligned text table under a bold header
+pub fn render(headers: &[String], rows: &[Vec<Cow<Value>>], color: bool) -> String {
+    let headers: Vec<Cow<str>> = headers.iter().map(|h| truncate(h, MAX_CELL_WIDTH)).collect();
+    let rows: Vec<Vec<Cow<str>>> = rows.iter()
+        .map(|row| row.iter()
+            .map(|v| Cow::Owned(truncate(&cell(v), MAX_CELL_WIDTH).into_owned()))
+            .collect())
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.width()).collect();
+    for row in &rows {
+        for (w, c) in widths.iter_mut().zip(row) {
+            *w = (*w).max(c.width());
+        }
+    }
+
+    let mut out = String::new();
+    let mut push_row = |cells: &[Cow<str>], style: Option<&str>| {
+        let last = cells.len().saturating_sub(1);
+        for (i, (c, w)) in cells.iter().zip(&widths).enumerate() {
+            match style {
+                Some(style) if color => {
+                    out.push_str(style);
+                    out.push_str(c);
+                    out.push_str(RESET);
+                }
+                _ => out.push_str(c),
+            }
+            if i != last {
+                out.push_str(&" ".repeat(w - c.width() + 2));
+            }
+        }
+        out.push('\n');
+    };
+    push_row(&headers, Some(BOLD));
+    let rule: Vec<Cow<str>> = widths.iter().map(|w| Cow::Owned("─".repeat(*w))).collect();
+    push_row(&rule, Some(DIM));
+    for row in &rows {
+        push_row(row, None);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let (alice, bob, age) = (json!("alice"), json!("bob"), json!(30));
+        let rows = vec![
+            vec![Cow::Borrowed(&alice), Cow::Borrowed(&age)],
+            vec![Cow::Borrowed(&bob), Cow::Owned(Value::Null)],
+        ];
+        let headers = vec!["name".to_string(), "age".to_string()];
+        assert_eq!(render(&headers, &rows, false), "name   age\n─────  ───\nalice  30\nbob    \n");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("abc", 4), "abc");
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use colored_json::ColorMode;
+use serde_json::Value;
+
+use crate::{json, yaml, PrintOptions};
+
+/// How `--tee` writes results to its file
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum TeeFormat {
+    /// One compact JSON value per line
+    Jsonl,
+    /// Pretty-printed JSON
+    Json,
+    /// YAML documents separated by `---`
+    Yaml,
+}
+
+impl TeeFormat {
+    /// The format for a file by its extension: YAML for .yaml and .yml, pretty JSON for .json, and JSON lines
+    /// otherwise
+    pub fn of(path: &str) -> Self {
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => TeeFormat::Yaml,
+            "json" => TeeFormat::Json,
+            _ => TeeFormat::Jsonl,
+        }
+    }
+}
+
+/// A copy of the results written to a file alongside the normal output
+pub struct Tee {
+    file: BufWriter<File>,
+    format: TeeFormat,
+    written: usize,
+}
+
+impl Tee {
+    pub fn create(path: &str, format: Option<TeeFormat>) -> Result<Self> {
+        let file = File::create(path).map_err(|e| anyhow!("Failed to create {}: {}", path, e))?;
+        Ok(Tee { file: BufWriter::new(file), format: format.unwrap_or_else(|| TeeFormat::of(path)), written: 0 })
+    }
+
+    /// Write the results of one input document. They're flushed right away, since the process may exit
+    /// without unwinding, e.g. for --exit-status.
+    pub fn write(&mut self, results: &[Value], opts: &PrintOptions) -> Result<()> {
+        for value in results {
+            match self.format {
+                TeeFormat::Jsonl => json::write_json(&mut self.file, value, opts, false, ColorMode::Off)?,
+                TeeFormat::Json => json::write_json(&mut self.file, value, opts, true, ColorMode::Off)?,
+                TeeFormat::Yaml => {
+                    if self.written > 0 && !opts.yaml.document_start {
+                        writeln!(self.file, "---")?;
+                    }
+                    self.file.write_all(yaml::to_string(value, &opts.yaml).as_bytes())?;
+                }
+            }
+            if self.format != TeeFormat::Yaml {
+                writeln!(self.file)?;
+            }
+            self.written += 1;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+}
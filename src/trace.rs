@@ -0,0 +1,71 @@
+use std::iter::once;
+
+use serde_json::Value;
+
+use jq::{apply_stream, Options, Result, StreamCommand};
+
+/// Longest value, as compact JSON, that's shown in full in a trace line
+const MAX_WIDTH: usize = 100;
+
+fn truncate(value: &Value) -> String {
+    let s = value.to_string();
+    match s.char_indices().nth(MAX_WIDTH) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
+
+fn input_line(command: &StreamCommand, input: &Value) -> String {
+    format!("trace: {:?} <- {}", command, truncate(input))
+}
+
+/// The trace lines for the values a command produced, or `(nothing)` if it filtered out its input
+fn output_lines(command: &StreamCommand, output: &[Result<Value>]) -> Vec<String> {
+    if output.is_empty() {
+        return vec![format!("trace: {:?} -> (nothing)", command)];
+    }
+    output.iter().map(|v| match v {
+        Ok(v) => format!("trace: {:?} -> {}", command, truncate(v)),
+        Err(e) => format!("trace: {:?} -> error: {}", command, e),
+    }).collect()
+}
+
+/// Like `apply_stream`, but running one command at a time and logging what goes into and comes out of each
+/// to stderr. The input is logged before the command runs, so it's visible even when the command fails.
+pub fn apply_stream_traced(obj: Value, stream: &[StreamCommand], options: Options) -> Box<dyn Iterator<Item=Result<Value>> + '_> {
+    let mut it: Box<dyn Iterator<Item=Result<Value>> + '_> = Box::new(once(Ok(obj)));
+    for (i, command) in stream.iter().enumerate() {
+        let step = &stream[i..i + 1];
+        it = Box::new(it.flat_map(move |v| {
+            let Ok(v) = v else {
+                return vec![v];
+            };
+            eprintln!("{}", input_line(command, &v));
+            let output: Vec<Result<Value>> = apply_stream(v, step, options).collect();
+            for line in output_lines(command, &output) {
+                eprintln!("{}", line);
+            }
+            output
+        }));
+    }
+    it
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_trace() {
+        let command = StreamCommand::Filter("a=1".to_string());
+        assert_eq!(input_line(&command, &json!({"a": 2})), r#"trace: Filter("a=1") <- {"a":2}"#);
+        assert_eq!(output_lines(&command, &[]), vec![r#"trace: Filter("a=1") -> (nothing)"#]);
+        let long = json!("x".repeat(200));
+        assert_eq!(truncate(&long).chars().count(), MAX_WIDTH + 1);
+
+        let (stream, _) = crate::evaluate_command("a.[]").unwrap();
+        let results: Vec<Value> = apply_stream_traced(json!({"a": [1, 2]}), &stream, Options::default()).collect::<Result<_>>().unwrap();
+        assert_eq!(results, vec![json!(1), json!(2)]);
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::Value;
+
+fn summary(value: &Value) -> String {
+    match value {
+        Value::Object(map) => format!("{{{} {}}}", map.len(), if map.len() == 1 { "key" } else { "keys" }),
+        Value::Array(arr) => format!("[{} {}]", arr.len(), if arr.len() == 1 { "item" } else { "items" }),
+        z => z.to_string(),
+    }
+}
+
+fn is_leaf(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.is_empty(),
+        Value::Array(arr) => arr.is_empty(),
+        _ => true,
+    }
+}
+
+fn children(value: &Value) -> Vec<(String, &Value)> {
+    match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        Value::Array(arr) => arr.iter().enumerate().map(|(i, v)| (format!("[{}]", i), v)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn walk(out: &mut String, value: &Value, prefix: &str, depth: usize, max_depth: Option<usize>) {
+    let entries = children(value);
+    let last = entries.len().saturating_sub(1);
+    for (i, (label, child)) in entries.into_iter().enumerate() {
+        let (branch, indent) = if i == last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        out.push_str(prefix);
+        out.push_str(branch);
+        out.push_str(&label);
+        if is_leaf(child) {
+            out.push_str(": ");
+            out.push_str(&summary(child));
+            out.push('\n');
+        } else if max_depth.is_some_and(|max| depth + 1 >= max) {
+            out.push(' ');
+            out.push_str(&summary(child));
+            out.push('\n');
+        } else {
+            out.push('\n');
+            walk(out, child, &format!("{}{}", prefix, indent), depth + 1, max_depth);
+        }
+    }
+}
+
+/// Render `value` as an indented tree with branch characters, like `tree(1)`. Containers nested
+/// `max_depth` or more levels deep are summarized by their size instead of expanded.
+pub fn render(value: &Value, max_depth: Option<usize>) -> String {
+    if is_leaf(value) {
+        return format!("{}\n", summary(value));
+    }
+    let mut out = String::from(".\n");
+    walk(&mut out, value, "", 0, max_depth);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let value = json!({"name": "alice", "tags": ["x", "y"], "address": {"city": "Paris"}});
+        assert_eq!(render(&value, None), "\
+.
+├── name: \"alice\"
+├── tags
+│   ├── [0]: \"x\"
+│   └── [1]: \"y\"
+└── address
+    └── city: \"Paris\"
+");
+        assert_eq!(render(&value, Some(1)), "\
+.
+├── name: \"alice\"
+├── tags [2 items]
+└── address {1 key}
+");
+    }
+}
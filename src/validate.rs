@@ -0,0 +1,107 @@
+use std::fmt;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// The format of a file by its extension, JSON unless it's .yaml, .yml or .toml
+    pub fn of(path: &str) -> Self {
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        match ext.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Format::Yaml,
+            "toml" => Format::Toml,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// A syntax error, with a 1-based line and column where the parser knows one
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The 1-based line and column of a byte offset
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Parse every document in `text` without building values, returning the first syntax error
+pub fn check(text: &str, format: Format) -> Result<(), SyntaxError> {
+    match format {
+        Format::Json => {
+            for doc in serde_json::Deserializer::from_str(text).into_iter::<IgnoredAny>() {
+                doc.map_err(|e| SyntaxError {
+                    line: Some(e.line()),
+                    column: Some(e.column()),
+                    // serde_json appends the position to its message, which we report separately
+                    message: e.to_string().split(" at line ").next().unwrap_or_default().to_string(),
+                })?;
+            }
+        }
+        Format::Yaml => {
+            for doc in serde_yaml::Deserializer::from_str(text) {
+                IgnoredAny::deserialize(doc).map_err(|e| {
+                    let location = e.location();
+                    let message = e.to_string();
+                    SyntaxError {
+                        line: location.as_ref().map(|l| l.line()),
+                        column: location.as_ref().map(|l| l.column()),
+                        message: message.split(" at line ").next().unwrap_or_default().to_string(),
+                    }
+                })?;
+            }
+        }
+        Format::Toml => {
+            toml::from_str::<IgnoredAny>(text).map_err(|e| {
+                let (line, column) = e.span().map(|s| position(text, s.start)).unzip();
+                SyntaxError { line, column, message: e.message().to_string() }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("{\"a\": 1}\n[2]", Format::Json), Ok(()));
+        let err = check("{\"a\": 1,\n  \"b\" 2}", Format::Json).unwrap_err();
+        assert_eq!((err.line, err.column), (Some(2), Some(7)));
+        assert_eq!(err.to_string(), "line 2, column 7: expected `:`");
+
+        assert_eq!(check("a: 1\n---\nb: [2]", Format::Yaml), Ok(()));
+        let err = check("a: 1\nb: [2\n", Format::Yaml).unwrap_err();
+        assert!(err.line.is_some());
+
+        assert_eq!(check("a = 1\n[b]\nc = 'x'", Format::Toml), Ok(()));
+        let err = check("a = 1\nb = \n", Format::Toml).unwrap_err();
+        assert_eq!(err.line, Some(2));
+        assert_eq!(Format::of("x.YML"), Format::Yaml);
+    }
+}
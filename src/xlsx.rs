@@ -0,0 +1,63 @@
+use anyhow::Result;
+use rust_xlsxwriter::{Format, Workbook};
+use serde_json::Value;
+
+/// Write `rows` to the first worksheet of a new workbook at `path`. Objects become rows under a bold header
+/// with a column for every key seen in any row; other values are written to a single `value` column.
+pub fn write(path: &str, rows: &[Value]) -> Result<()> {
+    let mut headers: Vec<&str> = Vec::new();
+    for row in rows {
+        match row {
+            Value::Object(map) => {
+                for k in map.keys() {
+                    if !headers.contains(&k.as_str()) {
+                        headers.push(k);
+                    }
+                }
+            }
+            _ => {
+                if !headers.contains(&"value") {
+                    headers.push("value");
+                }
+            }
+        }
+    }
+
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let bold = Format::new().set_bold();
+    for (col, header) in headers.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+
+    for (i, row) in rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, header) in headers.iter().enumerate() {
+            let cell = match row {
+                Value::Object(map) => map.get(*header),
+                z if *header == "value" => Some(z),
+                _ => None,
+            };
+            let col = col as u16;
+            match cell {
+                None | Some(Value::Null) => {}
+                Some(Value::Bool(b)) => {
+                    sheet.write_boolean(r, col, *b)?;
+                }
+                Some(Value::Number(n)) => {
+                    sheet.write_number(r, col, n.as_f64().unwrap_or(f64::NAN))?;
+                }
+                Some(Value::String(s)) => {
+                    sheet.write_string(r, col, s)?;
+                }
+                Some(z) => {
+                    sheet.write_string(r, col, z.to_string())?;
+                }
+            }
+        }
+    }
+    sheet.autofit();
+    workbook.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+/// Turn an arbitrary object key into a valid XML element name. Invalid characters become `_`,
+/// and names that can't start an element (digits, `-`, `.`, or the reserved `xml` prefix) get a leading `_`.
+fn element_name(key: &str) -> Cow<'_, str> {
+    let valid = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let bad_start = key.is_empty()
+        || key.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.')
+        || key.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("xml"));
+    if !bad_start && key.chars().all(valid) {
+        return Cow::Borrowed(key);
+    }
+    let mut name = String::with_capacity(key.len() + 1);
+    if bad_start {
+        name.push('_');
+    }
+    name.extend(key.chars().map(|c| if valid(c) { c } else { '_' }));
+    Cow::Owned(name)
+}
+
+/// `s` as element text. Control characters other than tab, newline and carriage return, and U+FFFE and U+FFFF,
+/// can't be in an XML 1.0 document at all, not even as character references, so they're an error.
+fn escape(s: &str) -> Result<Cow<'_, str>> {
+    let forbidden = |c: char| (c < ' ' && !matches!(c, '\t' | '\n' | '\r')) || matches!(c, '\u{fffe}' | '\u{ffff}');
+    if let Some(c) = s.chars().find(|&c| forbidden(c)) {
+        bail!("XML can't contain the character U+{:04X}, in {}", c as u32, Value::from(s));
+    }
+    if !s.contains(['&', '<', '>']) {
+        return Ok(Cow::Borrowed(s));
+    }
+    Ok(Cow::Owned(s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")))
+}
+
+fn write_element(out: &mut String, name: &str, value: &Value, item: &str, depth: usize) -> Result<()> {
+    let indent = "  ".repeat(depth);
+    match value {
+        Value::Null => {
+            out.push_str(&format!("{indent}<{name}/>\n"));
+        }
+        Value::Bool(_) | Value::Number(_) => {
+            out.push_str(&format!("{indent}<{name}>{value}</{name}>\n"));
+        }
+        Value::String(s) => {
+            out.push_str(&format!("{indent}<{name}>{}</{name}>\n", escape(s)?));
+        }
+        Value::Array(arr) if arr.is_empty() => {
+            out.push_str(&format!("{indent}<{name}/>\n"));
+        }
+        Value::Object(map) if map.is_empty() => {
+            out.push_str(&format!("{indent}<{name}/>\n"));
+        }
+        Value::Array(arr) => {
+            out.push_str(&format!("{indent}<{name}>\n"));
+            for v in arr {
+                write_element(out, item, v, item, depth + 1)?;
+            }
+            out.push_str(&format!("{indent}</{name}>\n"));
+        }
+        Value::Object(map) => {
+            out.push_str(&format!("{indent}<{name}>\n"));
+            for (k, v) in map {
+                write_element(out, &element_name(k), v, item, depth + 1)?;
+            }
+            out.push_str(&format!("{indent}</{name}>\n"));
+        }
+    }
+    Ok(())
+}
+
+/// Render `value` as an XML document. Objects become nested elements named after their keys,
+/// and array elements are each wrapped in an `item` element.
+pub fn to_string(value: &Value, root: &str, item: &str) -> Result<String> {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    write_element(&mut out, &element_name(root), value, &element_name(item), 0)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_string() {
+        let value = json!({"name": "a & b", "tags": ["x", "y"], "1st": null});
+        assert_eq!(to_string(&value, "root", "item").unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?>
+<root>
+  <name>a &amp; b</name>
+  <tags>
+    <item>x</item>
+    <item>y</item>
+  </tags>
+  <_1st/>
+</root>
+"#);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\tb\r\n<c>").unwrap(), "a\tb\r\n&lt;c&gt;");
+        assert!(escape("a\u{1}b").is_err());
+        assert!(escape("\u{ffff}").is_err());
+        assert!(to_string(&json!({"a": ["\u{0}"]}), "root", "item").is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use serde_json::Value;
+
+/// Options for emitting YAML. The defaults match the output of `serde_yaml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlStyle {
+    /// Number of spaces per nesting level
+    pub indent: usize,
+    /// Write collections in flow style (`{a: [1, 2]}`) rather than as indented blocks
+    pub flow: bool,
+    /// Indent block sequences under their parent key, rather than aligning the dashes with the key
+    pub indent_sequences: bool,
+    /// Also quote strings that YAML 1.1 readers would resolve to something else, like `yes`, `off` or `1_000`
+    pub quote_ambiguous: bool,
+    /// Quote with double quotes rather than single quotes
+    pub double_quotes: bool,
+    /// Begin each document with an explicit `---`
+    pub document_start: bool,
+}
+
+impl Default for YamlStyle {
+    fn default() -> Self {
+        YamlStyle {
+            indent: 2,
+            flow: false,
+            indent_sequences: false,
+            quote_ambiguous: false,
+            double_quotes: false,
+            document_start: false,
+        }
+    }
+}
+
+fn is_special(c: char) -> bool {
+    c.is_control() || c == '\u{feff}'
+}
+
+/// Whether a string would be read back as something other than a string, or would change meaning,
+/// if written as a plain (unquoted) scalar. Flow collections additionally reserve `,[]{}`.
+fn needs_quotes(s: &str, flow: bool) -> bool {
+    let Some(first) = s.chars().next() else {
+        return true;
+    };
+    if s.starts_with([' ', '\t']) || s.ends_with([' ', '\t']) {
+        return true;
+    }
+    if matches!(first, ',' | '[' | ']' | '{' | '}' | '#' | '&' | '*' | '!' | '|' | '>' | '\'' | '"' | '%' | '@' | '`') {
+        return true;
+    }
+    if matches!(first, '-' | '?' | ':') && s[1..].chars().next().is_none_or(|c| c == ' ') {
+        return true;
+    }
+    if s.starts_with("---") || s.starts_with("...") {
+        return true;
+    }
+    if s.contains(": ") || s.contains(" #") || s.ends_with(':') {
+        return true;
+    }
+    if s.chars().any(is_special) {
+        return true;
+    }
+    if flow && s.contains([',', '[', ']', '{', '}']) {
+        return true;
+    }
+    is_ambiguous(s)
+}
+
+/// Whether a plain scalar would be resolved to null, a bool, or a number by a YAML 1.2 reader
+fn is_ambiguous(s: &str) -> bool {
+    if matches!(s, "~" | "null" | "Null" | "NULL" | "true" | "True" | "TRUE" | "false" | "False" | "FALSE") {
+        return true;
+    }
+    if matches!(s.trim_start_matches(['+', '-']), ".inf" | ".Inf" | ".INF") || matches!(s, ".nan" | ".NaN" | ".NAN") {
+        return true;
+    }
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if let Some(hex) = digits.strip_prefix("0x") {
+        return !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let Some(bin) = digits.strip_prefix("0b") {
+        return !bin.is_empty() && bin.chars().all(|c| c == '0' || c == '1');
+    }
+    if let Some(oct) = digits.strip_prefix("0o") {
+        return !oct.is_empty() && oct.chars().all(|c| c.is_digit(8));
+    }
+    digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') && s.parse::<f64>().is_ok()
+}
+
+/// Whether a plain scalar would be resolved to a bool, number, or null by a YAML 1.1 reader, though
+/// it's a string in YAML 1.2. This is the "Norway problem", where the country code `NO` becomes false.
+fn is_ambiguous_1_1(s: &str) -> bool {
+    if matches!(
+        s,
+        "y" | "Y" | "yes" | "Yes" | "YES" | "n" | "N" | "no" | "No" | "NO"
+            | "on" | "On" | "ON" | "off" | "Off" | "OFF"
+    ) {
+        return true;
+    }
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        // Underscore separators (`1_000`) and base 60 (`12:30`)
+        if digits.contains('_') && digits.replace('_', "").parse::<f64>().is_ok() {
+            return true;
+        }
+        if digits.contains(':') && digits.split(':').all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit())) {
+            return true;
+        }
+    }
+    false
+}
+
+fn single_quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn double_quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\0' => out.push_str("\\0"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{7}' => out.push_str("\\a"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{b}' => out.push_str("\\v"),
+            '\u{c}' => out.push_str("\\f"),
+            '\u{1b}' => out.push_str("\\e"),
+            '\u{85}' => out.push_str("\\N"),
+            '\u{feff}' => out.push_str("\\uFEFF"),
+            c if (c as u32) < 0x100 && c.is_control() => out.push_str(&format!("\\x{:02X}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Whether a multi-line string can be written as a `|` block literal without changing its content
+fn literal_ok(s: &str) -> bool {
+    s.contains('\n')
+        && !s.starts_with([' ', '\n'])
+        && !s.chars().any(|c| is_special(c) && c != '\n')
+}
+
+struct Emitter<'a> {
+    style: &'a YamlStyle,
+    out: String,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(style: &'a YamlStyle) -> Self {
+        Emitter { style, out: String::new() }
+    }
+
+    fn pad(&mut self, indent: usize) {
+        self.out.extend(std::iter::repeat_n(' ', indent));
+    }
+
+    /// Write a string scalar, choosing plain, quoted, or block literal style. `indent` is the indentation of
+    /// the block literal's content lines, should one be used.
+    fn string(&mut self, s: &str, indent: usize) {
+        if literal_ok(s) {
+            let body = s.trim_end_matches('\n');
+            let chomp = match s.len() - body.len() {
+                0 => "-",
+                1 => "",
+                _ => "+",
+            };
+            self.out.push('|');
+            self.out.push_str(chomp);
+            for line in s.strip_suffix('\n').unwrap_or(s).split('\n') {
+                self.out.push('\n');
+                if !line.is_empty() {
+                    self.pad(indent);
+                    self.out.push_str(line);
+                }
+            }
+        } else if !(needs_quotes(s, self.style.flow) || self.style.quote_ambiguous && is_ambiguous_1_1(s)) {
+            self.out.push_str(s);
+        } else if self.style.double_quotes || s.chars().any(is_special) {
+            self.out.push_str(&double_quoted(s));
+        } else {
+            self.out.push_str(&single_quoted(s));
+        }
+    }
+
+    /// Write `value` in flow style on a single line
+    fn flow(&mut self, value: &Value) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                self.out.push('{');
+                for (i, (k, v)) in map.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.flow_string(k);
+                    self.out.push_str(": ");
+                    self.flow(v);
+                }
+                self.out.push('}');
+            }
+            Value::Array(arr) if !arr.is_empty() => {
+                self.out.push('[');
+                for (i, v) in arr.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.flow(v);
+                }
+                self.out.push(']');
+            }
+            Value::String(s) => self.flow_string(s),
+            z => {
+                self.inline(z, 0);
+            }
+        }
+    }
+
+    /// Flow style can't contain block literals, so multi-line strings are double quoted
+    fn flow_string(&mut self, s: &str) {
+        if s.contains('\n') {
+            self.out.push_str(&double_quoted(s));
+        } else {
+            self.string(s, 0);
+        }
+    }
+
+    fn key(&mut self, k: &str) {
+        if k.contains('\n') {
+            self.out.push_str(&double_quoted(k));
+        } else {
+            self.string(k, 0);
+        }
+    }
+
+    /// Write a node that fits on the current line: a scalar or an empty collection. Returns false for
+    /// collections that need their own block.
+    fn inline(&mut self, value: &Value, indent: usize) -> bool {
+        match value {
+            Value::Null => self.out.push_str("null"),
+            Value::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) => self.out.push_str(&n.to_string()),
+            Value::String(s) => self.string(s, indent),
+            Value::Array(arr) if arr.is_empty() => self.out.push_str("[]"),
+            Value::Object(map) if map.is_empty() => self.out.push_str("{}"),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write a non-empty collection as a block, with every line at `indent`
+    fn block(&mut self, value: &Value, indent: usize) {
+        let step = self.style.indent.max(1);
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map {
+                    self.pad(indent);
+                    self.key(k);
+                    self.out.push(':');
+                    let start = self.out.len();
+                    self.out.push(' ');
+                    if self.inline(v, indent + step) {
+                        self.out.push('\n');
+                        continue;
+                    }
+                    self.out.truncate(start);
+                    self.out.push('\n');
+                    let child = if v.is_array() && !self.style.indent_sequences { indent } else { indent + step };
+                    self.block(v, child);
+                }
+            }
+            Value::Array(arr) => {
+                // The first line of a nested collection shares its line with the dash
+                let child = indent + step.max(2);
+                for v in arr {
+                    self.pad(indent);
+                    self.out.push('-');
+                    self.pad(child - indent - 1);
+                    if self.inline(v, child) {
+                        self.out.push('\n');
+                        continue;
+                    }
+                    let start = self.out.len();
+                    self.block(v, child);
+                    self.out.replace_range(start..start + child, "");
+                }
+            }
+            _ => unreachable!("block called with a scalar"),
+        }
+    }
+
+    fn document(mut self, value: &Value) -> String {
+        let indent = self.style.indent.max(1);
+        if self.style.document_start {
+            self.out.push_str("---\n");
+        }
+        if self.style.flow {
+            self.flow(value);
+            self.out.push('\n');
+        } else if self.inline(value, indent) {
+            self.out.push('\n');
+        } else {
+            self.block(value, 0);
+        }
+        self.out
+    }
+}
+
+/// Render `value` as a YAML document
+pub fn to_string(value: &Value, style: &YamlStyle) -> String {
+    Emitter::new(style).document(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches_serde_yaml() {
+        let value = json!({
+            "a": {"b": [1, 2, {"c": "d", "e": []}], "f": {}},
+            "s": "multi\nline\n",
+            "u": "",
+            "v": "- x",
+            "w": "a: b",
+            "x": null,
+            "y": 1.5,
+            "z": "  lead",
+            "k ey": "#x",
+            "n": "123",
+            "m": "ends\n\n",
+            "o": "no trailing\nnewline",
+            "nested": [[1, 2], [3]],
+            "q": "it's",
+            "tab": "a\tb",
+            "t": true,
+        });
+        assert_eq!(to_string(&value, &YamlStyle::default()), serde_yaml::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn test_indent() {
+        let value = json!({"a": {"b": [1, {"c": 2, "d": 3}]}});
+        let style = YamlStyle { indent: 4, ..YamlStyle::default() };
+        assert_eq!(to_string(&value, &style), "a:\n    b:\n    -   1\n    -   c: 2\n        d: 3\n");
+        let style = YamlStyle { indent_sequences: true, ..YamlStyle::default() };
+        assert_eq!(to_string(&value, &style), "a:\n  b:\n    - 1\n    - c: 2\n      d: 3\n");
+    }
+
+    #[test]
+    fn test_styles() {
+        let value = json!({"country": "NO", "list": ["a,b", 1], "n": "it's"});
+        let style = YamlStyle { flow: true, document_start: true, ..YamlStyle::default() };
+        assert_eq!(to_string(&value, &style), "---\n{country: NO, list: ['a,b', 1], n: it's}\n");
+        let style = YamlStyle { quote_ambiguous: true, double_quotes: true, ..YamlStyle::default() };
+        assert_eq!(to_string(&value, &style), "country: \"NO\"\nlist:\n- a,b\n- 1\n\"n\": it's\n");
+    }
+}
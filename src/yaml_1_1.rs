@@ -0,0 +1,141 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Number, Value};
+
+/// The boolean a plain scalar is in YAML 1.1, which has many more spellings of them than YAML 1.2
+fn bool_1_1(s: &str) -> Option<bool> {
+    match s {
+        "y" | "Y" | "yes" | "Yes" | "YES" | "on" | "On" | "ON" | "true" | "True" | "TRUE" => Some(true),
+        "n" | "N" | "no" | "No" | "NO" | "off" | "Off" | "OFF" | "false" | "False" | "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+/// The documents of YAML `text`, with plain scalars like `no` and `on` read as booleans, as YAML 1.1 does,
+/// and a warning for each that YAML 1.2 would have left a string
+pub fn documents(text: &str) -> Vec<Result<Value>> {
+    documents_in(text, text)
+}
+
+/// Like `documents`, for `part`, a slice of `text`, with warnings saying where in `text` they are
+pub fn documents_in<'a>(text: &'a str, part: &'a str) -> Vec<Result<Value>> {
+    let mut docs = Vec::new();
+    for doc in serde_yaml::Deserializer::from_str(part) {
+        let doc = Reader { text }.deserialize(doc);
+        // After an error, serde_yaml gives the same error for every document it's asked for, without end
+        let failed = doc.is_err();
+        docs.push(doc.map_err(anyhow::Error::from));
+        if failed {
+            break;
+        }
+    }
+    docs
+}
+
+/// Builds a value like `Value`'s own deserializer, except for strings that are booleans in YAML 1.1
+struct Reader<'de> {
+    text: &'de str,
+}
+
+impl Reader<'_> {
+    /// Whether `s` is a plain scalar of the text rather than a quoted one. serde_yaml hands out plain and quoted
+    /// scalars alike as strings, but borrowed from the text, where the quotes are still around them.
+    fn is_plain(&self, s: &str) -> bool {
+        let offset = (s.as_ptr() as usize).wrapping_sub(self.text.as_ptr() as usize);
+        if offset > self.text.len() {
+            return false;
+        }
+        !matches!(self.text.as_bytes()[..offset].last(), Some(b'\'' | b'"'))
+    }
+
+    fn line(&self, s: &str) -> usize {
+        let offset = s.as_ptr() as usize - self.text.as_ptr() as usize;
+        self.text.as_bytes()[..offset].iter().filter(|&&b| b == b'\n').count() + 1
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Reader<'de> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Reader<'de> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any YAML value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Number(n.into()))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(Value::Number(n.into()))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(n).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_borrowed_str<E>(self, s: &'de str) -> Result<Value, E> {
+        match bool_1_1(s) {
+            Some(b) if self.is_plain(s) => {
+                eprintln!("Warning: line {}: reading `{}` as {}, as YAML 1.1 does", self.line(s), s, b);
+                Ok(Value::Bool(b))
+            }
+            _ => Ok(Value::String(s.to_string())),
+        }
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut arr = Vec::new();
+        while let Some(v) = seq.next_element_seed(Reader { text: self.text })? {
+            arr.push(v);
+        }
+        Ok(Value::Array(arr))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut obj = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            obj.insert(key, map.next_value_seed(Reader { text: self.text })?);
+        }
+        Ok(Value::Object(obj))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_documents() {
+        let text = "country: NO\nquoted: 'no'\nlist: [on, \"off\", y]\nname: norway\n---\nyes";
+        let docs: Vec<Value> = documents(text).into_iter().collect::<Result<_>>().unwrap();
+        assert_eq!(docs, vec![
+            json!({"country": false, "quoted": "no", "list": [true, "off", true], "name": "norway"}),
+            json!(true),
+        ]);
+        let docs = documents("a: 1\n---\nb: [\n");
+        assert!(matches!(docs.as_slice(), [Ok(_), Err(_)]));
+    }
+}
@@ -0,0 +1,425 @@
+use serde_json::{Map, Value};
+
+use crate::yaml::{self, YamlStyle};
+
+/// A block collection as it's laid out in the text. Scalars, flow collections and anything else that isn't
+/// worth looking inside are `Other`.
+enum Node {
+    Map(Vec<Entry>),
+    Seq(Vec<Entry>),
+    Other,
+}
+
+/// A mapping entry or a sequence item
+struct Entry {
+    /// The key of a mapping entry, or None for a sequence item
+    key: Option<String>,
+    line: usize,
+    /// The column of the key or the dash
+    col: usize,
+    /// The byte range of the value on `line`, when it's written on that one line
+    inline: Option<(usize, usize)>,
+    value: Node,
+    /// The line after the entry, not counting the blank lines and comments that follow it
+    end: usize,
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_dash(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// The length of a quoted scalar at the start of `text`, including the quotes
+fn quoted_len(text: &str) -> Option<usize> {
+    let quote = text.chars().next()?;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => {
+                chars.next();
+            }
+            // A doubled single quote is an escaped one
+            '\'' if quote == '\'' && text[i + 1..].starts_with('\'') => {
+                chars.next();
+            }
+            c if c == quote => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The key of a mapping entry at the start of `text`, and the offset of its value
+fn key(text: &str) -> Option<(String, usize)> {
+    let (key, rest) = match text.chars().next()? {
+        '"' => {
+            let len = quoted_len(text)?;
+            (serde_json::from_str(&text[..len]).ok()?, len)
+        }
+        '\'' => {
+            let len = quoted_len(text)?;
+            (text[1..len - 1].replace("''", "'"), len)
+        }
+        '[' | '{' | ']' | '}' | '?' | '&' | '*' | '!' | '|' | '>' | '#' | '%' | '@' | '`' | ',' => return None,
+        _ if is_dash(text) => return None,
+        _ => {
+            let colon = text.match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| text[i + 1..].is_empty() || text[i + 1..].starts_with(' '))?;
+            if text[..colon].contains(" #") {
+                return None;
+            }
+            (text[..colon].trim_end().to_string(), colon)
+        }
+    };
+    let after = text[rest..].trim_start_matches(' ');
+    let after = after.strip_prefix(':')?;
+    if !(after.is_empty() || after.starts_with(' ')) {
+        return None;
+    }
+    let value = text.len() - after.len();
+    Some((key, value + after.len() - after.trim_start_matches(' ').len()))
+}
+
+/// The length of the anchors and tags at the start of a value, with the spaces after them
+fn properties_len(text: &str) -> usize {
+    let mut len = 0;
+    while text[len..].starts_with(['&', '!']) {
+        let token = text[len..].find(' ').unwrap_or(text.len() - len);
+        len += token;
+        len += text[len..].len() - text[len..].trim_start_matches(' ').len();
+    }
+    len
+}
+
+/// The length of the scalar or flow collection at the start of `text`, without a trailing comment
+fn scalar_len(text: &str) -> usize {
+    if text.starts_with(['"', '\'']) {
+        if let Some(len) = quoted_len(text) {
+            return len;
+        }
+    }
+    let end = text.find(" #").unwrap_or(text.len());
+    text[..end].trim_end().len()
+}
+
+/// The inline range, layout and end line of a value
+type Parsed = (Option<(usize, usize)>, Node, usize);
+
+struct Parser<'a> {
+    lines: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn is_content(&self, line: usize) -> bool {
+        let text = self.lines[line].trim_start();
+        !text.is_empty() && !text.starts_with('#')
+    }
+
+    fn next_content(&self, from: usize) -> Option<usize> {
+        (from..self.lines.len()).find(|&l| self.is_content(l))
+    }
+
+    /// The line after the content that follows `line` indented past `col`
+    fn extent(&self, line: usize, col: usize) -> usize {
+        let mut end = line + 1;
+        for l in line + 1..self.lines.len() {
+            if self.is_content(l) {
+                if indent(self.lines[l]) <= col {
+                    break;
+                }
+                end = l + 1;
+            }
+        }
+        end
+    }
+
+    /// The collection starting at `col` on `line`, and the line after it
+    fn node(&self, line: usize, col: usize) -> Option<(Node, usize)> {
+        let text = &self.lines[line][col..];
+        if is_dash(text) {
+            self.seq(line, col)
+        } else if key(text).is_some() {
+            self.map(line, col)
+        } else {
+            None
+        }
+    }
+
+    fn map(&self, mut line: usize, col: usize) -> Option<(Node, usize)> {
+        let mut entries = Vec::new();
+        loop {
+            let (key, offset) = key(&self.lines[line][col..])?;
+            let (inline, value, end) = self.value(line, col, col + offset, false)?;
+            entries.push(Entry { key: Some(key), line, col, inline, value, end });
+            match self.next_content(end) {
+                Some(next) if indent(self.lines[next]) == col && !is_dash(&self.lines[next][col..]) => line = next,
+                _ => return Some((Node::Map(entries), end)),
+            }
+        }
+    }
+
+    fn seq(&self, mut line: usize, col: usize) -> Option<(Node, usize)> {
+        let mut items = Vec::new();
+        loop {
+            let start = col + 1 + indent(&self.lines[line][col + 1..]);
+            let (inline, value, end) = self.value(line, col, start, true)?;
+            items.push(Entry { key: None, line, col, inline, value, end });
+            match self.next_content(end) {
+                Some(next) if indent(self.lines[next]) == col && is_dash(&self.lines[next][col..]) => line = next,
+                _ => return Some((Node::Seq(items), end)),
+            }
+        }
+    }
+
+    /// The value starting at byte `start` of `line`, for an entry at `col`, and the line after it
+    fn value(&self, line: usize, col: usize, start: usize, item: bool) -> Option<Parsed> {
+        let text = self.lines[line];
+        let start = start + properties_len(&text[start..]);
+        let rest = &text[start..];
+        if rest.is_empty() || rest.starts_with('#') {
+            // The value is a block on the following lines, or null. A sequence under a key may start at the
+            // key's own column.
+            return match self.next_content(line + 1) {
+                Some(next) if indent(self.lines[next]) > col
+                    || (!item && indent(self.lines[next]) == col && is_dash(&self.lines[next][col..])) => {
+                    let (node, end) = self.node(next, indent(self.lines[next])).unwrap_or((Node::Other, next + 1));
+                    Some((None, node, self.extent(line, col).max(end)))
+                }
+                _ => Some((None, Node::Other, line + 1)),
+            };
+        }
+        // A collection that starts on the same line as its dash, like `- a: 1`
+        if item && (is_dash(rest) || key(rest).is_some()) {
+            let (node, end) = self.node(line, start)?;
+            return Some((None, node, end));
+        }
+        let end = self.extent(line, col);
+        if rest.starts_with(['|', '>']) || end > line + 1 {
+            return Some((None, Node::Other, end));
+        }
+        Some((Some((start, start + scalar_len(rest))), Node::Other, end))
+    }
+}
+
+/// `new` written in place of the inline value `old`, in the same quoting style where there is one
+fn inline_text(old: &str, new: &Value, style: &YamlStyle) -> Option<String> {
+    let flow = old.starts_with(['[', '{']);
+    let text = match new {
+        Value::String(s) if old.starts_with('"') => serde_json::to_string(s).ok()?,
+        Value::String(s) if old.starts_with('\'') && !s.chars().any(char::is_control) => {
+            format!("'{}'", s.replace('\'', "''"))
+        }
+        Value::Array(arr) if !flow && !arr.is_empty() => return None,
+        Value::Object(map) if !flow && !map.is_empty() => return None,
+        _ => yaml::to_string(new, &YamlStyle { flow: true, document_start: false, ..style.clone() }),
+    };
+    let text = text.trim_end();
+    (!text.contains('\n')).then(|| text.to_string())
+}
+
+/// Line edits: the range of lines to replace, and the lines to replace them with
+struct Editor<'a> {
+    lines: &'a [&'a str],
+    style: &'a YamlStyle,
+    edits: Vec<(usize, usize, Vec<String>)>,
+}
+
+impl Editor<'_> {
+    /// Render `value` as a block with every line at `col`, with the first line starting with `prefix`
+    fn render(&self, value: &Value, col: usize, prefix: &str) -> Vec<String> {
+        let style = YamlStyle { document_start: false, ..self.style.clone() };
+        yaml::to_string(value, &style)
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => format!("{}{}", prefix, line),
+                _ => format!("{}{}", " ".repeat(col), line),
+            })
+            .collect()
+    }
+
+    fn collection(&mut self, node: &Node, old: &Value, new: &Value) -> Option<()> {
+        match (node, old, new) {
+            (Node::Map(entries), Value::Object(old), Value::Object(new)) => {
+                for entry in entries {
+                    let key = entry.key.as_ref()?;
+                    match (old.get(key), new.get(key)) {
+                        (Some(a), Some(b)) => self.entry(entry, a, b)?,
+                        (Some(_), None) => self.remove(entry)?,
+                        (None, _) => return None,
+                    }
+                }
+                let added: Map<String, Value> = new.iter()
+                    .filter(|(k, _)| !old.contains_key(*k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                if !added.is_empty() {
+                    let last = entries.last()?;
+                    let lines = self.render(&Value::Object(added), last.col, &" ".repeat(last.col));
+                    self.edits.push((last.end, last.end, lines));
+                }
+            }
+            (Node::Seq(items), Value::Array(old), Value::Array(new)) => {
+                if items.len() != old.len() {
+                    return None;
+                }
+                for (i, (item, a)) in items.iter().zip(old).enumerate() {
+                    match new.get(i) {
+                        Some(b) => self.entry(item, a, b)?,
+                        None => self.remove(item)?,
+                    }
+                }
+                if new.len() > old.len() {
+                    let last = items.last()?;
+                    let lines = self.render(&Value::Array(new[old.len()..].to_vec()), last.col, &" ".repeat(last.col));
+                    self.edits.push((last.end, last.end, lines));
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn entry(&mut self, entry: &Entry, old: &Value, new: &Value) -> Option<()> {
+        if old == new {
+            return Some(());
+        }
+        if matches!(entry.value, Node::Map(_) | Node::Seq(_)) {
+            let len = self.edits.len();
+            if self.collection(&entry.value, old, new).is_some() {
+                return Some(());
+            }
+            self.edits.truncate(len);
+        }
+        let line = self.lines[entry.line];
+        if let Some((start, end)) = entry.inline {
+            if let Some(text) = inline_text(&line[start..end], new, self.style) {
+                let edited = format!("{}{}{}", &line[..start], text, &line[end..]);
+                self.edits.push((entry.line, entry.line + 1, vec![edited]));
+                return Some(());
+            }
+        }
+        let value = match &entry.key {
+            Some(key) => Value::Object(Map::from_iter([(key.clone(), new.clone())])),
+            None => Value::Array(vec![new.clone()]),
+        };
+        let lines = self.render(&value, entry.col, &line[..entry.col]);
+        self.edits.push((entry.line, entry.end, lines));
+        Some(())
+    }
+
+    fn remove(&mut self, entry: &Entry) -> Option<()> {
+        // The first entry of a mapping that shares its line with a dash can't be removed on its own
+        if indent(self.lines[entry.line]) != entry.col {
+            return None;
+        }
+        self.edits.push((entry.line, entry.end, Vec::new()));
+        Some(())
+    }
+}
+
+/// `text`, a YAML document, edited to hold `new`. Only the entries that changed are rewritten, so the comments,
+/// key order, quoting and anchors of the rest of the document are kept. Returns None when the document can't
+/// be edited that way, like a stream of several documents or a change to the type of the root, so the caller
+/// can write `new` out in full instead.
+pub fn edit(text: &str, new: &Value, style: &YamlStyle) -> Option<String> {
+    let old: Value = serde_yaml::from_str(text).ok()?;
+    let lines: Vec<&str> = text.lines().collect();
+    let parser = Parser { lines: &lines };
+    let mut root = parser.next_content(0)?;
+    let first = lines[root].trim_end();
+    if first == "---" || first.starts_with("--- #") {
+        root = parser.next_content(root + 1)?;
+    }
+    if lines.iter().any(|l| l.starts_with('\t') || l.starts_with('%')) {
+        return None;
+    }
+    let (node, end) = parser.node(root, indent(lines[root]))?;
+    if parser.next_content(end).is_some() {
+        return None;
+    }
+    let mut editor = Editor { lines: &lines, style, edits: Vec::new() };
+    editor.collection(&node, &old, new)?;
+    editor.edits.sort_by_key(|(start, _, _)| *start);
+    let mut out: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    for (start, end, replacement) in editor.edits.into_iter().rev() {
+        out.splice(start..end, replacement);
+    }
+    let mut edited = out.join("\n");
+    if text.ends_with('\n') {
+        edited.push('\n');
+    }
+    // Anything the edits got wrong, like a change to an anchored value that aliases share, is caught here
+    let check: Value = serde_yaml::from_str(&edited).ok()?;
+    (check == *new).then_some(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CONFIG: &str = "\
+# Service config
+name: api   # the service name
+image: 'example/api:1.0'
+replicas: 2
+
+env:
+  # Where to find the database
+  DATABASE_URL: \"postgres://db\"
+  LOG_LEVEL: info
+ports:
+- 80
+- name: https
+  port: 443
+defaults: &defaults {retries: 3}
+";
+
+    fn edited(f: impl FnOnce(&mut Value)) -> String {
+        let mut value: Value = serde_yaml::from_str(CONFIG).unwrap();
+        f(&mut value);
+        edit(CONFIG, &value, &YamlStyle::default()).unwrap()
+    }
+
+    #[test]
+    fn test_edit_scalars() {
+        let out = edited(|v| {
+            v["name"] = json!("web");
+            v["image"] = json!("example/api:1.1");
+            v["env"]["DATABASE_URL"] = json!("postgres://replica");
+            v["ports"][1]["port"] = json!(8443);
+        });
+        assert_eq!(out, CONFIG
+            .replace("name: api ", "name: web ")
+            .replace("'example/api:1.0'", "'example/api:1.1'")
+            .replace("\"postgres://db\"", "\"postgres://replica\"")
+            .replace("port: 443", "port: 8443"));
+    }
+
+    #[test]
+    fn test_edit_structure() {
+        let out = edited(|v| {
+            v["env"].as_object_mut().unwrap().remove("LOG_LEVEL");
+            v["env"]["DEBUG"] = json!(true);
+            v["ports"].as_array_mut().unwrap().push(json!(8080));
+            v["defaults"]["retries"] = json!(5);
+            v["labels"] = json!({"team": "core"});
+        });
+        assert_eq!(out, CONFIG
+            .replace("  LOG_LEVEL: info\n", "  DEBUG: true\n")
+            .replace("  port: 443\n", "  port: 443\n- 8080\n")
+            .replace("{retries: 3}", "{retries: 5}")
+            + "labels:\n  team: core\n");
+    }
+
+    #[test]
+    fn test_edit_unsupported() {
+        assert_eq!(edit("a: 1\n---\nb: 2\n", &json!({"a": 2}), &YamlStyle::default()), None);
+        assert_eq!(edit("a: 1\n", &json!([1]), &YamlStyle::default()), None);
+    }
+}